@@ -0,0 +1,93 @@
+//! Measures idle CPU usage and wake latency of `LightrayFIFOWorkQueue` for several idle policies.
+//!
+//! Run with `cargo bench --bench fifo_queue_wakeup`. CPU usage is read from `/proc/self/stat`,
+//! so that part of the report is only available on Linux.
+use lightray_core::lightray_executor::executor::InMemorySimpleLightrayExecutor;
+use lightray_core::lightray_executor::LightrayModelId;
+use lightray_core::lightray_scheduler::greedy_fifo_queue::LightrayFIFOWorkQueue;
+use lightray_core::lightray_scheduler::queue::LightrayWorkQueue;
+use lightray_core::lightray_scheduler::wakeup::LightrayWorkerIdlePolicy;
+use lightray_core::lightray_torch::TorchScriptInput;
+use std::fs;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+const IDLE_DURATION: Duration = Duration::from_secs(2);
+const WAKE_SAMPLES: usize = 200;
+const WAKE_INTERVAL: Duration = Duration::from_millis(5);
+/// Clock ticks per second used by `/proc/self/stat` (USER_HZ), 100 on every common Linux.
+const CLOCK_TICKS_PER_SECOND: f64 = 100.0;
+
+fn process_cpu_seconds() -> Option<f64> {
+    let stat = fs::read_to_string("/proc/self/stat").ok()?;
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    let user_ticks: f64 = fields.get(11)?.parse().ok()?;
+    let system_ticks: f64 = fields.get(12)?.parse().ok()?;
+    Some((user_ticks + system_ticks) / CLOCK_TICKS_PER_SECOND)
+}
+
+fn benchmark_policy(name: &str, idle_policy: LightrayWorkerIdlePolicy) {
+    let queue = Arc::new(LightrayFIFOWorkQueue::with_idle_policy(
+        InMemorySimpleLightrayExecutor::new(),
+        false,
+        idle_policy,
+    ));
+    let worker_queue = queue.clone();
+    thread::spawn(move || worker_queue.worker_loop());
+    // The model is never registered, so the executor answers immediately and the round trip
+    // only measures the scheduler.
+    let model_id = LightrayModelId {
+        model_id: Uuid::new_v4(),
+        model_version: 0,
+    };
+
+    let idle_cpu_start = process_cpu_seconds();
+    thread::sleep(IDLE_DURATION);
+    let idle_cpu_end = process_cpu_seconds();
+
+    let mut runtime = Runtime::new().unwrap();
+    let mut wake_latencies: Vec<Duration> = Vec::with_capacity(WAKE_SAMPLES);
+    for _ in 0..WAKE_SAMPLES {
+        thread::sleep(WAKE_INTERVAL);
+        let start = Instant::now();
+        let payload = TorchScriptInput {
+            positional_arguments: vec![],
+            ..Default::default()
+        };
+        let _result = runtime.block_on(queue.enqueue(payload, model_id));
+        wake_latencies.push(start.elapsed());
+    }
+    wake_latencies.sort();
+
+    let idle_cpu = match (idle_cpu_start, idle_cpu_end) {
+        (Some(start), Some(end)) => format!(
+            "{:.1}%",
+            100.0 * (end - start) / IDLE_DURATION.as_secs_f64()
+        ),
+        _ => "n/a".to_string(),
+    };
+    println!(
+        "{:<12} idle cpu: {:>7}  wake latency p50: {:>10?}  p99: {:>10?}  max: {:>10?}",
+        name,
+        idle_cpu,
+        wake_latencies[WAKE_SAMPLES / 2],
+        wake_latencies[WAKE_SAMPLES * 99 / 100],
+        wake_latencies[WAKE_SAMPLES - 1],
+    );
+}
+
+fn main() {
+    benchmark_policy("park", LightrayWorkerIdlePolicy { spin_count: 0 });
+    benchmark_policy("default", LightrayWorkerIdlePolicy::default());
+    benchmark_policy("spin-4096", LightrayWorkerIdlePolicy { spin_count: 4096 });
+    // Never parks during the benchmark, equivalent to the previous yield-only worker loop.
+    benchmark_policy(
+        "spin-only",
+        LightrayWorkerIdlePolicy {
+            spin_count: u32::MAX,
+        },
+    );
+}
@@ -1,4 +1,4 @@
-pub(crate) mod lightray_caching;
+pub mod lightray_caching;
 pub mod lightray_executor;
 pub mod lightray_scheduler;
 pub mod lightray_torch;
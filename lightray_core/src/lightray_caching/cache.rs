@@ -2,16 +2,21 @@ use crate::lightray_executor::executor::LightrayExecutorResult;
 use crate::lightray_executor::model::LightrayModelId;
 use crate::lightray_torch::core::SerializableIValue;
 use crate::lightray_torch::core::TorchScriptInput;
+/// Cache of model outputs. Only outputs of deterministic models may be cached.
 pub trait LightrayCaching {
+    /// Returns the cached result of executing `model_input`, if any.
     fn try_read(
         &mut self,
         model_id: &LightrayModelId,
         model_input: &TorchScriptInput,
     ) -> Option<LightrayExecutorResult>;
+    /// Offers an output for caching. Returns whether it was cached.
     fn hint_cache(
         &mut self,
         model_id: &LightrayModelId,
         model_input: &TorchScriptInput,
         model_output: &SerializableIValue,
     ) -> bool;
+    /// Drops every cached output of `model_id`.
+    fn invalidate_model(&mut self, model_id: &LightrayModelId);
 }
@@ -0,0 +1,240 @@
+use crate::lightray_caching::cache::LightrayCaching;
+use crate::lightray_executor::executor::{LightrayExecutedExample, LightrayExecutorResult};
+use crate::lightray_executor::model::LightrayModelId;
+use crate::lightray_executor::statistics::LightrayModelExecutionStatistic;
+use crate::lightray_torch::core::SerializableIValue;
+use crate::lightray_torch::core::TorchScriptInput;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant, SystemTime};
+
+type CacheKey = (LightrayModelId, Vec<u8>);
+
+struct CacheEntry {
+    output: SerializableIValue,
+    inserted_at: Instant,
+    last_used: u64,
+}
+
+/// In-memory response cache that evicts the least recently used entry once it holds
+/// `max_entries` outputs. Entries older than `time_to_live` are never served.
+pub struct LightrayLruCache {
+    max_entries: usize,
+    time_to_live: Option<Duration>,
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// Keys ordered by last use, oldest first.
+    recency: BTreeMap<u64, CacheKey>,
+    clock: u64,
+}
+
+impl LightrayLruCache {
+    pub fn new(max_entries: usize, time_to_live: Option<Duration>) -> LightrayLruCache {
+        LightrayLruCache {
+            max_entries,
+            time_to_live,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn cache_key(model_id: &LightrayModelId, model_input: &TorchScriptInput) -> CacheKey {
+        (*model_id, model_input.canonical_bytes())
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn is_expired(&self, entry: &CacheEntry) -> bool {
+        match self.time_to_live {
+            Some(time_to_live) => entry.inserted_at.elapsed() >= time_to_live,
+            None => false,
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+        }
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest_key = self.recency.values().next().cloned();
+        if let Some(oldest_key) = oldest_key {
+            self.remove(&oldest_key);
+        }
+    }
+}
+
+impl LightrayCaching for LightrayLruCache {
+    fn try_read(
+        &mut self,
+        model_id: &LightrayModelId,
+        model_input: &TorchScriptInput,
+    ) -> Option<LightrayExecutorResult> {
+        let system_start_time = SystemTime::now();
+        let instant_start_time = Instant::now();
+        let key = LightrayLruCache::cache_key(model_id, model_input);
+        let expired = self.is_expired(self.entries.get(&key)?);
+        if expired {
+            self.remove(&key);
+            return None;
+        }
+        let last_used = self.tick();
+        let entry = self.entries.get_mut(&key)?;
+        self.recency.remove(&entry.last_used);
+        entry.last_used = last_used;
+        self.recency.insert(last_used, key);
+        Some(Ok(LightrayExecutedExample {
+            execution_statistic: LightrayModelExecutionStatistic {
+                elapsed_execution_time: instant_start_time.elapsed(),
+                start_execution_time: system_start_time,
+                end_execution_time: SystemTime::now(),
+                batch_size: 1,
+                cache_hit: true,
+            },
+            execution_result: entry.output.clone(),
+        }))
+    }
+
+    fn hint_cache(
+        &mut self,
+        model_id: &LightrayModelId,
+        model_input: &TorchScriptInput,
+        model_output: &SerializableIValue,
+    ) -> bool {
+        if self.max_entries == 0 {
+            return false;
+        }
+        let key = LightrayLruCache::cache_key(model_id, model_input);
+        self.remove(&key);
+        while self.entries.len() >= self.max_entries {
+            self.evict_least_recently_used();
+        }
+        let last_used = self.tick();
+        self.recency.insert(last_used, key.clone());
+        self.entries.insert(
+            key,
+            CacheEntry {
+                output: model_output.clone(),
+                inserted_at: Instant::now(),
+                last_used,
+            },
+        );
+        true
+    }
+
+    fn invalidate_model(&mut self, model_id: &LightrayModelId) {
+        let model_keys: Vec<CacheKey> = self
+            .entries
+            .keys()
+            .filter(|(cached_model_id, _)| cached_model_id == model_id)
+            .cloned()
+            .collect();
+        for key in model_keys {
+            self.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use uuid::Uuid;
+
+    fn model(model_version: u16) -> LightrayModelId {
+        LightrayModelId {
+            model_id: Uuid::nil(),
+            model_version,
+        }
+    }
+
+    fn input(value: i64) -> TorchScriptInput {
+        TorchScriptInput {
+            positional_arguments: vec![SerializableIValue::Int(value)],
+            ..Default::default()
+        }
+    }
+
+    fn cached_output(
+        cache: &mut LightrayLruCache,
+        model_id: &LightrayModelId,
+        model_input: &TorchScriptInput,
+    ) -> Option<SerializableIValue> {
+        cache
+            .try_read(model_id, model_input)
+            .map(|result| result.unwrap().execution_result)
+    }
+
+    #[test]
+    fn test_cache_hit() {
+        let mut cache = LightrayLruCache::new(4, None);
+        assert!(cached_output(&mut cache, &model(0), &input(1)).is_none());
+        assert!(cache.hint_cache(&model(0), &input(1), &SerializableIValue::Int(2)));
+        let executed_example = cache.try_read(&model(0), &input(1)).unwrap().unwrap();
+        assert!(executed_example.execution_statistic.cache_hit);
+        assert_eq!(
+            executed_example.execution_result,
+            SerializableIValue::Int(2)
+        );
+        assert!(cached_output(&mut cache, &model(1), &input(1)).is_none());
+        assert!(cached_output(&mut cache, &model(0), &input(2)).is_none());
+    }
+
+    #[test]
+    fn test_equivalent_inputs_share_entries() {
+        let mut cache = LightrayLruCache::new(4, None);
+        let negative_zero = TorchScriptInput {
+            positional_arguments: vec![SerializableIValue::Double(-0.0)],
+            ..Default::default()
+        };
+        let positive_zero = TorchScriptInput {
+            positional_arguments: vec![SerializableIValue::Double(0.0)],
+            ..Default::default()
+        };
+        cache.hint_cache(&model(0), &negative_zero, &SerializableIValue::Int(1));
+        assert!(cached_output(&mut cache, &model(0), &positive_zero).is_some());
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let mut cache = LightrayLruCache::new(2, None);
+        cache.hint_cache(&model(0), &input(1), &SerializableIValue::Int(1));
+        cache.hint_cache(&model(0), &input(2), &SerializableIValue::Int(2));
+        assert!(cached_output(&mut cache, &model(0), &input(1)).is_some());
+        cache.hint_cache(&model(0), &input(3), &SerializableIValue::Int(3));
+        assert_eq!(cache.len(), 2);
+        assert!(cached_output(&mut cache, &model(0), &input(1)).is_some());
+        assert!(cached_output(&mut cache, &model(0), &input(2)).is_none());
+        assert!(cached_output(&mut cache, &model(0), &input(3)).is_some());
+    }
+
+    #[test]
+    fn test_expired_entries_are_not_served() {
+        let mut cache = LightrayLruCache::new(2, Some(Duration::from_millis(10)));
+        cache.hint_cache(&model(0), &input(1), &SerializableIValue::Int(1));
+        thread::sleep(Duration::from_millis(20));
+        assert!(cached_output(&mut cache, &model(0), &input(1)).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_invalidate_model() {
+        let mut cache = LightrayLruCache::new(4, None);
+        cache.hint_cache(&model(0), &input(1), &SerializableIValue::Int(1));
+        cache.hint_cache(&model(1), &input(1), &SerializableIValue::Int(1));
+        cache.invalidate_model(&model(0));
+        assert!(cached_output(&mut cache, &model(0), &input(1)).is_none());
+        assert!(cached_output(&mut cache, &model(1), &input(1)).is_some());
+    }
+}
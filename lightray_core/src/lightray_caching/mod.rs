@@ -1 +1,2 @@
-pub(crate) mod cache;
+pub mod cache;
+pub mod lru_cache;
@@ -1,4 +1,5 @@
 use crate::lightray_torch::errors::InternalTorchError;
+use serde::Serialize;
 use std::error::Error;
 use std::fmt;
 use std::sync::PoisonError;
@@ -6,22 +7,64 @@ use std::sync::PoisonError;
 pub struct LightrayMissingSamples {}
 
 #[derive(Debug)]
-pub struct LightrayVerificationInputSize {
-    pub input_length: u16,
-    pub output_length: u16,
+pub struct LightrayMissingSemantics {
+    pub method: String,
+}
+
+#[derive(Debug)]
+pub struct LightrayUnknownMethod {
+    pub method: String,
+}
+
+#[derive(Debug)]
+pub struct LightrayInconsistentSample {
+    pub method: String,
+    /// Position of the sample among the samples of the method.
+    pub sample_position: usize,
+    pub error: LightrayModelInputSemanticError,
 }
+
 #[derive(Debug)]
-pub struct LightrayVerificationInputTypes {
-    pub argument_position: u16,
+pub struct LightrayUnsampledSignature {
+    pub method: String,
+    /// Position of the signature, `0` being `positional_semantics` and the signature variants
+    /// following.
+    pub signature_position: usize,
 }
+
+/// Output of a method violating its `output_semantics`.
 #[derive(Debug)]
-pub struct LightrayVerificationInputDoesNotEqual {
-    pub argument_position: u16,
+pub struct LightrayModelOutputSemanticError {
+    pub method: String,
+    pub report: LightrayVerificationReport,
 }
+
 #[derive(Debug)]
-pub struct LightrayVerificationInputSizeDoesNotEqual {
-    pub argument_position: u16,
-    pub expected_input_size: u16,
+pub struct LightrayInconsistentSampleOutput {
+    /// Position of the sample among the samples of the method.
+    pub sample_position: usize,
+    pub error: LightrayModelOutputSemanticError,
+}
+
+#[derive(Debug)]
+pub struct LightrayVerificationInputSize {
+    pub input_length: u16,
+    pub output_length: u16,
+}
+/// Value of an input violating a rule of its semantics.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct LightraySemanticViolation {
+    /// JSON path of the value, indexing into nested tuples and lists, e.g.
+    /// `$.positional_arguments[0][2]`.
+    pub path: String,
+    /// Name of the violated semantic, e.g. `TensorShape`.
+    pub rule: String,
+    pub expected: String,
+    pub actual: String,
+}
+#[derive(Debug, Serialize)]
+pub struct LightrayVerificationReport {
+    pub violations: Vec<LightraySemanticViolation>,
 }
 impl fmt::Display for LightrayMissingSamples {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -34,64 +77,142 @@ impl fmt::Display for LightrayMissingSamples {
 
 impl Error for LightrayMissingSamples {}
 
-impl fmt::Display for LightrayVerificationInputSize {
+impl fmt::Display for LightrayMissingSemantics {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "LightrayModel expected {0} inputs but instead got {1}",
-            self.input_length, self.output_length
+            "LightrayModel is missing semantics of method {0}, which cannot be derived from its samples",
+            self.method
         )
     }
 }
 
-impl Error for LightrayVerificationInputSize {}
+impl Error for LightrayMissingSemantics {}
 
-impl fmt::Display for LightrayVerificationInputTypes {
+impl fmt::Display for LightrayUnknownMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LightrayModel has no registered method {0}", self.method)
+    }
+}
+
+impl Error for LightrayUnknownMethod {}
+
+impl fmt::Display for LightrayInconsistentSample {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "LightrayModel input does not match the correct type at position {0}",
-            self.argument_position
+            "Sample {0} of method {1} does not satisfy its semantics: {2}",
+            self.sample_position, self.method, self.error
         )
     }
 }
 
-impl Error for LightrayVerificationInputTypes {}
+impl Error for LightrayInconsistentSample {}
 
-impl fmt::Display for LightrayVerificationInputDoesNotEqual {
+impl fmt::Display for LightrayUnsampledSignature {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "LightrayModel input at position {0} does not equal it's exact value",
-            self.argument_position,
+            "Signature {0} of method {1} has no sample passing as many arguments",
+            self.signature_position, self.method
         )
     }
 }
 
-impl Error for LightrayVerificationInputDoesNotEqual {}
+impl Error for LightrayUnsampledSignature {}
 
-impl fmt::Display for LightrayVerificationInputSizeDoesNotEqual {
+impl fmt::Display for LightrayModelOutputSemanticError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "LightrayModel input at position {0} requires exactly {1} arguments",
-            self.argument_position, self.expected_input_size
+            "LightrayModel output of method {0} violates its output semantics",
+            self.method
+        )?;
+        self.report.fmt_violations(f)
+    }
+}
+
+impl Error for LightrayModelOutputSemanticError {}
+
+impl fmt::Display for LightrayInconsistentSampleOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Sample {0}: {1}", self.sample_position, self.error)
+    }
+}
+
+impl Error for LightrayInconsistentSampleOutput {}
+
+impl fmt::Display for LightrayVerificationInputSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "LightrayModel expected {0} inputs but instead got {1}",
+            self.input_length, self.output_length
         )
     }
 }
 
-impl Error for LightrayVerificationInputSizeDoesNotEqual {}
+impl Error for LightrayVerificationInputSize {}
+
+impl LightrayVerificationReport {
+    /// Writes the violations after a sentence saying what violates the semantics.
+    fn fmt_violations(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, violation) in self.violations.iter().enumerate() {
+            write!(
+                f,
+                "{0} {1} ({2}): expected {3}, got {4}",
+                if index == 0 { ":" } else { ";" },
+                violation.path,
+                violation.rule,
+                violation.expected,
+                violation.actual
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl From<LightrayVerificationInputSize> for LightrayVerificationReport {
+    /// Reports an input passing a number of arguments no signature takes as a violation of
+    /// its arguments, so that every semantic error is reported alike.
+    fn from(error: LightrayVerificationInputSize) -> LightrayVerificationReport {
+        LightrayVerificationReport {
+            violations: vec![LightraySemanticViolation {
+                path: "$.positional_arguments".to_string(),
+                rule: "ArgumentCount".to_string(),
+                expected: format!("{} arguments", error.output_length),
+                actual: format!("{} arguments", error.input_length),
+            }],
+        }
+    }
+}
+
+impl fmt::Display for LightrayVerificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LightrayModel input violates its semantics")?;
+        self.fmt_violations(f)
+    }
+}
+
+impl Error for LightrayVerificationReport {}
 
 #[derive(Debug)]
 pub enum LightrayModelVerificationError {
     InternalTorchError(InternalTorchError),
     LightrayMissingSamples(LightrayMissingSamples),
+    LightrayMissingSemantics(LightrayMissingSemantics),
+    LightrayUnknownMethod(LightrayUnknownMethod),
+    LightrayInconsistentSample(LightrayInconsistentSample),
+    LightrayUnsampledSignature(LightrayUnsampledSignature),
+    LightrayInconsistentSampleOutput(LightrayInconsistentSampleOutput),
 }
 
 #[derive(Debug)]
 pub enum LightrayModelExecutionError {
     LightrayModelInputSemanticError(LightrayModelInputSemanticError),
+    LightrayModelOutputSemanticError(LightrayModelOutputSemanticError),
     InternalTorchScriptError(InternalTorchError),
+    LightrayUnknownMethod(LightrayUnknownMethod),
     IncorrectTypeSignature,
     MissingModel,
     PoisonError,
@@ -117,7 +238,16 @@ impl<T> From<PoisonError<T>> for LightrayModelExecutionError {
 #[derive(Debug)]
 pub enum LightrayModelInputSemanticError {
     LightrayVerificationInputSize(LightrayVerificationInputSize),
-    LightrayVerificationInputTypes(LightrayVerificationInputTypes),
-    LightrayVerificationInputDoesNotEqual(LightrayVerificationInputDoesNotEqual),
-    LightrayVerificationInputSizeDoesNotEqual(LightrayVerificationInputSizeDoesNotEqual),
+    LightrayVerificationReport(LightrayVerificationReport),
 }
+
+impl fmt::Display for LightrayModelInputSemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LightrayModelInputSemanticError::LightrayVerificationInputSize(error) => error.fmt(f),
+            LightrayModelInputSemanticError::LightrayVerificationReport(report) => report.fmt(f),
+        }
+    }
+}
+
+impl Error for LightrayModelInputSemanticError {}
@@ -1,12 +1,13 @@
+use crate::lightray_caching::cache::LightrayCaching;
 use crate::lightray_executor::errors::{LightrayModelExecutionError, LightrayRegistrationError};
-use crate::lightray_executor::model::{LightrayModel, LightrayModelId};
+use crate::lightray_executor::model::{LightrayModel, LightrayModelDescription, LightrayModelId};
 use crate::lightray_executor::statistics::LightrayModelExecutionStatistic;
 use crate::lightray_torch::core::{SerializableIValue, TorchScriptInput};
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::mem::drop;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Instant, SystemTime};
 
 pub type LightrayExecutorResult = Result<LightrayExecutedExample, LightrayModelExecutionError>;
@@ -17,7 +18,7 @@ pub struct LightrayExecutedExample {
     pub execution_result: SerializableIValue,
 }
 
-pub trait LightrayExecutor {
+pub trait LightrayExecutor: Send + Sync {
     fn execute(
         &self,
         model_id: &LightrayModelId,
@@ -25,6 +26,21 @@ pub trait LightrayExecutor {
         do_verification: bool,
     ) -> LightrayExecutorResult;
 
+    fn execute_batched(
+        &self,
+        model_id: &LightrayModelId,
+        examples: &[TorchScriptInput],
+        do_verification: bool,
+    ) -> Vec<LightrayExecutorResult>;
+
+    fn is_batchable(&self, model_id: &LightrayModelId) -> bool;
+
+    /// Describes how to call the model `model_id`.
+    fn describe_model(
+        &self,
+        model_id: &LightrayModelId,
+    ) -> Result<LightrayModelDescription, LightrayModelExecutionError>;
+
     fn register_model(
         &self,
         model: LightrayModel,
@@ -36,12 +52,58 @@ pub trait LightrayExecutor {
 #[derive(Default)]
 pub struct InMemorySimpleLightrayExecutor {
     in_memory_mapping: Arc<RwLock<HashMap<LightrayModelId, Arc<LightrayModel>>>>,
+    response_cache: Option<Mutex<Box<dyn LightrayCaching + Send>>>,
 }
 
 impl InMemorySimpleLightrayExecutor {
     pub fn new() -> Self {
         Self {
             in_memory_mapping: Arc::new(RwLock::new(HashMap::new())),
+            response_cache: None,
+        }
+    }
+    /// Executor that serves repeated inputs of deterministic models from `response_cache`
+    /// instead of running `forward` again.
+    pub fn with_response_cache<C: LightrayCaching + Send + 'static>(response_cache: C) -> Self {
+        Self {
+            in_memory_mapping: Arc::new(RwLock::new(HashMap::new())),
+            response_cache: Some(Mutex::new(Box::new(response_cache))),
+        }
+    }
+    fn get_model(
+        &self,
+        model_id: &LightrayModelId,
+    ) -> Result<Arc<LightrayModel>, LightrayModelExecutionError> {
+        let read_guard = self.in_memory_mapping.read()?;
+        let model = read_guard
+            .get(model_id)
+            .ok_or(LightrayModelExecutionError::MissingModel)?
+            .clone();
+        drop(read_guard);
+        Ok(model)
+    }
+    fn read_cache(
+        &self,
+        model: &LightrayModel,
+        example: &TorchScriptInput,
+    ) -> Option<LightrayExecutorResult> {
+        if !model.executor.deterministic {
+            return None;
+        }
+        let mut response_cache = self.response_cache.as_ref()?.lock().ok()?;
+        response_cache.try_read(&model.id, example)
+    }
+    fn hint_cache(
+        &self,
+        model: &LightrayModel,
+        example: &TorchScriptInput,
+        output: &SerializableIValue,
+    ) {
+        if !model.executor.deterministic {
+            return;
+        }
+        if let Some(Ok(mut response_cache)) = self.response_cache.as_ref().map(Mutex::lock) {
+            response_cache.hint_cache(&model.id, example, output);
         }
     }
 }
@@ -53,32 +115,125 @@ impl LightrayExecutor for InMemorySimpleLightrayExecutor {
         example: &TorchScriptInput,
         do_semantic_verification: bool,
     ) -> LightrayExecutorResult {
-        let read_guard = self.in_memory_mapping.read()?;
-        let model = read_guard
-            .get(&model_id)
-            .ok_or(LightrayModelExecutionError::MissingModel)?
-            .clone();
-        drop(read_guard);
+        let model = self.get_model(model_id)?;
+        // Cached responses are only served to inputs the model would execute.
+        model.verify_input(example, do_semantic_verification)?;
+        if let Some(cached_result) = self.read_cache(&model, example) {
+            return cached_result;
+        }
 
         let system_start_time = SystemTime::now();
         let instant_start_time = Instant::now();
-        let model_output = model.execute(&example, do_semantic_verification);
+        let model_output = model.forward(&example);
         let instant_end_time = Instant::now();
         let system_end_time = SystemTime::now();
 
         match model_output {
-            Ok(output_value) => Ok(LightrayExecutedExample {
-                execution_statistic: LightrayModelExecutionStatistic {
-                    elapsed_execution_time: instant_end_time - instant_start_time,
-                    start_execution_time: system_start_time,
-                    end_execution_time: system_end_time,
-                },
-                execution_result: output_value,
-            }),
+            Ok(output_value) => {
+                self.hint_cache(&model, example, &output_value);
+                Ok(LightrayExecutedExample {
+                    execution_statistic: LightrayModelExecutionStatistic {
+                        elapsed_execution_time: instant_end_time - instant_start_time,
+                        start_execution_time: system_start_time,
+                        end_execution_time: system_end_time,
+                        batch_size: 1,
+                        cache_hit: false,
+                    },
+                    execution_result: output_value,
+                })
+            }
             Err(error) => Err(error),
         }
     }
 
+    fn execute_batched(
+        &self,
+        model_id: &LightrayModelId,
+        examples: &[TorchScriptInput],
+        do_semantic_verification: bool,
+    ) -> Vec<LightrayExecutorResult> {
+        let model = match self.get_model(model_id) {
+            Ok(model) => model,
+            Err(_) => {
+                return examples
+                    .iter()
+                    .map(|example| self.execute(model_id, example, do_semantic_verification))
+                    .collect()
+            }
+        };
+
+        // Examples failing verification and cached examples are answered directly, the
+        // remaining ones are executed as a batch.
+        let answered_results: Vec<Option<LightrayExecutorResult>> = examples
+            .iter()
+            .map(
+                |example| match model.verify_input(example, do_semantic_verification) {
+                    Ok(()) => self.read_cache(&model, example),
+                    Err(error) => Some(Err(error)),
+                },
+            )
+            .collect();
+        let uncached_examples: Vec<&TorchScriptInput> = examples
+            .iter()
+            .zip(&answered_results)
+            .filter(|(_, answered_result)| answered_result.is_none())
+            .map(|(example, _)| example)
+            .collect();
+        if uncached_examples.is_empty() {
+            return answered_results.into_iter().flatten().collect();
+        }
+
+        let system_start_time = SystemTime::now();
+        let instant_start_time = Instant::now();
+        let model_outputs = model.forward_batched(&uncached_examples);
+        let instant_end_time = Instant::now();
+        let system_end_time = SystemTime::now();
+
+        // Models that are not batchable execute the examples one at a time.
+        let batch_size = if model.executor.batchable {
+            uncached_examples.len()
+        } else {
+            1
+        };
+        let mut executed_results =
+            model_outputs
+                .into_iter()
+                .zip(uncached_examples)
+                .map(|(model_output, example)| {
+                    model_output.map(|output_value| {
+                        self.hint_cache(&model, example, &output_value);
+                        LightrayExecutedExample {
+                            execution_statistic: LightrayModelExecutionStatistic {
+                                elapsed_execution_time: instant_end_time - instant_start_time,
+                                start_execution_time: system_start_time,
+                                end_execution_time: system_end_time,
+                                batch_size,
+                                cache_hit: false,
+                            },
+                            execution_result: output_value,
+                        }
+                    })
+                });
+        answered_results
+            .into_iter()
+            .filter_map(|answered_result| answered_result.or_else(|| executed_results.next()))
+            .collect()
+    }
+
+    fn is_batchable(&self, model_id: &LightrayModelId) -> bool {
+        match self.get_model(model_id) {
+            Ok(model) => model.executor.batchable,
+            Err(_) => false,
+        }
+    }
+
+    fn describe_model(
+        &self,
+        model_id: &LightrayModelId,
+    ) -> Result<LightrayModelDescription, LightrayModelExecutionError> {
+        Ok(self.get_model(model_id)?.describe())
+    }
+
     fn register_model(
         &self,
         model: LightrayModel,
@@ -93,7 +248,13 @@ impl LightrayExecutor for InMemorySimpleLightrayExecutor {
     fn delete_model(&self, model_id: LightrayModelId) -> Result<(), LightrayRegistrationError> {
         match self.in_memory_mapping.write()?.remove(&model_id) {
             None => Err(LightrayRegistrationError::MissingModel),
-            _ => Ok(()),
+            _ => {
+                if let Some(Ok(mut response_cache)) = self.response_cache.as_ref().map(Mutex::lock)
+                {
+                    response_cache.invalidate_model(&model_id);
+                }
+                Ok(())
+            }
         }
     }
 }
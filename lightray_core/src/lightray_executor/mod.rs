@@ -3,6 +3,8 @@ pub mod executor;
 pub mod model;
 pub mod semantics;
 pub mod statistics;
+pub mod verification;
 
 pub use self::model::*;
 pub use self::semantics::*;
+pub use self::verification::*;
@@ -1,10 +1,23 @@
 use crate::lightray_executor::errors::{
-    LightrayMissingSamples, LightrayModelExecutionError, LightrayModelVerificationError,
+    LightrayInconsistentSample, LightrayInconsistentSampleOutput, LightrayMissingSamples,
+    LightrayMissingSemantics, LightrayModelExecutionError, LightrayModelOutputSemanticError,
+    LightrayModelVerificationError, LightrayUnknownMethod, LightrayUnsampledSignature,
 };
 use crate::lightray_executor::semantics::LightrayModelSemantics;
-use crate::lightray_torch::core::{SerializableIValue, TorchScriptGraph, TorchScriptInput};
+use crate::lightray_executor::verification::{
+    LightrayVerificationPolicy, LightrayVerificationSampler,
+};
+use crate::lightray_torch::core::{
+    resolve_arguments, ResolvedArgument, SerializableIValue, TorchScriptGraph, TorchScriptInput,
+};
+use crate::lightray_torch::errors::InternalTorchError;
+use crate::lightray_torch::schema::TorchScriptMethodSchema;
 
 use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::slice;
+use std::sync::atomic::{AtomicU64, Ordering};
 use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Copy, Clone)]
@@ -12,37 +25,233 @@ pub struct LightrayModelId {
     pub model_id: Uuid,
     pub model_version: u16,
 }
+/// Samples, semantics and schema of an exported method of a model.
+pub struct LightrayModelMethod {
+    pub samples: Vec<TorchScriptInput>,
+    /// Arguments the method is called with by each of the `samples`, see `resolve_arguments`.
+    pub sample_arguments: Vec<Vec<SerializableIValue>>,
+    pub semantics: LightrayModelSemantics,
+    /// Signature of the method, if it could be read from the TorchScript file.
+    pub schema: Option<TorchScriptMethodSchema>,
+}
+/// How to call a method of a registered model.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LightrayMethodDescription {
+    pub schema: Option<TorchScriptMethodSchema>,
+    pub semantics: LightrayModelSemantics,
+}
+/// How to call a registered model.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LightrayModelDescription {
+    #[serde(flatten)]
+    pub id: LightrayModelId,
+    pub batchable: bool,
+    pub deterministic: bool,
+    pub verification_policy: LightrayVerificationPolicy,
+    /// Number of inputs violating the semantics that were executed under a `Shadow` policy.
+    pub shadow_violations: u64,
+    /// Number of outputs rejected for violating the output semantics of their method.
+    pub output_violations: u64,
+    /// Why the method schemas could not be read from the TorchScript file, if they could not.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_error: Option<String>,
+    pub methods: BTreeMap<String, LightrayMethodDescription>,
+}
 pub struct LightrayModel {
     pub id: LightrayModelId,
     pub executor: TorchScriptGraph,
-    pub samples: Vec<TorchScriptInput>,
-    pub semantics: LightrayModelSemantics,
+    /// Methods that can be executed, by name.
+    pub methods: HashMap<String, LightrayModelMethod>,
+    /// When inputs are verified against the semantics of their method.
+    pub verification_policy: LightrayVerificationPolicy,
+    verification_sampler: LightrayVerificationSampler,
+    shadow_violations: AtomicU64,
+    output_violations: AtomicU64,
+    schema_error: Option<String>,
+}
+/// Values of the arguments `sample` calls the method described by `schema` with.
+fn resolved_values(
+    sample: &TorchScriptInput,
+    schema: Option<&TorchScriptMethodSchema>,
+) -> Result<Vec<SerializableIValue>, String> {
+    Ok(resolve_arguments(sample, schema)?
+        .into_iter()
+        .map(|argument| argument.value.into_owned())
+        .collect())
 }
+
 impl LightrayModel {
+    /// Model executing the methods called by `samples`. Methods without an entry in `semantics`
+    /// have theirs derived from their samples, and every method with semantics needs at least one
+    /// sample.
     pub fn new(
         id: LightrayModelId,
         executor: TorchScriptGraph,
         samples: Vec<TorchScriptInput>,
-        semantics: LightrayModelSemantics,
+        mut semantics: HashMap<String, LightrayModelSemantics>,
     ) -> Result<LightrayModel, LightrayModelVerificationError> {
+        let mut method_samples: HashMap<String, Vec<TorchScriptInput>> = HashMap::new();
+        for sample in samples {
+            method_samples
+                .entry(sample.method_name().to_string())
+                .or_default()
+                .push(sample);
+        }
+        if semantics
+            .keys()
+            .any(|method| !method_samples.contains_key(method))
+        {
+            return Err(LightrayModelVerificationError::LightrayMissingSamples(
+                LightrayMissingSamples {},
+            ));
+        }
+        let mut methods = HashMap::with_capacity(method_samples.len());
+        for (method, samples) in method_samples {
+            let schema = executor
+                .schema
+                .as_ref()
+                .and_then(|schema| schema.method(&method))
+                .cloned();
+            let sample_arguments = samples
+                .iter()
+                .map(|sample| resolved_values(sample, schema.as_ref()))
+                .collect::<Result<Vec<Vec<SerializableIValue>>, String>>()
+                .map_err(|error| {
+                    LightrayModelVerificationError::InternalTorchError(InternalTorchError::from(
+                        error,
+                    ))
+                })?;
+            let method_semantics = semantics
+                .remove(&method)
+                .or_else(|| LightrayModelSemantics::derive_from_samples(&sample_arguments))
+                .ok_or_else(|| {
+                    LightrayModelVerificationError::LightrayMissingSemantics(
+                        LightrayMissingSemantics {
+                            method: method.clone(),
+                        },
+                    )
+                })?;
+            methods.insert(
+                method,
+                LightrayModelMethod {
+                    samples,
+                    sample_arguments,
+                    semantics: method_semantics,
+                    schema,
+                },
+            );
+        }
         let model = LightrayModel {
             id,
-            samples,
             executor,
-            semantics,
+            methods,
+            verification_policy: LightrayVerificationPolicy::Off,
+            verification_sampler: LightrayVerificationSampler::default(),
+            shadow_violations: AtomicU64::new(0),
+            output_violations: AtomicU64::new(0),
+            schema_error: None,
         };
         model.verify()?;
         Ok(model)
     }
+
+    /// Verifies inputs as `verification_policy` says, even when executed without semantic
+    /// verification.
+    pub fn with_verification_policy(
+        mut self,
+        verification_policy: LightrayVerificationPolicy,
+    ) -> LightrayModel {
+        self.verification_policy = verification_policy;
+        self
+    }
+
+    /// Reports in the description why the schema of the TorchScript file could not be read.
+    pub fn with_schema_error(mut self, schema_error: String) -> LightrayModel {
+        self.schema_error = Some(schema_error);
+        self
+    }
+    /// Checks that every method is exported and has samples that satisfy its semantics and
+    /// whose outputs satisfy its output semantics.
+    ///
+    /// Each sample is only compared with itself, so rules comparing with a sample, such as
+    /// `ExactValueMatch` or `SizeMatch`, always hold for it and only the other rules are
+    /// checked. Samples are not compared with each other because samples differing in such
+    /// rules are how inputs of several shapes are accepted.
     pub fn verify(&self) -> Result<(), LightrayModelVerificationError> {
-        if self.samples.is_empty() {
+        if self.methods.is_empty() {
             return Err(LightrayModelVerificationError::LightrayMissingSamples(
                 LightrayMissingSamples {},
             ));
         }
-        for sample in &self.samples {
-            if let Err(err) = self.executor.forward(&sample) {
-                return Err(LightrayModelVerificationError::InternalTorchError(err));
+        for (name, method) in &self.methods {
+            if !self.executor.has_method(name) {
+                return Err(LightrayModelVerificationError::LightrayUnknownMethod(
+                    LightrayUnknownMethod {
+                        method: name.clone(),
+                    },
+                ));
+            }
+            if method.samples.is_empty() {
+                return Err(LightrayModelVerificationError::LightrayMissingSamples(
+                    LightrayMissingSamples {},
+                ));
+            }
+            for (sample_position, (sample, arguments)) in method
+                .samples
+                .iter()
+                .zip(&method.sample_arguments)
+                .enumerate()
+            {
+                let resolved =
+                    resolve_arguments(sample, method.schema.as_ref()).map_err(|error| {
+                        LightrayModelVerificationError::InternalTorchError(
+                            InternalTorchError::from(error),
+                        )
+                    })?;
+                if let Err(error) = method
+                    .semantics
+                    .verify_semantics(&resolved, slice::from_ref(arguments))
+                {
+                    return Err(LightrayModelVerificationError::LightrayInconsistentSample(
+                        LightrayInconsistentSample {
+                            method: name.clone(),
+                            sample_position,
+                            error,
+                        },
+                    ));
+                }
+            }
+            if let Some(signature_position) = method
+                .semantics
+                .unsampled_signature(&method.sample_arguments)
+            {
+                return Err(LightrayModelVerificationError::LightrayUnsampledSignature(
+                    LightrayUnsampledSignature {
+                        method: name.clone(),
+                        signature_position,
+                    },
+                ));
+            }
+            for (sample_position, sample) in method.samples.iter().enumerate() {
+                let output = match self.executor.forward(&sample) {
+                    Ok(output) => output,
+                    Err(err) => {
+                        return Err(LightrayModelVerificationError::InternalTorchError(err))
+                    }
+                };
+                if let Err(report) = method.semantics.verify_output(&output) {
+                    return Err(
+                        LightrayModelVerificationError::LightrayInconsistentSampleOutput(
+                            LightrayInconsistentSampleOutput {
+                                sample_position,
+                                error: LightrayModelOutputSemanticError {
+                                    method: name.clone(),
+                                    report,
+                                },
+                            },
+                        ),
+                    );
+                }
             }
         }
         Ok(())
@@ -50,7 +259,7 @@ impl LightrayModel {
     pub fn warmup_jit(&self, warmup_count: u16) -> Result<(), LightrayModelVerificationError> {
         let mut counter = 0;
         loop {
-            for sample in &self.samples {
+            for sample in self.methods.values().flat_map(|method| &method.samples) {
                 if counter >= warmup_count {
                     return Ok(());
                 }
@@ -62,20 +271,206 @@ impl LightrayModel {
         }
     }
 
-    pub fn execute(
+    pub fn describe(&self) -> LightrayModelDescription {
+        LightrayModelDescription {
+            id: self.id,
+            batchable: self.executor.batchable,
+            deterministic: self.executor.deterministic,
+            verification_policy: self.verification_policy,
+            shadow_violations: self.shadow_violations.load(Ordering::Relaxed),
+            output_violations: self.output_violations.load(Ordering::Relaxed),
+            schema_error: self.schema_error.clone(),
+            methods: self
+                .methods
+                .iter()
+                .map(|(name, method)| {
+                    (
+                        name.clone(),
+                        LightrayMethodDescription {
+                            schema: method.schema.clone(),
+                            semantics: method.semantics.clone(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Registered method `input` calls.
+    fn method(
+        &self,
+        input: &TorchScriptInput,
+    ) -> Result<&LightrayModelMethod, LightrayModelExecutionError> {
+        self.methods.get(input.method_name()).ok_or_else(|| {
+            LightrayModelExecutionError::LightrayUnknownMethod(LightrayUnknownMethod {
+                method: input.method_name().to_string(),
+            })
+        })
+    }
+
+    /// Verifies `input` if `do_semantic_verification` is set or the verification policy of the
+    /// model picks it. Executors call it before answering `input` from a response cache.
+    pub(crate) fn verify_input(
         &self,
         input: &TorchScriptInput,
         do_semantic_verification: bool,
-    ) -> Result<SerializableIValue, LightrayModelExecutionError> {
+    ) -> Result<(), LightrayModelExecutionError> {
+        let method = self.method(input)?;
+        let verification_policy = if do_semantic_verification {
+            LightrayVerificationPolicy::Always
+        } else {
+            self.verification_policy
+        };
+        let do_semantic_verification = match verification_policy {
+            LightrayVerificationPolicy::Off => false,
+            LightrayVerificationPolicy::Always | LightrayVerificationPolicy::Shadow => true,
+            LightrayVerificationPolicy::Sampled(percent) => {
+                self.verification_sampler.sample(percent)
+            }
+        };
         if do_semantic_verification {
-            if let Err(x) = self.semantics.verify_semantics(input, &self.samples[0]) {
+            // Inputs whose arguments do not resolve fail to execute with the same error.
+            let arguments: Vec<ResolvedArgument> = resolve_arguments(input, method.schema.as_ref())
+                .map_err(|error| {
+                    LightrayModelExecutionError::InternalTorchScriptError(InternalTorchError::from(
+                        error,
+                    ))
+                })?;
+            if let Err(x) = method
+                .semantics
+                .verify_semantics(&arguments, &method.sample_arguments)
+            {
+                if verification_policy == LightrayVerificationPolicy::Shadow {
+                    self.shadow_violations.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
                 return Err(LightrayModelExecutionError::LightrayModelInputSemanticError(x));
             }
         }
-        let result = self.executor.forward(input);
-        match result {
-            Result::Ok(x) => Ok(x),
+        Ok(())
+    }
+
+    /// Passes on `output` of `input` if it satisfies the output semantics of the method.
+    fn verify_output(
+        &self,
+        input: &TorchScriptInput,
+        output: SerializableIValue,
+    ) -> Result<SerializableIValue, LightrayModelExecutionError> {
+        let method = self.method(input)?;
+        match method.semantics.verify_output(&output) {
+            Ok(()) => Ok(output),
+            Err(report) => {
+                self.output_violations.fetch_add(1, Ordering::Relaxed);
+                Err(
+                    LightrayModelExecutionError::LightrayModelOutputSemanticError(
+                        LightrayModelOutputSemanticError {
+                            method: input.method_name().to_string(),
+                            report,
+                        },
+                    ),
+                )
+            }
+        }
+    }
+
+    pub fn execute(
+        &self,
+        input: &TorchScriptInput,
+        do_semantic_verification: bool,
+    ) -> Result<SerializableIValue, LightrayModelExecutionError> {
+        self.verify_input(input, do_semantic_verification)?;
+        self.forward(input)
+    }
+
+    /// Runs the method `input` calls and verifies its output. `input` has to have passed
+    /// `verify_input` already.
+    pub(crate) fn forward(
+        &self,
+        input: &TorchScriptInput,
+    ) -> Result<SerializableIValue, LightrayModelExecutionError> {
+        match self.executor.forward(input) {
+            Result::Ok(x) => self.verify_output(input, x),
             Result::Err(y) => Err(LightrayModelExecutionError::InternalTorchScriptError(y)),
         }
     }
+
+    /// Executes several inputs at once. Only the inputs that pass semantic verification are
+    /// run, see `forward_batched`.
+    pub fn execute_batched<T: Borrow<TorchScriptInput>>(
+        &self,
+        inputs: &[T],
+        do_semantic_verification: bool,
+    ) -> Vec<Result<SerializableIValue, LightrayModelExecutionError>> {
+        let verification_results: Vec<Result<(), LightrayModelExecutionError>> = inputs
+            .iter()
+            .map(|input| self.verify_input(input.borrow(), do_semantic_verification))
+            .collect();
+        let verified_inputs: Vec<&TorchScriptInput> = inputs
+            .iter()
+            .zip(&verification_results)
+            .filter(|(_, verification_result)| verification_result.is_ok())
+            .map(|(input, _)| input.borrow())
+            .collect();
+        let mut forwarded_results = self.forward_batched(&verified_inputs).into_iter();
+        verification_results
+            .into_iter()
+            .filter_map(|verification_result| match verification_result {
+                Ok(()) => forwarded_results.next(),
+                Err(error) => Some(Err(error)),
+            })
+            .collect()
+    }
+
+    /// Runs inputs that passed `verify_input` already. Batchable models run them through one
+    /// batched call per method, falling back to running them in turn if they cannot be
+    /// batched together. Other models run them in turn.
+    pub(crate) fn forward_batched<T: Borrow<TorchScriptInput>>(
+        &self,
+        inputs: &[T],
+    ) -> Vec<Result<SerializableIValue, LightrayModelExecutionError>> {
+        if !self.executor.batchable {
+            return inputs
+                .iter()
+                .map(|input| self.forward(input.borrow()))
+                .collect();
+        }
+        let mut results: Vec<Option<Result<SerializableIValue, LightrayModelExecutionError>>> =
+            Vec::with_capacity(inputs.len());
+        let mut batched_positions: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (position, input) in inputs.iter().enumerate() {
+            let input = input.borrow();
+            results.push(None);
+            batched_positions
+                .entry(input.method_name())
+                .or_default()
+                .push(position);
+        }
+        for positions in batched_positions.values() {
+            let batched_inputs: Vec<&TorchScriptInput> = positions
+                .iter()
+                .map(|position| inputs[*position].borrow())
+                .collect();
+            match self.executor.forward_batched(&batched_inputs) {
+                Result::Ok(outputs) => {
+                    for (position, output) in positions.iter().zip(outputs) {
+                        results[*position] =
+                            Some(self.verify_output(inputs[*position].borrow(), output));
+                    }
+                }
+                Result::Err(error) if positions.len() == 1 => {
+                    results[positions[0]] = Some(Err(
+                        LightrayModelExecutionError::InternalTorchScriptError(error),
+                    ));
+                }
+                // Inputs that cannot be stacked into one batch, e.g. because they differ in a
+                // non-tensor argument, are executed one at a time.
+                Result::Err(_) => {
+                    for position in positions {
+                        results[*position] = Some(self.forward(inputs[*position].borrow()));
+                    }
+                }
+            }
+        }
+        results.into_iter().flatten().collect()
+    }
 }
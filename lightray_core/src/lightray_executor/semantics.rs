@@ -1,94 +1,622 @@
 use crate::lightray_executor::errors::{
-    LightrayModelInputSemanticError, LightrayVerificationInputDoesNotEqual,
-    LightrayVerificationInputSize, LightrayVerificationInputSizeDoesNotEqual,
-    LightrayVerificationInputTypes,
+    LightrayModelInputSemanticError, LightraySemanticViolation, LightrayVerificationInputSize,
+    LightrayVerificationReport,
 };
-use crate::lightray_torch::core::{SerializableIValue, TorchScriptInput};
+use crate::lightray_torch::core::{ResolvedArgument, SerializableIValue};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::iter;
+use std::mem::discriminant;
 
-#[derive(Serialize, Deserialize)]
+/// Rule an argument has to satisfy. Every argument also has to have the type of the argument
+/// of the sample.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum LightrayIValueSemantic {
     ExactValueMatch,
+    /// Tuples, lists and dictionaries have as many elements, and tensors the shape, of the
+    /// sample.
     SizeMatch,
     TypeMatch,
+    /// Value of this type, named as in reports, e.g. `Tensor` or `List`.
+    Type(String),
+    /// Tensor of this dtype, named as in tensor literals, e.g. `float32`.
+    TensorDtype(String),
+    /// Tensor of this shape. `None` dimensions, written `null`, match any size.
+    TensorShape(Vec<Option<i64>>),
+    /// `Int`, `Double` or tensor whose elements are all within the inclusive bounds.
+    Range {
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    /// `Double` or tensor without NaN or infinite elements.
+    Finite,
+    /// Tuple, list or dictionary of at most this many elements, or string of at most this many
+    /// characters.
+    MaxLength(usize),
+    /// String matching this regular expression as a whole.
+    Regex(LightrayRegex),
+    /// String equal to one of these.
+    OneOf(Vec<String>),
+    /// Tuple whose elements satisfy the semantic at their position.
+    Elements(Vec<LightrayIValueSemantic>),
+    /// List whose elements all satisfy this semantic.
+    EachElement(Box<LightrayIValueSemantic>),
+    /// Value satisfying all of these semantics.
+    AllOf(Vec<LightrayIValueSemantic>),
 }
-#[derive(Serialize, Deserialize)]
+
+/// Regular expression of a `Regex` semantic, serialized as its pattern. It is compiled once,
+/// when the semantics are built or deserialized, so invalid patterns fail the registration.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct LightrayRegex {
+    pattern: String,
+    /// `pattern` anchored at both ends.
+    regex: Regex,
+}
+
+impl LightrayRegex {
+    pub fn new(pattern: &str) -> Result<LightrayRegex, regex::Error> {
+        Ok(LightrayRegex {
+            pattern: pattern.to_string(),
+            regex: Regex::new(&format!("^(?:{})$", pattern))?,
+        })
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Whether `value` matches the pattern as a whole.
+    pub fn is_match(&self, value: &str) -> bool {
+        self.regex.is_match(value)
+    }
+}
+
+impl PartialEq for LightrayRegex {
+    fn eq(&self, other: &LightrayRegex) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl TryFrom<String> for LightrayRegex {
+    type Error = regex::Error;
+    fn try_from(pattern: String) -> Result<LightrayRegex, regex::Error> {
+        LightrayRegex::new(&pattern)
+    }
+}
+
+impl From<LightrayRegex> for String {
+    fn from(regex: LightrayRegex) -> String {
+        regex.pattern
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct LightrayModelSemantics {
+    /// Semantics of the arguments the method is called with. Keyword arguments and the arrays
+    /// of NPZ arguments are checked at the position they resolve to, see `resolve_arguments`.
     pub positional_semantics: Vec<LightrayIValueSemantic>,
+    /// Further signatures accepted besides `positional_semantics`, e.g. of methods taking a
+    /// varying number of arguments. Each needs a sample passing as many arguments.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signature_variants: Vec<Vec<LightrayIValueSemantic>>,
+    /// Semantic the output of the method has to satisfy. Rules comparing with samples do not
+    /// constrain outputs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_semantics: Option<LightrayIValueSemantic>,
 }
 
-impl LightrayModelSemantics {
-    pub fn verify_semantics(
+/// Samples only show that a value never varies, or that its size is fixed, if there are at
+/// least this many of them.
+const MIN_DERIVATION_SAMPLES: usize = 2;
+
+fn is_tensor(value: &SerializableIValue) -> bool {
+    matches!(
+        value,
+        SerializableIValue::TensorNPYBase64(_)
+            | SerializableIValue::TensorNPY(_)
+            | SerializableIValue::Tensor { .. }
+    )
+}
+
+/// Whether `value` has the type of `baseline`. Tensors have the same type however they are
+/// written.
+fn same_type(value: &SerializableIValue, baseline: &SerializableIValue) -> bool {
+    (is_tensor(value) && is_tensor(baseline)) || discriminant(value) == discriminant(baseline)
+}
+
+/// Number of elements of tuples, lists and dictionaries.
+fn size(value: &SerializableIValue) -> Option<usize> {
+    match value {
+        SerializableIValue::Tuple(elements) | SerializableIValue::List(elements) => {
+            Some(elements.len())
+        }
+        SerializableIValue::Dict(entries) => Some(entries.len()),
+        _ => None,
+    }
+}
+
+fn shape_matches(shape: &[Option<i64>], actual: &[i64]) -> bool {
+    shape.len() == actual.len()
+        && shape
+            .iter()
+            .zip(actual)
+            .all(|(dimension, actual)| dimension.is_none() || *dimension == Some(*actual))
+}
+
+fn shape_text(shape: &[Option<i64>]) -> String {
+    let dimensions: Vec<String> = shape
+        .iter()
+        .map(|dimension| match dimension {
+            Some(dimension) => dimension.to_string(),
+            None => "*".to_string(),
+        })
+        .collect();
+    format!("[{}]", dimensions.join(", "))
+}
+
+fn range_text(min: &Option<f64>, max: &Option<f64>) -> String {
+    let bound = |bound: &Option<f64>, unbounded: &str| match bound {
+        Some(bound) => bound.to_string(),
+        None => unbounded.to_string(),
+    };
+    format!("[{}, {}]", bound(min, "-inf"), bound(max, "inf"))
+}
+
+/// Describes the tensor `elements` that are `violating`, `None` if none are.
+fn elements_text(elements: &[f64], violating: impl Fn(f64) -> bool) -> Option<String> {
+    let mut violating_elements = elements.iter().filter(|element| violating(**element));
+    let first = violating_elements.next()?;
+    Some(format!(
+        "{} of {} elements, e.g. {}",
+        violating_elements.count() + 1,
+        elements.len(),
+        first
+    ))
+}
+
+/// Name of the type of `value`. Tensors are `Tensor` however they are written.
+fn type_name(value: &SerializableIValue) -> &'static str {
+    match value {
+        SerializableIValue::None => "None",
+        SerializableIValue::Bool(_) => "Bool",
+        SerializableIValue::Int(_) => "Int",
+        SerializableIValue::Double(_) => "Double",
+        SerializableIValue::Str(_) => "Str",
+        SerializableIValue::Tuple(_) => "Tuple",
+        SerializableIValue::List(_) => "List",
+        SerializableIValue::Optional(_) => "Optional",
+        SerializableIValue::TensorNPYBase64(_)
+        | SerializableIValue::TensorNPY(_)
+        | SerializableIValue::Tensor { .. } => "Tensor",
+        SerializableIValue::TensorNPZBase64(_) => "TensorNPZBase64",
+        SerializableIValue::Dict(_) => "Dict",
+    }
+}
+
+/// Values are written out in reports as JSON cut after this many characters.
+const MAX_VALUE_TEXT_LENGTH: usize = 64;
+
+/// `value` as written out in reports. Tensors are described by their dtype and shape.
+fn value_text(value: &SerializableIValue) -> String {
+    if let Some((dtype, shape)) = value.tensor_dtype_and_shape() {
+        return format!("{} tensor of shape {:?}", dtype, shape);
+    }
+    let text = serde_json::to_string(value).unwrap_or_default();
+    if text.chars().count() <= MAX_VALUE_TEXT_LENGTH {
+        return text;
+    }
+    let cut: String = text.chars().take(MAX_VALUE_TEXT_LENGTH).collect();
+    format!("{}...", cut)
+}
+
+impl LightrayIValueSemantic {
+    fn name(&self) -> &'static str {
+        match self {
+            LightrayIValueSemantic::ExactValueMatch => "ExactValueMatch",
+            LightrayIValueSemantic::SizeMatch => "SizeMatch",
+            LightrayIValueSemantic::TypeMatch => "TypeMatch",
+            LightrayIValueSemantic::Type(_) => "Type",
+            LightrayIValueSemantic::TensorDtype(_) => "TensorDtype",
+            LightrayIValueSemantic::TensorShape(_) => "TensorShape",
+            LightrayIValueSemantic::Range { .. } => "Range",
+            LightrayIValueSemantic::Finite => "Finite",
+            LightrayIValueSemantic::MaxLength(_) => "MaxLength",
+            LightrayIValueSemantic::Regex(_) => "Regex",
+            LightrayIValueSemantic::OneOf(_) => "OneOf",
+            LightrayIValueSemantic::Elements(_) => "Elements",
+            LightrayIValueSemantic::EachElement(_) => "EachElement",
+            LightrayIValueSemantic::AllOf(_) => "AllOf",
+        }
+    }
+
+    fn violation(&self, path: &str, expected: String, actual: String) -> LightraySemanticViolation {
+        LightraySemanticViolation {
+            path: path.to_string(),
+            rule: self.name().to_string(),
+            expected,
+            actual,
+        }
+    }
+
+    /// Appends the violations of this semantic by `value`, found at the JSON path `path`, to
+    /// `violations`. `baseline` is the corresponding value of the sample if there is one:
+    /// elements of tuples and lists are compared with the element of the sample at the same
+    /// position, or with its first element for lists.
+    fn collect_violations(
         &self,
-        model_input: &TorchScriptInput,
-        model_baseline: &TorchScriptInput,
-    ) -> Result<(), LightrayModelInputSemanticError> {
-        if model_input.positional_arguments.len() != self.positional_semantics.len() {
-            return Err(
-                LightrayModelInputSemanticError::LightrayVerificationInputSize(
-                    LightrayVerificationInputSize {
-                        input_length: model_input.positional_arguments.len() as u16,
-                        output_length: self.positional_semantics.len() as u16,
-                    },
-                ),
-            );
+        path: &str,
+        value: &SerializableIValue,
+        baseline: Option<&SerializableIValue>,
+        violations: &mut Vec<LightraySemanticViolation>,
+    ) {
+        match baseline {
+            Some(baseline) if !same_type(value, baseline) => {
+                violations.push(LightrayIValueSemantic::TypeMatch.violation(
+                    path,
+                    type_name(baseline).to_string(),
+                    type_name(value).to_string(),
+                ))
+            }
+            _ => self.collect_rule_violations(path, value, baseline, violations),
         }
-        for i in 0..model_input.positional_arguments.len() {
-            if std::mem::discriminant(&model_baseline.positional_arguments[i])
-                != std::mem::discriminant(&model_input.positional_arguments[i])
-            {
-                return Err(
-                    LightrayModelInputSemanticError::LightrayVerificationInputTypes(
-                        LightrayVerificationInputTypes {
-                            argument_position: i as u16,
-                        },
-                    ),
-                );
+    }
+
+    /// `collect_violations` for a value known to have the type of `baseline`.
+    fn collect_rule_violations(
+        &self,
+        path: &str,
+        value: &SerializableIValue,
+        baseline: Option<&SerializableIValue>,
+        violations: &mut Vec<LightraySemanticViolation>,
+    ) {
+        let wrong_type = |expected: &str| {
+            self.violation(path, expected.to_string(), type_name(value).to_string())
+        };
+        match self {
+            LightrayIValueSemantic::ExactValueMatch => match baseline {
+                // Compared by canonical encoding, so equal tensors match however encoded.
+                Some(baseline) if baseline.canonical_bytes() != value.canonical_bytes() => {
+                    violations.push(self.violation(path, value_text(baseline), value_text(value)))
+                }
+                _ => {}
+            },
+            LightrayIValueSemantic::SizeMatch => {
+                let baseline = match baseline {
+                    Some(baseline) => baseline,
+                    None => return,
+                };
+                match (size(baseline), size(value)) {
+                    (Some(expected_size), Some(actual_size)) if expected_size != actual_size => {
+                        violations.push(self.violation(
+                            path,
+                            format!("{} elements", expected_size),
+                            format!("{} elements", actual_size),
+                        ))
+                    }
+                    _ => {}
+                }
+                match (
+                    baseline.tensor_dtype_and_shape(),
+                    value.tensor_dtype_and_shape(),
+                ) {
+                    (Some((_, expected_shape)), Some((_, shape))) if shape != expected_shape => {
+                        violations.push(self.violation(
+                            path,
+                            format!("shape {:?}", expected_shape),
+                            format!("shape {:?}", shape),
+                        ))
+                    }
+                    _ => {}
+                }
             }
-            match &self.positional_semantics[i] {
-                LightrayIValueSemantic::ExactValueMatch => {
-                    if model_baseline.positional_arguments[i] != model_input.positional_arguments[i]
-                    {
-                        return Err(
-                            LightrayModelInputSemanticError::LightrayVerificationInputDoesNotEqual(
-                                LightrayVerificationInputDoesNotEqual {
-                                    argument_position: i as u16,
-                                },
-                            ),
-                        );
+            LightrayIValueSemantic::TypeMatch => {}
+            LightrayIValueSemantic::Type(expected_type) => {
+                if type_name(value) != expected_type {
+                    violations.push(wrong_type(expected_type))
+                }
+            }
+            LightrayIValueSemantic::TensorDtype(expected_dtype) => {
+                match value.tensor_dtype_and_shape() {
+                    Some((dtype, _)) if dtype == *expected_dtype => {}
+                    Some((dtype, _)) => {
+                        violations.push(self.violation(path, expected_dtype.clone(), dtype))
                     }
+                    None => violations.push(wrong_type("Tensor")),
                 }
-                LightrayIValueSemantic::SizeMatch => {
-                    match (
-                        &model_baseline.positional_arguments[i],
-                        &model_input.positional_arguments[i],
-                    ) {
-                        (SerializableIValue::Tuple(x), SerializableIValue::Tuple(y)) => {
-                            if x.len() != y.len() {
-                                return Err(LightrayModelInputSemanticError::LightrayVerificationInputSizeDoesNotEqual(
-                                    LightrayVerificationInputSizeDoesNotEqual{ argument_position: i as u16, expected_input_size: x.len() as u16}));
-                            }
+            }
+            LightrayIValueSemantic::TensorShape(expected_shape) => {
+                match value.tensor_dtype_and_shape() {
+                    Some((_, shape)) if shape_matches(expected_shape, &shape) => {}
+                    Some((_, shape)) => violations.push(self.violation(
+                        path,
+                        shape_text(expected_shape),
+                        format!("{:?}", shape),
+                    )),
+                    None => violations.push(wrong_type("Tensor")),
+                }
+            }
+            LightrayIValueSemantic::Range { min, max } => {
+                let out_of_range = |number: f64| {
+                    number.is_nan()
+                        || matches!(min, Some(min) if number < *min)
+                        || matches!(max, Some(max) if number > *max)
+                };
+                let number = match value {
+                    SerializableIValue::Int(int_value) => *int_value as f64,
+                    SerializableIValue::Double(double_value) => *double_value,
+                    _ => {
+                        let elements = match value.tensor_elements() {
+                            Some(elements) => elements,
+                            None => return violations.push(wrong_type("Int, Double or Tensor")),
+                        };
+                        if let Some(actual) = elements_text(&elements, out_of_range) {
+                            violations.push(self.violation(path, range_text(min, max), actual))
                         }
-                        (SerializableIValue::List(x), SerializableIValue::List(y)) => {
-                            if x.len() != y.len() {
-                                return Err(LightrayModelInputSemanticError::LightrayVerificationInputSizeDoesNotEqual(
-                                    LightrayVerificationInputSizeDoesNotEqual{ argument_position: i as u16, expected_input_size: x.len() as u16}));
+                        return;
+                    }
+                };
+                if out_of_range(number) {
+                    violations.push(self.violation(path, range_text(min, max), number.to_string()))
+                }
+            }
+            LightrayIValueSemantic::Finite => {
+                let non_finite = |number: f64| !number.is_finite();
+                let actual = match value {
+                    SerializableIValue::Double(double_value) if non_finite(*double_value) => {
+                        Some(double_value.to_string())
+                    }
+                    SerializableIValue::Double(_) => None,
+                    _ => match value.tensor_elements() {
+                        Some(elements) => elements_text(&elements, non_finite),
+                        None => return violations.push(wrong_type("Double or Tensor")),
+                    },
+                };
+                if let Some(actual) = actual {
+                    violations.push(self.violation(path, "finite values".to_string(), actual))
+                }
+            }
+            LightrayIValueSemantic::MaxLength(max_length) => {
+                let length = match value {
+                    SerializableIValue::Str(string_value) => string_value.chars().count(),
+                    _ => match size(value) {
+                        Some(length) => length,
+                        None => return violations.push(wrong_type("Str, Tuple, List or Dict")),
+                    },
+                };
+                if length > *max_length {
+                    violations.push(self.violation(
+                        path,
+                        format!("length of at most {}", max_length),
+                        format!("length {}", length),
+                    ))
+                }
+            }
+            LightrayIValueSemantic::Regex(regex) => match value {
+                SerializableIValue::Str(string_value) if regex.is_match(string_value) => {}
+                SerializableIValue::Str(_) => violations.push(self.violation(
+                    path,
+                    regex.pattern().to_string(),
+                    value_text(value),
+                )),
+                _ => violations.push(wrong_type("Str")),
+            },
+            LightrayIValueSemantic::OneOf(allowed_values) => match value {
+                SerializableIValue::Str(string_value) if allowed_values.contains(string_value) => {}
+                SerializableIValue::Str(_) => violations.push(self.violation(
+                    path,
+                    format!("one of {:?}", allowed_values),
+                    value_text(value),
+                )),
+                _ => violations.push(wrong_type("Str")),
+            },
+            LightrayIValueSemantic::Elements(element_semantics) => match value {
+                SerializableIValue::Tuple(elements) => {
+                    if elements.len() != element_semantics.len() {
+                        violations.push(self.violation(
+                            path,
+                            format!("{} elements", element_semantics.len()),
+                            format!("{} elements", elements.len()),
+                        ));
+                    }
+                    for (position, (element_semantic, element)) in
+                        element_semantics.iter().zip(elements).enumerate()
+                    {
+                        let element_baseline = match baseline {
+                            Some(SerializableIValue::Tuple(baseline_elements)) => {
+                                baseline_elements.get(position)
                             }
-                        }
-                        (_, _) => {}
+                            _ => None,
+                        };
+                        element_semantic.collect_violations(
+                            &format!("{}[{}]", path, position),
+                            element,
+                            element_baseline,
+                            violations,
+                        );
+                    }
+                }
+                _ => violations.push(wrong_type("Tuple")),
+            },
+            LightrayIValueSemantic::EachElement(element_semantic) => match value {
+                SerializableIValue::List(elements) => {
+                    for (position, element) in elements.iter().enumerate() {
+                        let element_baseline = match baseline {
+                            Some(SerializableIValue::List(baseline_elements)) => baseline_elements
+                                .get(position)
+                                .or_else(|| baseline_elements.first()),
+                            _ => None,
+                        };
+                        element_semantic.collect_violations(
+                            &format!("{}[{}]", path, position),
+                            element,
+                            element_baseline,
+                            violations,
+                        );
                     }
                 }
-                LightrayIValueSemantic::TypeMatch => { //already satisfied
+                _ => violations.push(wrong_type("List")),
+            },
+            LightrayIValueSemantic::AllOf(semantics) => {
+                for semantic in semantics {
+                    semantic.collect_rule_violations(path, value, baseline, violations);
+                }
+            }
+        }
+    }
+}
+
+/// Strictest semantic all `values` agree on.
+fn derive_semantic(values: &[&SerializableIValue]) -> LightrayIValueSemantic {
+    if values.len() < MIN_DERIVATION_SAMPLES {
+        return LightrayIValueSemantic::TypeMatch;
+    }
+    let canonical_bytes = values[0].canonical_bytes();
+    if values[1..]
+        .iter()
+        .all(|value| value.canonical_bytes() == canonical_bytes)
+    {
+        return LightrayIValueSemantic::ExactValueMatch;
+    }
+    let first_size = size(values[0]);
+    if first_size.is_some() && values[1..].iter().all(|value| size(value) == first_size) {
+        return LightrayIValueSemantic::SizeMatch;
+    }
+    LightrayIValueSemantic::TypeMatch
+}
+
+impl LightrayModelSemantics {
+    /// Semantics inferred from the resolved arguments of samples: `ExactValueMatch` for
+    /// arguments that never vary, `SizeMatch` for tuples, lists and dictionaries whose length
+    /// never varies and `TypeMatch` otherwise. A single sample only yields `TypeMatch`. Returns
+    /// `None` without samples or if they pass different numbers of arguments.
+    pub fn derive_from_samples(
+        sample_arguments: &[Vec<SerializableIValue>],
+    ) -> Option<LightrayModelSemantics> {
+        let argument_count = sample_arguments.first()?.len();
+        if sample_arguments
+            .iter()
+            .any(|arguments| arguments.len() != argument_count)
+        {
+            return None;
+        }
+        let positional_semantics = (0..argument_count)
+            .map(|position| {
+                let values: Vec<&SerializableIValue> = sample_arguments
+                    .iter()
+                    .map(|arguments| &arguments[position])
+                    .collect();
+                derive_semantic(&values)
+            })
+            .collect();
+        Some(LightrayModelSemantics {
+            positional_semantics,
+            ..Default::default()
+        })
+    }
+
+    /// Checks the output of the method against `output_semantics`.
+    pub fn verify_output(
+        &self,
+        model_output: &SerializableIValue,
+    ) -> Result<(), LightrayVerificationReport> {
+        let mut violations = Vec::new();
+        if let Some(output_semantics) = &self.output_semantics {
+            output_semantics.collect_violations("$", model_output, None, &mut violations);
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(LightrayVerificationReport { violations })
+        }
+    }
+
+    /// Accepted signatures, `positional_semantics` followed by `signature_variants`.
+    fn signatures(&self) -> impl Iterator<Item = &Vec<LightrayIValueSemantic>> {
+        iter::once(&self.positional_semantics).chain(&self.signature_variants)
+    }
+
+    /// Position among `signatures` of the first signature no sample passes as many arguments
+    /// to, given the resolved arguments of the samples.
+    pub fn unsampled_signature(
+        &self,
+        sample_arguments: &[Vec<SerializableIValue>],
+    ) -> Option<usize> {
+        self.signatures().position(|signature| {
+            !sample_arguments
+                .iter()
+                .any(|arguments| arguments.len() == signature.len())
+        })
+    }
+
+    /// Checks the resolved arguments of an input against these semantics. They are accepted if
+    /// they satisfy any signature compared with the resolved arguments of any sample passing as
+    /// many arguments. Otherwise the violations of the signature and sample they come closest
+    /// to are reported.
+    pub fn verify_semantics(
+        &self,
+        model_input: &[ResolvedArgument],
+        sample_arguments: &[Vec<SerializableIValue>],
+    ) -> Result<(), LightrayModelInputSemanticError> {
+        let argument_count = model_input.len();
+        let mut closest_violations: Option<Vec<LightraySemanticViolation>> = None;
+        for signature in self
+            .signatures()
+            .filter(|signature| signature.len() == argument_count)
+        {
+            for model_baseline in sample_arguments
+                .iter()
+                .filter(|arguments| arguments.len() == argument_count)
+            {
+                let mut violations = Vec::new();
+                for (semantic, (input, baseline)) in
+                    signature.iter().zip(model_input.iter().zip(model_baseline))
+                {
+                    semantic.collect_violations(
+                        &input.path,
+                        &input.value,
+                        Some(baseline),
+                        &mut violations,
+                    );
+                }
+                if violations.is_empty() {
+                    return Ok(());
                 }
+                closest_violations = match closest_violations {
+                    Some(closest) if closest.len() <= violations.len() => Some(closest),
+                    _ => Some(violations),
+                };
             }
         }
-        Ok(())
+        match closest_violations {
+            Some(violations) => Err(LightrayModelInputSemanticError::LightrayVerificationReport(
+                LightrayVerificationReport { violations },
+            )),
+            None => Err(
+                LightrayModelInputSemanticError::LightrayVerificationInputSize(
+                    LightrayVerificationInputSize {
+                        input_length: argument_count as u16,
+                        output_length: self.positional_semantics.len() as u16,
+                    },
+                ),
+            ),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lightray_torch::core::{resolve_arguments, TorchScriptInput};
+    use crate::lightray_torch::literal::{literal_npy, literal_npy_parts};
+    use crate::lightray_torch::schema::{TorchScriptArgument, TorchScriptMethodSchema};
+    use crate::lightray_torch::tensor::encode_npy;
+    use serde_json::Number;
+    use std::io::{Cursor, Write};
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
 
     #[test]
     fn test_different_length_verification() {
@@ -104,12 +632,564 @@ mod tests {
                 SerializableIValue::Int(3),
                 SerializableIValue::Int(3),
             ],
+            ..Default::default()
         };
         let _torchscript_semantic = LightrayModelSemantics {
             positional_semantics: vec![],
+            ..Default::default()
         };
         let serialized = serde_json::to_string(&torchscript_input).unwrap();
         let unserialized: TorchScriptInput = serde_json::from_str(&serialized).unwrap();
         assert_eq!(torchscript_input, unserialized)
     }
+
+    fn violations(
+        semantic: &LightrayIValueSemantic,
+        value: &SerializableIValue,
+        baseline: Option<&SerializableIValue>,
+    ) -> Vec<LightraySemanticViolation> {
+        let mut violations = Vec::new();
+        semantic.collect_violations("$", value, baseline, &mut violations);
+        violations
+    }
+
+    fn violation(
+        path: &str,
+        rule: &str,
+        expected: &str,
+        actual: &str,
+    ) -> LightraySemanticViolation {
+        LightraySemanticViolation {
+            path: path.to_string(),
+            rule: rule.to_string(),
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        }
+    }
+
+    fn tensor(dtype: &str, shape: Vec<i64>) -> SerializableIValue {
+        let data = (0..shape.iter().product::<i64>())
+            .map(serde_json::Number::from)
+            .collect();
+        SerializableIValue::Tensor {
+            dtype: dtype.to_string(),
+            shape,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_tensor_semantics() {
+        let image = tensor("float32", vec![1, 3, 2]);
+        let dtype = LightrayIValueSemantic::TensorDtype("float32".to_string());
+        assert!(violations(&dtype, &image, Some(&image)).is_empty());
+        assert_eq!(
+            violations(&dtype, &tensor("int64", vec![1, 3, 2]), Some(&image)),
+            vec![violation("$", "TensorDtype", "float32", "int64")]
+        );
+        assert_eq!(
+            violations(&dtype, &SerializableIValue::Int(1), None),
+            vec![violation("$", "TensorDtype", "Tensor", "Int")]
+        );
+
+        let shape = LightrayIValueSemantic::TensorShape(vec![None, Some(3), Some(2)]);
+        assert!(violations(&shape, &tensor("float32", vec![4, 3, 2]), Some(&image)).is_empty());
+        assert_eq!(
+            violations(&shape, &tensor("float32", vec![4, 2, 3]), Some(&image)),
+            vec![violation("$", "TensorShape", "[*, 3, 2]", "[4, 2, 3]")]
+        );
+        assert_eq!(
+            violations(&shape, &tensor("float32", vec![3, 2]), Some(&image)).len(),
+            1
+        );
+
+        let size = LightrayIValueSemantic::SizeMatch;
+        assert!(violations(&size, &tensor("float32", vec![1, 3, 2]), Some(&image)).is_empty());
+        assert_eq!(
+            violations(&size, &tensor("float32", vec![2, 3, 2]), Some(&image)),
+            vec![violation(
+                "$",
+                "SizeMatch",
+                "shape [1, 3, 2]",
+                "shape [2, 3, 2]"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_value_semantics() {
+        let range = LightrayIValueSemantic::Range {
+            min: Some(1.0),
+            max: Some(8.0),
+        };
+        let int = SerializableIValue::Int(3);
+        assert!(violations(&range, &int, Some(&int)).is_empty());
+        assert_eq!(
+            violations(&range, &SerializableIValue::Int(9), None),
+            vec![violation("$", "Range", "[1, 8]", "9")]
+        );
+        assert_eq!(
+            violations(&range, &SerializableIValue::Double(f64::NAN), None).len(),
+            1
+        );
+        let at_least: LightrayIValueSemantic =
+            serde_json::from_str(r#"{"Range": {"min": 0.5}}"#).unwrap();
+        assert!(violations(&at_least, &SerializableIValue::Double(1e9), None).is_empty());
+        assert_eq!(
+            violations(&at_least, &SerializableIValue::Double(0.0), None),
+            vec![violation("$", "Range", "[0.5, inf]", "0")]
+        );
+
+        let token = SerializableIValue::Str("<bos>".to_string());
+        let special_token = LightrayIValueSemantic::Regex(LightrayRegex::new("<[a-z]+>").unwrap());
+        assert!(violations(&special_token, &token, Some(&token)).is_empty());
+        let prefixed = SerializableIValue::Str("x<bos>".to_string());
+        assert_eq!(
+            violations(&special_token, &prefixed, None),
+            vec![violation("$", "Regex", "<[a-z]+>", r#"{"Str":"x<bos>"}"#)]
+        );
+        assert!(LightrayRegex::new("(").is_err());
+        assert_eq!(
+            serde_json::from_str::<LightrayIValueSemantic>(r#"{"Regex": "<[a-z]+>"}"#).unwrap(),
+            special_token
+        );
+        assert_eq!(
+            serde_json::to_string(&special_token).unwrap(),
+            r#"{"Regex":"<[a-z]+>"}"#
+        );
+        assert!(serde_json::from_str::<LightrayIValueSemantic>(r#"{"Regex": "("}"#).is_err());
+
+        let one_of = LightrayIValueSemantic::OneOf(vec!["<bos>".to_string(), "<eos>".to_string()]);
+        assert!(violations(&one_of, &token, None).is_empty());
+        assert_eq!(violations(&one_of, &prefixed, None).len(), 1);
+        assert_eq!(
+            violations(&one_of, &int, None),
+            vec![violation("$", "OneOf", "Str", "Int")]
+        );
+
+        let max_length = LightrayIValueSemantic::MaxLength(2);
+        let tokens = SerializableIValue::List(vec![token.clone(), token.clone()]);
+        assert!(violations(&max_length, &tokens, None).is_empty());
+        assert_eq!(
+            violations(&max_length, &token, None),
+            vec![violation(
+                "$",
+                "MaxLength",
+                "length of at most 2",
+                "length 5"
+            )]
+        );
+        assert_eq!(violations(&max_length, &int, None).len(), 1);
+    }
+
+    #[test]
+    fn test_element_semantics() {
+        let token = |token: &str| SerializableIValue::Str(token.to_string());
+        let baseline = SerializableIValue::Tuple(vec![
+            SerializableIValue::List(vec![token("<bos>"), token("<eos>")]),
+            SerializableIValue::Int(3),
+        ]);
+        let semantic = LightrayIValueSemantic::Elements(vec![
+            LightrayIValueSemantic::EachElement(Box::new(LightrayIValueSemantic::AllOf(vec![
+                LightrayIValueSemantic::MaxLength(5),
+                LightrayIValueSemantic::Regex(LightrayRegex::new("[a-z<>]+").unwrap()),
+            ]))),
+            LightrayIValueSemantic::ExactValueMatch,
+        ]);
+        let value = |tokens: Vec<SerializableIValue>, beam_size: i64| {
+            SerializableIValue::Tuple(vec![
+                SerializableIValue::List(tokens),
+                SerializableIValue::Int(beam_size),
+            ])
+        };
+        let paths = |value: &SerializableIValue| -> Vec<(String, String)> {
+            violations(&semantic, value, Some(&baseline))
+                .into_iter()
+                .map(|violation| (violation.path, violation.rule))
+                .collect()
+        };
+        let path = |path: &str, rule: &str| (path.to_string(), rule.to_string());
+        assert!(paths(&value(
+            vec![token("<bos>"), token("call"), token("<eos>")],
+            3
+        ))
+        .is_empty());
+        assert_eq!(
+            paths(&value(vec![token("<bos>"), token("Mother"), token("1")], 4)),
+            vec![
+                path("$[0][1]", "MaxLength"),
+                path("$[0][1]", "Regex"),
+                path("$[0][2]", "Regex"),
+                path("$[1]", "ExactValueMatch"),
+            ]
+        );
+        assert_eq!(
+            paths(&value(vec![token("<bos>"), SerializableIValue::Int(1)], 3)),
+            vec![path("$[0][1]", "TypeMatch")]
+        );
+        assert_eq!(
+            paths(&SerializableIValue::Tuple(vec![SerializableIValue::List(
+                vec![]
+            )])),
+            vec![path("$", "Elements")]
+        );
+    }
+
+    #[test]
+    fn test_verification_report() {
+        let semantics = LightrayModelSemantics {
+            positional_semantics: vec![
+                LightrayIValueSemantic::EachElement(Box::new(LightrayIValueSemantic::OneOf(vec![
+                    "<bos>".to_string(),
+                    "<eos>".to_string(),
+                ]))),
+                LightrayIValueSemantic::Range {
+                    min: Some(1.0),
+                    max: None,
+                },
+                LightrayIValueSemantic::ExactValueMatch,
+            ],
+            ..Default::default()
+        };
+        let baseline = sample(&["<bos>", "<eos>"], 3);
+        let baseline_arguments = vec![arguments(&baseline)];
+        assert!(semantics
+            .verify_semantics(&resolved(&baseline), &baseline_arguments)
+            .is_ok());
+        let mut input = sample(&["<bos>", "call", "<eos>"], 0);
+        input.positional_arguments[2] = SerializableIValue::Double(3.0);
+        match semantics.verify_semantics(&resolved(&input), &baseline_arguments) {
+            Err(LightrayModelInputSemanticError::LightrayVerificationReport(report)) => {
+                assert_eq!(
+                    report.violations,
+                    vec![
+                        violation(
+                            "$.positional_arguments[0][1]",
+                            "OneOf",
+                            r#"one of ["<bos>", "<eos>"]"#,
+                            r#"{"Str":"call"}"#
+                        ),
+                        violation("$.positional_arguments[1]", "Range", "[1, inf]", "0"),
+                        violation("$.positional_arguments[2]", "TypeMatch", "Int", "Double"),
+                    ]
+                );
+                assert_eq!(
+                    report.to_string(),
+                    "LightrayModel input violates its semantics: $.positional_arguments[0][1] \
+                     (OneOf): expected one of [\"<bos>\", \"<eos>\"], got {\"Str\":\"call\"}; \
+                     $.positional_arguments[1] (Range): expected [1, inf], got 0; \
+                     $.positional_arguments[2] (TypeMatch): expected Int, got Double"
+                );
+            }
+            _ => panic!("the input violates three rules"),
+        }
+    }
+
+    #[test]
+    fn test_any_sample_and_signature() {
+        let semantics = LightrayModelSemantics {
+            positional_semantics: vec![
+                LightrayIValueSemantic::SizeMatch,
+                LightrayIValueSemantic::TypeMatch,
+                LightrayIValueSemantic::ExactValueMatch,
+            ],
+            signature_variants: vec![vec![LightrayIValueSemantic::SizeMatch]],
+            ..Default::default()
+        };
+        let mut single_argument = sample(&["<bos>"], 3);
+        single_argument.positional_arguments.truncate(1);
+        let samples: Vec<Vec<SerializableIValue>> = [
+            sample(&["<bos>", "<eos>"], 3),
+            sample(&["<bos>", "call", "<eos>"], 3),
+            single_argument,
+        ]
+        .iter()
+        .map(arguments)
+        .collect();
+        assert_eq!(semantics.unsampled_signature(&samples), None);
+        assert_eq!(semantics.unsampled_signature(&samples[..2]), Some(1));
+
+        for tokens in &[&["<s>", "</s>"][..], &["<s>", "mom", "</s>"], &["mom"]] {
+            let mut input = sample(tokens, 5);
+            if tokens.len() == 1 {
+                input.positional_arguments.truncate(1);
+            }
+            assert!(semantics
+                .verify_semantics(&resolved(&input), &samples)
+                .is_ok());
+        }
+        let four_tokens = sample(&["<s>", "call", "mom", "</s>"], 5);
+        match semantics.verify_semantics(&resolved(&four_tokens), &samples) {
+            Err(LightrayModelInputSemanticError::LightrayVerificationReport(report)) => {
+                assert_eq!(
+                    report.violations,
+                    vec![violation(
+                        "$.positional_arguments[0]",
+                        "SizeMatch",
+                        "2 elements",
+                        "4 elements"
+                    )]
+                );
+            }
+            _ => panic!("no sample passes four tokens"),
+        }
+        let mut two_arguments = sample(&["<bos>"], 3);
+        two_arguments.positional_arguments.truncate(2);
+        match semantics.verify_semantics(&resolved(&two_arguments), &samples) {
+            Err(LightrayModelInputSemanticError::LightrayVerificationInputSize(error)) => {
+                assert_eq!(
+                    LightrayVerificationReport::from(error).violations,
+                    vec![violation(
+                        "$.positional_arguments",
+                        "ArgumentCount",
+                        "3 arguments",
+                        "2 arguments"
+                    )]
+                );
+            }
+            _ => panic!("no signature takes two arguments"),
+        }
+    }
+
+    fn resolved(input: &TorchScriptInput) -> Vec<ResolvedArgument<'_>> {
+        resolve_arguments(input, None).unwrap()
+    }
+
+    fn arguments(input: &TorchScriptInput) -> Vec<SerializableIValue> {
+        resolved(input)
+            .into_iter()
+            .map(|argument| argument.value.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_resolved_arguments() {
+        let argument = |name: &str, default_value: Option<&str>| TorchScriptArgument {
+            name: name.to_string(),
+            type_name: "Tensor".to_string(),
+            default_value: default_value.map(str::to_string),
+        };
+        let schema = TorchScriptMethodSchema {
+            name: "forward".to_string(),
+            arguments: vec![
+                argument("features", None),
+                argument("mask", None),
+                argument("beam_size", Some("3")),
+                argument("top_k", Some("5")),
+            ],
+            return_type: None,
+        };
+        let semantics = LightrayModelSemantics {
+            positional_semantics: vec![
+                LightrayIValueSemantic::TensorDtype("float32".to_string()),
+                LightrayIValueSemantic::TensorDtype("bool".to_string()),
+                LightrayIValueSemantic::Range {
+                    min: None,
+                    max: Some(8.0),
+                },
+                LightrayIValueSemantic::ExactValueMatch,
+            ],
+            ..Default::default()
+        };
+        let npz = |mask_dtype: &str| {
+            let mut writer = ZipWriter::new(Cursor::new(vec![]));
+            let features = literal_npy("float32", &[1], &[Number::from(1)]).unwrap();
+            let mask = literal_npy(mask_dtype, &[1], &[Number::from(1)]).unwrap();
+            writer
+                .start_file("arr_0.npy", FileOptions::default())
+                .unwrap();
+            writer.write_all(&features).unwrap();
+            writer
+                .start_file("mask.npy", FileOptions::default())
+                .unwrap();
+            writer.write_all(&mask).unwrap();
+            SerializableIValue::TensorNPZBase64(base64::encode(
+                &writer.finish().unwrap().into_inner(),
+            ))
+        };
+        let input = |mask_dtype: &str, beam_size: i64| {
+            let mut input = TorchScriptInput {
+                positional_arguments: vec![npz(mask_dtype)],
+                ..Default::default()
+            };
+            input
+                .keyword_arguments
+                .insert("beam_size".to_string(), SerializableIValue::Int(beam_size));
+            input
+                .keyword_arguments
+                .insert("top_k".to_string(), SerializableIValue::Int(5));
+            input
+        };
+        let baseline = input("bool", 3);
+        let baseline_arguments = resolve_arguments(&baseline, Some(&schema)).unwrap();
+        assert_eq!(
+            baseline_arguments
+                .iter()
+                .map(|argument| argument.path.as_str())
+                .collect::<Vec<&str>>(),
+            vec![
+                "$.positional_arguments[0].arr_0",
+                "$.positional_arguments[0].mask",
+                "$.keyword_arguments.beam_size",
+                "$.keyword_arguments.top_k",
+            ]
+        );
+        let sample_arguments: Vec<Vec<SerializableIValue>> = vec![baseline_arguments
+            .into_iter()
+            .map(|argument| argument.value.into_owned())
+            .collect()];
+
+        let valid = input("bool", 8);
+        let valid = resolve_arguments(&valid, Some(&schema)).unwrap();
+        assert!(semantics
+            .verify_semantics(&valid, &sample_arguments)
+            .is_ok());
+        let invalid = input("int32", 9);
+        let invalid = resolve_arguments(&invalid, Some(&schema)).unwrap();
+        match semantics.verify_semantics(&invalid, &sample_arguments) {
+            Err(LightrayModelInputSemanticError::LightrayVerificationReport(report)) => {
+                assert_eq!(
+                    report
+                        .violations
+                        .into_iter()
+                        .map(|violation| (violation.path, violation.rule))
+                        .collect::<Vec<(String, String)>>(),
+                    vec![
+                        (
+                            "$.positional_arguments[0].mask".to_string(),
+                            "TensorDtype".to_string()
+                        ),
+                        (
+                            "$.keyword_arguments.beam_size".to_string(),
+                            "Range".to_string()
+                        ),
+                    ]
+                );
+            }
+            _ => panic!("the mask array and the beam size violate their semantics"),
+        }
+    }
+
+    fn sample(tokens: &[&str], beam_size: i64) -> TorchScriptInput {
+        TorchScriptInput {
+            positional_arguments: vec![
+                SerializableIValue::List(
+                    tokens
+                        .iter()
+                        .map(|token| SerializableIValue::Str(token.to_string()))
+                        .collect(),
+                ),
+                SerializableIValue::Int(beam_size),
+                SerializableIValue::Int(3),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_output_semantics() {
+        let semantics = LightrayModelSemantics {
+            output_semantics: Some(LightrayIValueSemantic::AllOf(vec![
+                LightrayIValueSemantic::Type("Tensor".to_string()),
+                LightrayIValueSemantic::TensorShape(vec![None, Some(2)]),
+                LightrayIValueSemantic::Finite,
+                LightrayIValueSemantic::Range {
+                    min: Some(0.0),
+                    max: Some(1.0),
+                },
+            ])),
+            ..Default::default()
+        };
+        let (header, _) =
+            literal_npy_parts("float32", &[1, 2], &[Number::from(0), Number::from(0)]).unwrap();
+        let probabilities = |first: f32| {
+            let mut data = first.to_le_bytes().to_vec();
+            data.extend_from_slice(&0.5f32.to_le_bytes());
+            SerializableIValue::TensorNPY(encode_npy(&header, &data).unwrap())
+        };
+        assert!(semantics.verify_output(&probabilities(0.5)).is_ok());
+        assert_eq!(
+            semantics
+                .verify_output(&probabilities(f32::NAN))
+                .unwrap_err()
+                .violations,
+            vec![
+                violation("$", "Finite", "finite values", "1 of 2 elements, e.g. NaN"),
+                violation("$", "Range", "[0, 1]", "1 of 2 elements, e.g. NaN"),
+            ]
+        );
+        assert_eq!(
+            semantics
+                .verify_output(&probabilities(1.5))
+                .unwrap_err()
+                .violations,
+            vec![violation(
+                "$",
+                "Range",
+                "[0, 1]",
+                "1 of 2 elements, e.g. 1.5"
+            )]
+        );
+        let rules: Vec<String> = semantics
+            .verify_output(&SerializableIValue::Int(1))
+            .unwrap_err()
+            .violations
+            .into_iter()
+            .map(|violation| violation.rule)
+            .collect();
+        assert_eq!(rules, vec!["Type", "TensorShape", "Finite"]);
+
+        assert!(LightrayModelSemantics::default()
+            .verify_output(&probabilities(f32::NAN))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_derive_from_samples() {
+        let samples = vec![
+            arguments(&sample(&["<bos>", "call", "<eos>"], 3)),
+            arguments(&sample(&["<bos>", "mom", "<eos>"], 5)),
+        ];
+        assert_eq!(
+            LightrayModelSemantics::derive_from_samples(&samples),
+            Some(LightrayModelSemantics {
+                positional_semantics: vec![
+                    LightrayIValueSemantic::SizeMatch,
+                    LightrayIValueSemantic::TypeMatch,
+                    LightrayIValueSemantic::ExactValueMatch,
+                ],
+                ..Default::default()
+            })
+        );
+
+        let varying_sizes = vec![
+            arguments(&sample(&["<bos>", "<eos>"], 3)),
+            arguments(&sample(&["<bos>"], 3)),
+        ];
+        assert_eq!(
+            LightrayModelSemantics::derive_from_samples(&varying_sizes)
+                .unwrap()
+                .positional_semantics[0],
+            LightrayIValueSemantic::TypeMatch
+        );
+
+        let single_sample = vec![arguments(&sample(&["<bos>"], 3))];
+        assert_eq!(
+            LightrayModelSemantics::derive_from_samples(&single_sample)
+                .unwrap()
+                .positional_semantics,
+            vec![LightrayIValueSemantic::TypeMatch; 3]
+        );
+
+        let mut fewer_arguments = arguments(&sample(&["<bos>"], 3));
+        fewer_arguments.pop();
+        assert_eq!(
+            LightrayModelSemantics::derive_from_samples(&[
+                arguments(&sample(&["<bos>"], 3)),
+                fewer_arguments
+            ]),
+            None
+        );
+        assert_eq!(LightrayModelSemantics::derive_from_samples(&[]), None);
+    }
 }
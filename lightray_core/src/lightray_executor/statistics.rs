@@ -9,4 +9,8 @@ pub struct LightrayModelExecutionStatistic {
     pub start_execution_time: SystemTime,
     /// SystemTime of when object execution ended
     pub end_execution_time: SystemTime,
+    /// Number of examples executed together with this one
+    pub batch_size: usize,
+    /// Whether the result was served from the response cache
+    pub cache_hit: bool,
 }
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// When a model verifies inputs against the semantics of the method they call.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum LightrayVerificationPolicy {
+    /// Inputs are executed without verification.
+    Off,
+    /// Every input is verified and inputs violating the semantics are rejected.
+    Always,
+    /// Verifies the percentage of the inputs, spread evenly over them, and rejects the
+    /// violating ones.
+    Sampled(f64),
+    /// Every input is verified, but violations are only counted in `shadow_violations` of the
+    /// model description and the input is executed.
+    Shadow,
+}
+
+/// Counts the inputs of a model to pick those a `Sampled` policy verifies.
+#[derive(Debug, Default)]
+pub struct LightrayVerificationSampler {
+    inputs: AtomicU64,
+}
+impl LightrayVerificationSampler {
+    /// Whether to verify the next input when verifying `percent` percent of them. Input `n`
+    /// is verified if it raises the number of inputs to verify so far, `n * percent / 100`
+    /// rounded down.
+    pub fn sample(&self, percent: f64) -> bool {
+        let input = self.inputs.fetch_add(1, Ordering::Relaxed) as f64;
+        ((input + 1.0) * percent / 100.0).floor() > (input * percent / 100.0).floor()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampled_verification() {
+        let count_sampled = |percent: f64| {
+            let sampler = LightrayVerificationSampler::default();
+            (0..1000).filter(|_| sampler.sample(percent)).count()
+        };
+        assert_eq!(count_sampled(0.0), 0);
+        assert_eq!(count_sampled(2.5), 25);
+        assert_eq!(count_sampled(10.0), 100);
+        assert_eq!(count_sampled(100.0), 1000);
+
+        let sampler = LightrayVerificationSampler::default();
+        let sampled: Vec<bool> = (0..4).map(|_| sampler.sample(50.0)).collect();
+        assert_eq!(sampled, vec![false, true, false, true]);
+    }
+
+    #[test]
+    fn test_verification_policy_json() {
+        assert_eq!(
+            serde_json::from_str::<LightrayVerificationPolicy>(r#"{"Sampled": 10}"#).unwrap(),
+            LightrayVerificationPolicy::Sampled(10.0)
+        );
+        assert_eq!(
+            serde_json::from_str::<LightrayVerificationPolicy>(r#""Shadow""#).unwrap(),
+            LightrayVerificationPolicy::Shadow
+        );
+    }
+}
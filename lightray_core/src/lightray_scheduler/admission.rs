@@ -0,0 +1,154 @@
+use crate::lightray_executor::model::LightrayModelId;
+use crate::lightray_scheduler::errors::{LightrayQueueLimit, LightraySchedulerError};
+use crate::lightray_scheduler::queue::ChannelBasedWork;
+use crate::lightray_scheduler::wakeup::{LightrayShutdownGate, LightrayWorkerWakeup};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+/// Maximum number of work items a queue holds before it sheds load. `None` leaves the
+/// corresponding depth unbounded.
+///
+/// Every queue takes its limits through `with_queue_limits`. Work beyond the limits is
+/// rejected with `QueueFull` instead of being queued.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct LightrayQueueLimits {
+    pub max_queue_depth: Option<usize>,
+    pub max_queue_depth_per_model: Option<usize>,
+}
+
+#[derive(Default)]
+struct QueueDepth {
+    total: usize,
+    per_model: HashMap<LightrayModelId, usize>,
+}
+
+/// Tracks how much work is queued, globally and per model, and rejects work that would
+/// exceed the configured limits.
+///
+/// Every successful `acquire` must be paired with a `release` once the work leaves the
+/// queue, whether it was executed, expired or abandoned.
+pub(crate) struct LightrayAdmissionControl {
+    limits: LightrayQueueLimits,
+    depth: Mutex<QueueDepth>,
+}
+
+impl LightrayAdmissionControl {
+    pub(crate) fn new(limits: LightrayQueueLimits) -> LightrayAdmissionControl {
+        LightrayAdmissionControl {
+            limits,
+            depth: Mutex::new(QueueDepth::default()),
+        }
+    }
+
+    fn lock_depth(&self) -> MutexGuard<'_, QueueDepth> {
+        match self.depth.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub(crate) fn acquire(&self, model_id: &LightrayModelId) -> Result<(), LightraySchedulerError> {
+        let mut depth = self.lock_depth();
+        if let Some(max_queue_depth) = self.limits.max_queue_depth {
+            if depth.total >= max_queue_depth {
+                return Err(LightraySchedulerError::QueueFull(
+                    LightrayQueueLimit::Global,
+                ));
+            }
+        }
+        let model_depth = depth.per_model.get(model_id).copied().unwrap_or(0);
+        if let Some(max_queue_depth_per_model) = self.limits.max_queue_depth_per_model {
+            if model_depth >= max_queue_depth_per_model {
+                return Err(LightraySchedulerError::QueueFull(LightrayQueueLimit::Model));
+            }
+        }
+        depth.total += 1;
+        depth.per_model.insert(*model_id, model_depth + 1);
+        Ok(())
+    }
+
+    /// Admission path shared by the queues: reserves a slot for `work`, hands it to `push`
+    /// unless `shutdown_gate` is closed and wakes up one worker. The slot is released again
+    /// when the queue is shutting down.
+    pub(crate) fn admit<P: FnOnce(ChannelBasedWork)>(
+        &self,
+        work: ChannelBasedWork,
+        shutdown_gate: &LightrayShutdownGate,
+        worker_wakeup: &LightrayWorkerWakeup,
+        push: P,
+    ) -> Result<(), LightraySchedulerError> {
+        let model_id = work.model_id;
+        self.acquire(&model_id)?;
+        if shutdown_gate.admit(|| push(work)).is_none() {
+            self.release(&model_id);
+            return Err(LightraySchedulerError::SchedulerShutdown);
+        }
+        worker_wakeup.notify_one();
+        Ok(())
+    }
+
+    pub(crate) fn release(&self, model_id: &LightrayModelId) {
+        let mut depth = self.lock_depth();
+        depth.total = depth.total.saturating_sub(1);
+        let model_depth = depth.per_model.get(model_id).copied().unwrap_or(0);
+        if model_depth <= 1 {
+            depth.per_model.remove(model_id);
+        } else {
+            depth.per_model.insert(*model_id, model_depth - 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn model(model_version: u16) -> LightrayModelId {
+        LightrayModelId {
+            model_id: Uuid::nil(),
+            model_version,
+        }
+    }
+
+    #[test]
+    fn test_unbounded_by_default() {
+        let admission_control = LightrayAdmissionControl::new(LightrayQueueLimits::default());
+        for _ in 0..1000 {
+            assert!(admission_control.acquire(&model(0)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_per_model_limit() {
+        let admission_control = LightrayAdmissionControl::new(LightrayQueueLimits {
+            max_queue_depth: None,
+            max_queue_depth_per_model: Some(2),
+        });
+        assert!(admission_control.acquire(&model(0)).is_ok());
+        assert!(admission_control.acquire(&model(0)).is_ok());
+        match admission_control.acquire(&model(0)) {
+            Err(LightraySchedulerError::QueueFull(LightrayQueueLimit::Model)) => {}
+            _ => panic!("expected a full model queue"),
+        }
+        assert!(admission_control.acquire(&model(1)).is_ok());
+        admission_control.release(&model(0));
+        assert!(admission_control.acquire(&model(0)).is_ok());
+    }
+
+    #[test]
+    fn test_global_limit() {
+        let admission_control = LightrayAdmissionControl::new(LightrayQueueLimits {
+            max_queue_depth: Some(2),
+            max_queue_depth_per_model: Some(2),
+        });
+        assert!(admission_control.acquire(&model(0)).is_ok());
+        assert!(admission_control.acquire(&model(1)).is_ok());
+        match admission_control.acquire(&model(2)) {
+            Err(LightraySchedulerError::QueueFull(LightrayQueueLimit::Global)) => {}
+            _ => panic!("expected a full queue"),
+        }
+        admission_control.release(&model(1));
+        assert!(admission_control.acquire(&model(2)).is_ok());
+    }
+}
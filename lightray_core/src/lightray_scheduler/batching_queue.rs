@@ -0,0 +1,353 @@
+use crate::lightray_executor::executor::LightrayExecutor;
+use crate::lightray_executor::model::LightrayModelId;
+use crate::lightray_scheduler::admission::{LightrayAdmissionControl, LightrayQueueLimits};
+use crate::lightray_scheduler::queue::{
+    enqueue_channel_based_work, ChannelBasedWork, LightrayScheduledExecutionResult,
+    LightrayScheduledWorkResult, LightrayWorkOptions, LightrayWorkQueue,
+};
+use crate::lightray_scheduler::wakeup::{
+    LightrayShutdownGate, LightrayWorkerIdlePolicy, LightrayWorkerWakeup,
+};
+use crate::lightray_torch::core::TorchScriptInput;
+use async_trait::async_trait;
+use crossbeam_queue::SegQueue;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot::Sender;
+
+struct PendingBatch {
+    opened_at: Instant,
+    work: Vec<ChannelBasedWork>,
+}
+
+/// Work queue that coalesces queued work for the same model into batches.
+///
+/// A batch is dispatched once it holds `max_batch_size` items or once its oldest item has
+/// waited `max_batch_latency`. Work for models that are not batchable is executed right away.
+/// Batches are shared by all workers, so work for a model is coalesced whichever worker
+/// dequeues it.
+pub struct LightrayBatchingWorkQueue<T: LightrayExecutor> {
+    worker_queue: SegQueue<ChannelBasedWork>,
+    pending_batches: Mutex<HashMap<LightrayModelId, PendingBatch>>,
+    worker_wakeup: LightrayWorkerWakeup,
+    shutdown_gate: LightrayShutdownGate,
+    admission_control: LightrayAdmissionControl,
+    worker_executor: T,
+    verify_model_input: bool,
+    max_batch_size: usize,
+    max_batch_latency: Duration,
+}
+
+impl<T: LightrayExecutor> LightrayBatchingWorkQueue<T> {
+    pub fn new(
+        worker_executor: T,
+        verify_model_input: bool,
+        max_batch_size: usize,
+        max_batch_latency: Duration,
+    ) -> io::Result<LightrayBatchingWorkQueue<T>> {
+        if max_batch_size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "max_batch_size must be positive",
+            ));
+        }
+        Ok(LightrayBatchingWorkQueue::<T> {
+            worker_queue: SegQueue::new(),
+            pending_batches: Mutex::new(HashMap::new()),
+            worker_wakeup: LightrayWorkerWakeup::new(LightrayWorkerIdlePolicy::default()),
+            shutdown_gate: LightrayShutdownGate::default(),
+            admission_control: LightrayAdmissionControl::new(LightrayQueueLimits::default()),
+            worker_executor,
+            verify_model_input,
+            max_batch_size,
+            max_batch_latency,
+        })
+    }
+
+    pub fn with_queue_limits(
+        mut self,
+        queue_limits: LightrayQueueLimits,
+    ) -> LightrayBatchingWorkQueue<T> {
+        self.admission_control = LightrayAdmissionControl::new(queue_limits);
+        self
+    }
+
+    fn lock_pending_batches(&self) -> MutexGuard<'_, HashMap<LightrayModelId, PendingBatch>> {
+        match self.pending_batches.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Dispatches the batches that are due, or every pending batch if `take_all` is set.
+    /// Returns whether any batch was dispatched.
+    fn dispatch_due_batches(&self, take_all: bool) -> bool {
+        // Batches are dispatched outside of the lock so other workers keep batching.
+        let due_batches = self.take_due_batches(take_all);
+        let dispatched = !due_batches.is_empty();
+        for (model_id, due_batch) in due_batches {
+            self.dispatch(&model_id, due_batch.work);
+        }
+        dispatched
+    }
+
+    /// Adds `work` to the pending batch of its model and returns the batch once it is full.
+    fn add_to_batch(&self, work: ChannelBasedWork) -> Option<PendingBatch> {
+        let model_id = work.model_id;
+        let mut pending_batches = self.lock_pending_batches();
+        let pending_batch = pending_batches
+            .entry(model_id)
+            .or_insert_with(|| PendingBatch {
+                opened_at: Instant::now(),
+                work: Vec::with_capacity(self.max_batch_size),
+            });
+        pending_batch.work.push(work);
+        if pending_batch.work.len() >= self.max_batch_size {
+            pending_batches.remove(&model_id)
+        } else {
+            None
+        }
+    }
+
+    /// Removes the batches that waited `max_batch_latency`, or all of them if `take_all`.
+    fn take_due_batches(&self, take_all: bool) -> Vec<(LightrayModelId, PendingBatch)> {
+        let mut pending_batches = self.lock_pending_batches();
+        let due_models: Vec<LightrayModelId> = pending_batches
+            .iter()
+            .filter(|(_, pending_batch)| {
+                take_all || pending_batch.opened_at.elapsed() >= self.max_batch_latency
+            })
+            .map(|(model_id, _)| *model_id)
+            .collect();
+        due_models
+            .into_iter()
+            .filter_map(|model_id| {
+                pending_batches
+                    .remove(&model_id)
+                    .map(|pending_batch| (model_id, pending_batch))
+            })
+            .collect()
+    }
+
+    fn dispatch(&self, model_id: &LightrayModelId, work: Vec<ChannelBasedWork>) {
+        for _ in &work {
+            self.admission_control.release(model_id);
+        }
+        let (payloads, senders): (
+            Vec<TorchScriptInput>,
+            Vec<Sender<LightrayScheduledWorkResult>>,
+        ) = work
+            .into_iter()
+            .filter_map(ChannelBasedWork::into_live)
+            .map(|value| (value.payload, value.sender))
+            .unzip();
+        if payloads.is_empty() {
+            return;
+        }
+        let executed_values =
+            self.worker_executor
+                .execute_batched(model_id, &payloads, self.verify_model_input);
+        for (sender, executed_value) in senders.into_iter().zip(executed_values) {
+            let _x = sender.send(Ok(executed_value));
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl<T: LightrayExecutor> LightrayWorkQueue<T> for LightrayBatchingWorkQueue<T> {
+    async fn enqueue_with_options(
+        &self,
+        payload: TorchScriptInput,
+        model_id: LightrayModelId,
+        options: LightrayWorkOptions,
+    ) -> LightrayScheduledExecutionResult {
+        enqueue_channel_based_work(
+            payload,
+            model_id,
+            options,
+            |work| {
+                self.admission_control.admit(
+                    work,
+                    &self.shutdown_gate,
+                    &self.worker_wakeup,
+                    |work| self.worker_queue.push(work),
+                )
+            },
+            || self.worker_queue.len(),
+        )
+        .await
+    }
+    fn worker_loop(&self) {
+        loop {
+            let is_shut_down = self.shutdown_gate.is_closed();
+            let mut made_progress = false;
+            while let Ok(value) = self.worker_queue.pop() {
+                made_progress = true;
+                let model_id = value.model_id;
+                if !self.worker_executor.is_batchable(&model_id) {
+                    self.dispatch(&model_id, vec![value]);
+                } else if let Some(full_batch) = self.add_to_batch(value) {
+                    self.dispatch(&model_id, full_batch.work);
+                }
+                // Draining can take a while, so batches that became due meanwhile go out now.
+                self.dispatch_due_batches(false);
+            }
+
+            if self.dispatch_due_batches(is_shut_down) {
+                made_progress = true;
+            }
+            if is_shut_down {
+                return;
+            }
+
+            if !made_progress {
+                // Park until new work arrives or the oldest pending batch is due.
+                let next_batch_deadline = self
+                    .lock_pending_batches()
+                    .values()
+                    .map(|pending_batch| {
+                        self.max_batch_latency
+                            .checked_sub(pending_batch.opened_at.elapsed())
+                            .unwrap_or_default()
+                    })
+                    .min();
+                self.worker_wakeup.wait(
+                    || !self.worker_queue.is_empty() || self.shutdown_gate.is_closed(),
+                    next_batch_deadline,
+                );
+            }
+        }
+    }
+    fn shutdown(&self) {
+        self.shutdown_gate.close();
+        self.worker_wakeup.notify_all();
+    }
+    fn get_executor(&self) -> &T {
+        &self.worker_executor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lightray_executor::errors::{
+        LightrayModelExecutionError, LightrayRegistrationError,
+    };
+    use crate::lightray_executor::executor::LightrayExecutorResult;
+    use crate::lightray_executor::model::{LightrayModel, LightrayModelDescription};
+    use crate::lightray_scheduler::queue::LightrayWorkOptions;
+    use std::sync::Arc;
+    use std::thread;
+    use tokio::sync::oneshot::{channel, Receiver};
+    use uuid::Uuid;
+
+    const BATCHABLE_MODEL: LightrayModelId = LightrayModelId {
+        model_id: Uuid::nil(),
+        model_version: 1,
+    };
+    const UNBATCHABLE_MODEL: LightrayModelId = LightrayModelId {
+        model_id: Uuid::nil(),
+        model_version: 0,
+    };
+
+    /// Executor whose calls take a few milliseconds and only batch `BATCHABLE_MODEL`.
+    struct SlowExecutor;
+
+    impl LightrayExecutor for SlowExecutor {
+        fn execute(
+            &self,
+            _model_id: &LightrayModelId,
+            _example: &TorchScriptInput,
+            _do_verification: bool,
+        ) -> LightrayExecutorResult {
+            thread::sleep(Duration::from_millis(2));
+            Err(LightrayModelExecutionError::MissingModel)
+        }
+        fn execute_batched(
+            &self,
+            model_id: &LightrayModelId,
+            examples: &[TorchScriptInput],
+            do_verification: bool,
+        ) -> Vec<LightrayExecutorResult> {
+            examples
+                .iter()
+                .map(|example| self.execute(model_id, example, do_verification))
+                .collect()
+        }
+        fn is_batchable(&self, model_id: &LightrayModelId) -> bool {
+            *model_id == BATCHABLE_MODEL
+        }
+        fn describe_model(
+            &self,
+            _model_id: &LightrayModelId,
+        ) -> Result<LightrayModelDescription, LightrayModelExecutionError> {
+            Err(LightrayModelExecutionError::MissingModel)
+        }
+        fn register_model(
+            &self,
+            _model: LightrayModel,
+        ) -> Result<LightrayModelId, LightrayRegistrationError> {
+            Err(LightrayRegistrationError::MissingModel)
+        }
+        fn delete_model(
+            &self,
+            _model_id: LightrayModelId,
+        ) -> Result<(), LightrayRegistrationError> {
+            Err(LightrayRegistrationError::MissingModel)
+        }
+    }
+
+    fn work(
+        model_id: LightrayModelId,
+    ) -> (ChannelBasedWork, Receiver<LightrayScheduledWorkResult>) {
+        let (sender, receiver) = channel();
+        let work = ChannelBasedWork {
+            payload: TorchScriptInput::default(),
+            model_id,
+            options: LightrayWorkOptions::default(),
+            sender,
+        };
+        (work, receiver)
+    }
+
+    #[test]
+    fn test_zero_batch_size_is_rejected() {
+        assert!(
+            LightrayBatchingWorkQueue::new(SlowExecutor, false, 0, Duration::from_millis(5))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_partial_batch_is_dispatched_under_unbatchable_load() {
+        let max_batch_latency = Duration::from_millis(20);
+        let queue = Arc::new(
+            LightrayBatchingWorkQueue::new(SlowExecutor, false, 8, max_batch_latency).unwrap(),
+        );
+        let worker_queue = queue.clone();
+        let worker = thread::spawn(move || worker_queue.worker_loop());
+
+        let (batched_work, mut batched_receiver) = work(BATCHABLE_MODEL);
+        queue.worker_queue.push(batched_work);
+        let started_at = Instant::now();
+        let mut unbatchable_receivers = Vec::new();
+        // Work for the unbatchable model arrives faster than it is executed, so the worker
+        // never finds the queue empty.
+        while batched_receiver.try_recv().is_err() {
+            assert!(
+                started_at.elapsed() < Duration::from_secs(1),
+                "partial batch was not dispatched under load"
+            );
+            let (unbatchable_work, receiver) = work(UNBATCHABLE_MODEL);
+            queue.worker_queue.push(unbatchable_work);
+            unbatchable_receivers.push(receiver);
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(!queue.worker_queue.is_empty());
+
+        queue.shutdown();
+        worker.join().unwrap();
+    }
+}
@@ -0,0 +1,72 @@
+use crate::lightray_executor::executor::LightrayExecutor;
+use crate::lightray_executor::model::LightrayModelId;
+use crate::lightray_scheduler::admission::LightrayQueueLimits;
+use crate::lightray_scheduler::batching_queue::LightrayBatchingWorkQueue;
+use crate::lightray_scheduler::priority_queue::LightrayPriorityWorkQueue;
+use crate::lightray_scheduler::queue::{
+    LightrayScheduledExecutionResult, LightrayWorkOptions, LightrayWorkQueue,
+};
+use crate::lightray_torch::core::TorchScriptInput;
+use async_trait::async_trait;
+
+/// Work queue chosen when the server starts.
+///
+/// The priority queue serves interactive work first but executes every input on its own, the
+/// batching queue coalesces work for batchable models but ignores priorities.
+pub enum LightrayConfiguredWorkQueue<T: LightrayExecutor> {
+    Priority(Box<LightrayPriorityWorkQueue<T>>),
+    Batching(Box<LightrayBatchingWorkQueue<T>>),
+}
+
+impl<T: LightrayExecutor> LightrayConfiguredWorkQueue<T> {
+    pub fn with_queue_limits(
+        self,
+        queue_limits: LightrayQueueLimits,
+    ) -> LightrayConfiguredWorkQueue<T> {
+        match self {
+            LightrayConfiguredWorkQueue::Priority(queue) => LightrayConfiguredWorkQueue::Priority(
+                Box::new(queue.with_queue_limits(queue_limits)),
+            ),
+            LightrayConfiguredWorkQueue::Batching(queue) => LightrayConfiguredWorkQueue::Batching(
+                Box::new(queue.with_queue_limits(queue_limits)),
+            ),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl<T: LightrayExecutor> LightrayWorkQueue<T> for LightrayConfiguredWorkQueue<T> {
+    async fn enqueue_with_options(
+        &self,
+        payload: TorchScriptInput,
+        model_id: LightrayModelId,
+        options: LightrayWorkOptions,
+    ) -> LightrayScheduledExecutionResult {
+        match self {
+            LightrayConfiguredWorkQueue::Priority(queue) => {
+                queue.enqueue_with_options(payload, model_id, options).await
+            }
+            LightrayConfiguredWorkQueue::Batching(queue) => {
+                queue.enqueue_with_options(payload, model_id, options).await
+            }
+        }
+    }
+    fn worker_loop(&self) {
+        match self {
+            LightrayConfiguredWorkQueue::Priority(queue) => queue.worker_loop(),
+            LightrayConfiguredWorkQueue::Batching(queue) => queue.worker_loop(),
+        }
+    }
+    fn shutdown(&self) {
+        match self {
+            LightrayConfiguredWorkQueue::Priority(queue) => queue.shutdown(),
+            LightrayConfiguredWorkQueue::Batching(queue) => queue.shutdown(),
+        }
+    }
+    fn get_executor(&self) -> &T {
+        match self {
+            LightrayConfiguredWorkQueue::Priority(queue) => queue.get_executor(),
+            LightrayConfiguredWorkQueue::Batching(queue) => queue.get_executor(),
+        }
+    }
+}
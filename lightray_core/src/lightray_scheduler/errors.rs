@@ -1,8 +1,21 @@
 use crate::lightray_executor::errors::LightrayModelExecutionError;
+
+/// Queue depth limit that rejected work.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum LightrayQueueLimit {
+    /// Too much work is queued for the requested model.
+    Model,
+    /// Too much work is queued across all models.
+    Global,
+}
+
 #[derive(Debug)]
 pub enum LightraySchedulerError {
     LightrayModelExecutionError(LightrayModelExecutionError),
     SchedulerError,
+    SchedulerShutdown,
+    DeadlineExceeded,
+    QueueFull(LightrayQueueLimit),
 }
 
 impl From<LightrayModelExecutionError> for LightraySchedulerError {
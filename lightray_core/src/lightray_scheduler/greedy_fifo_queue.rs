@@ -1,96 +1,112 @@
-use crate::lightray_executor::executor::{LightrayExecutor, LightrayExecutorResult};
+use crate::lightray_executor::executor::LightrayExecutor;
 use crate::lightray_executor::model::LightrayModelId;
-use crate::lightray_scheduler::errors::LightraySchedulerError;
-use crate::lightray_scheduler::queue::{LightrayScheduledExecutionResult, LightrayWorkQueue};
-use crate::lightray_scheduler::statistics::SchedulerStatistics;
+use crate::lightray_scheduler::admission::{LightrayAdmissionControl, LightrayQueueLimits};
+use crate::lightray_scheduler::queue::{
+    enqueue_channel_based_work, ChannelBasedWork, LightrayScheduledExecutionResult,
+    LightrayWorkOptions, LightrayWorkQueue,
+};
+use crate::lightray_scheduler::wakeup::{
+    LightrayShutdownGate, LightrayWorkerIdlePolicy, LightrayWorkerWakeup,
+};
 use crate::lightray_torch::core::TorchScriptInput;
 use async_trait::async_trait;
 use crossbeam_queue::SegQueue;
-use std::thread;
-use std::time::{Instant, SystemTime};
-use tokio::sync::oneshot::{channel, Receiver, Sender};
-pub struct ChannelBasedWork {
-    payload: TorchScriptInput,
-    model_id: LightrayModelId,
-    sender: Sender<LightrayExecutorResult>,
-}
 pub struct LightrayFIFOWorkQueue<T: LightrayExecutor> {
     worker_queue: SegQueue<ChannelBasedWork>,
+    worker_wakeup: LightrayWorkerWakeup,
+    shutdown_gate: LightrayShutdownGate,
+    admission_control: LightrayAdmissionControl,
     worker_executor: T,
     verify_model_input: bool,
 }
 
 impl<T: LightrayExecutor> LightrayFIFOWorkQueue<T> {
     pub fn new(worker_executor: T, verify_model_input: bool) -> LightrayFIFOWorkQueue<T> {
+        LightrayFIFOWorkQueue::with_idle_policy(
+            worker_executor,
+            verify_model_input,
+            LightrayWorkerIdlePolicy::default(),
+        )
+    }
+    pub fn with_idle_policy(
+        worker_executor: T,
+        verify_model_input: bool,
+        idle_policy: LightrayWorkerIdlePolicy,
+    ) -> LightrayFIFOWorkQueue<T> {
         LightrayFIFOWorkQueue::<T> {
             worker_queue: SegQueue::new(),
+            worker_wakeup: LightrayWorkerWakeup::new(idle_policy),
+            shutdown_gate: LightrayShutdownGate::default(),
+            admission_control: LightrayAdmissionControl::new(LightrayQueueLimits::default()),
             worker_executor,
             verify_model_input,
         }
     }
+
+    pub fn with_queue_limits(
+        mut self,
+        queue_limits: LightrayQueueLimits,
+    ) -> LightrayFIFOWorkQueue<T> {
+        self.admission_control = LightrayAdmissionControl::new(queue_limits);
+        self
+    }
 }
 
 #[async_trait(?Send)]
 impl<T: LightrayExecutor> LightrayWorkQueue<T> for LightrayFIFOWorkQueue<T> {
-    async fn enqueue(
-        &mut self,
+    async fn enqueue_with_options(
+        &self,
         payload: TorchScriptInput,
         model_id: LightrayModelId,
+        options: LightrayWorkOptions,
     ) -> LightrayScheduledExecutionResult {
-        let (tx, rx): (
-            Sender<LightrayExecutorResult>,
-            Receiver<LightrayExecutorResult>,
-        ) = channel();
-        let work: ChannelBasedWork = ChannelBasedWork {
+        enqueue_channel_based_work(
             payload,
             model_id,
-            sender: tx,
-        };
-        let queue_instance_start_time = Instant::now();
-        let queue_start_time = SystemTime::now();
-
-        self.worker_queue.push(work);
-        let execution_result = rx.await;
-
-        let queue_instance_end_time = Instant::now();
-        let queue_end_time = SystemTime::now();
-
-        let metrics = SchedulerStatistics {
-            time_spent_in_queue: queue_instance_end_time - queue_instance_start_time,
-            start_time_in_queue: queue_start_time,
-            end_time_in_queue: queue_end_time,
-            number_of_elements_in_queue: self.worker_queue.len(),
-        };
-
-        match execution_result {
-            Ok(executor_result) => LightrayScheduledExecutionResult {
-                execution_result: Some(executor_result),
-                scheduler_error: None,
-                scheduler_metrics: metrics,
+            options,
+            |work| {
+                self.admission_control.admit(
+                    work,
+                    &self.shutdown_gate,
+                    &self.worker_wakeup,
+                    |work| self.worker_queue.push(work),
+                )
             },
-            Err(_) => LightrayScheduledExecutionResult {
-                execution_result: None,
-                scheduler_error: Some(LightraySchedulerError::SchedulerError),
-                scheduler_metrics: metrics,
-            },
-        }
+            || self.worker_queue.len(),
+        )
+        .await
     }
-    fn worker_loop(&mut self) {
+    fn worker_loop(&self) {
         loop {
+            // Read the shutdown state before popping: work is only admitted before the gate
+            // closes, so an empty queue observed afterwards is drained for good.
+            let is_shut_down = self.shutdown_gate.is_closed();
             if let Ok(value) = self.worker_queue.pop() {
-                let executed_value = self.worker_executor.execute(
-                    &value.model_id,
-                    &value.payload,
-                    self.verify_model_input,
-                );
-                let _x = value.sender.send(executed_value);
+                self.admission_control.release(&value.model_id);
+                if let Some(value) = value.into_live() {
+                    let executed_value = self.worker_executor.execute(
+                        &value.model_id,
+                        &value.payload,
+                        self.verify_model_input,
+                    );
+                    value.respond(executed_value);
+                }
+            } else if is_shut_down {
+                return;
             } else {
-                // Otherwise, this will be an incredibly tight loop which might end up taking a whole core.
-                // To stop this from happening we yield the thread when the queue is empty.
-                thread::yield_now();
+                // Park instead of spinning on an empty queue, otherwise an idle worker
+                // takes a whole core.
+                self.worker_wakeup.wait(
+                    || !self.worker_queue.is_empty() || self.shutdown_gate.is_closed(),
+                    None,
+                );
             }
         }
     }
+    fn shutdown(&self) {
+        self.shutdown_gate.close();
+        self.worker_wakeup.notify_all();
+    }
     fn get_executor(&self) -> &T {
         &self.worker_executor
     }
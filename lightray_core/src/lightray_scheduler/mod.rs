@@ -1,4 +1,10 @@
+pub mod admission;
+pub mod batching_queue;
+pub mod configured_queue;
 pub mod errors;
 pub mod greedy_fifo_queue;
+pub mod priority_queue;
 pub mod queue;
 pub mod statistics;
+pub mod wakeup;
+pub mod worker_pool;
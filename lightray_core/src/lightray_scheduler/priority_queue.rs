@@ -0,0 +1,220 @@
+use crate::lightray_executor::executor::LightrayExecutor;
+use crate::lightray_executor::model::LightrayModelId;
+use crate::lightray_scheduler::admission::{LightrayAdmissionControl, LightrayQueueLimits};
+use crate::lightray_scheduler::queue::{
+    enqueue_channel_based_work, ChannelBasedWork, LightrayScheduledExecutionResult,
+    LightrayWorkOptions, LightrayWorkQueue,
+};
+use crate::lightray_scheduler::wakeup::{
+    LightrayShutdownGate, LightrayWorkerIdlePolicy, LightrayWorkerWakeup,
+};
+use crate::lightray_torch::core::TorchScriptInput;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+const PRIORITY_CLASSES: usize = 2;
+
+struct PrioritizedWork {
+    work: ChannelBasedWork,
+    enqueued_at: Instant,
+}
+
+/// Work queue that serves work by priority class, FIFO within a class.
+///
+/// Starvation is prevented by aging: work of a class ranked `r` competes as if it had been
+/// enqueued `r * aging_interval` later than it was. Bulk work that waited longer than the
+/// aging interval is therefore served before interactive work that arrived after it.
+pub struct LightrayPriorityWorkQueue<T: LightrayExecutor> {
+    worker_queues: Mutex<[VecDeque<PrioritizedWork>; PRIORITY_CLASSES]>,
+    worker_wakeup: LightrayWorkerWakeup,
+    shutdown_gate: LightrayShutdownGate,
+    admission_control: LightrayAdmissionControl,
+    worker_executor: T,
+    verify_model_input: bool,
+    aging_interval: Duration,
+}
+
+impl<T: LightrayExecutor> LightrayPriorityWorkQueue<T> {
+    pub fn new(
+        worker_executor: T,
+        verify_model_input: bool,
+        aging_interval: Duration,
+    ) -> LightrayPriorityWorkQueue<T> {
+        LightrayPriorityWorkQueue::<T> {
+            worker_queues: Mutex::new([VecDeque::new(), VecDeque::new()]),
+            worker_wakeup: LightrayWorkerWakeup::new(LightrayWorkerIdlePolicy::default()),
+            shutdown_gate: LightrayShutdownGate::default(),
+            admission_control: LightrayAdmissionControl::new(LightrayQueueLimits::default()),
+            worker_executor,
+            verify_model_input,
+            aging_interval,
+        }
+    }
+
+    pub fn with_queue_limits(
+        mut self,
+        queue_limits: LightrayQueueLimits,
+    ) -> LightrayPriorityWorkQueue<T> {
+        self.admission_control = LightrayAdmissionControl::new(queue_limits);
+        self
+    }
+
+    fn lock_queues(&self) -> MutexGuard<'_, [VecDeque<PrioritizedWork>; PRIORITY_CLASSES]> {
+        match self.worker_queues.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.lock_queues().iter().map(VecDeque::len).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.lock_queues().iter().all(VecDeque::is_empty)
+    }
+
+    fn push(&self, work: ChannelBasedWork) {
+        let rank = work.options.priority.rank();
+        self.lock_queues()[rank].push_back(PrioritizedWork {
+            work,
+            enqueued_at: Instant::now(),
+        });
+    }
+
+    fn pop(&self) -> Option<ChannelBasedWork> {
+        let mut worker_queues = self.lock_queues();
+        let aging_interval = self.aging_interval;
+        let next_rank = worker_queues
+            .iter()
+            .enumerate()
+            .filter_map(|(rank, worker_queue)| {
+                worker_queue
+                    .front()
+                    .map(|front| (rank, front.enqueued_at + aging_interval * rank as u32))
+            })
+            .min_by_key(|(_, effective_enqueue_time)| *effective_enqueue_time)
+            .map(|(rank, _)| rank)?;
+        worker_queues[next_rank]
+            .pop_front()
+            .map(|prioritized| prioritized.work)
+    }
+}
+
+#[async_trait(?Send)]
+impl<T: LightrayExecutor> LightrayWorkQueue<T> for LightrayPriorityWorkQueue<T> {
+    async fn enqueue_with_options(
+        &self,
+        payload: TorchScriptInput,
+        model_id: LightrayModelId,
+        options: LightrayWorkOptions,
+    ) -> LightrayScheduledExecutionResult {
+        enqueue_channel_based_work(
+            payload,
+            model_id,
+            options,
+            |work| {
+                self.admission_control.admit(
+                    work,
+                    &self.shutdown_gate,
+                    &self.worker_wakeup,
+                    |work| self.push(work),
+                )
+            },
+            || self.len(),
+        )
+        .await
+    }
+    fn worker_loop(&self) {
+        loop {
+            let is_shut_down = self.shutdown_gate.is_closed();
+            if let Some(value) = self.pop() {
+                self.admission_control.release(&value.model_id);
+                if let Some(value) = value.into_live() {
+                    let executed_value = self.worker_executor.execute(
+                        &value.model_id,
+                        &value.payload,
+                        self.verify_model_input,
+                    );
+                    value.respond(executed_value);
+                }
+            } else if is_shut_down {
+                return;
+            } else {
+                self.worker_wakeup
+                    .wait(|| !self.is_empty() || self.shutdown_gate.is_closed(), None);
+            }
+        }
+    }
+    fn shutdown(&self) {
+        self.shutdown_gate.close();
+        self.worker_wakeup.notify_all();
+    }
+    fn get_executor(&self) -> &T {
+        &self.worker_executor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lightray_executor::executor::InMemorySimpleLightrayExecutor;
+    use crate::lightray_scheduler::queue::LightrayWorkPriority;
+    use std::thread;
+    use tokio::sync::oneshot::channel;
+    use uuid::Uuid;
+
+    fn work(priority: LightrayWorkPriority, model_version: u16) -> ChannelBasedWork {
+        let (sender, _receiver) = channel();
+        ChannelBasedWork {
+            payload: TorchScriptInput {
+                positional_arguments: vec![],
+                ..Default::default()
+            },
+            model_id: LightrayModelId {
+                model_id: Uuid::nil(),
+                model_version,
+            },
+            options: LightrayWorkOptions {
+                priority,
+                deadline: None,
+            },
+            sender,
+        }
+    }
+
+    #[test]
+    fn test_interactive_work_first() {
+        let queue = LightrayPriorityWorkQueue::new(
+            InMemorySimpleLightrayExecutor::new(),
+            false,
+            Duration::from_secs(60),
+        );
+        queue.push(work(LightrayWorkPriority::Bulk, 0));
+        queue.push(work(LightrayWorkPriority::Interactive, 1));
+        queue.push(work(LightrayWorkPriority::Interactive, 2));
+        assert_eq!(queue.len(), 3);
+        let order: Vec<u16> = (0..3)
+            .map(|_| queue.pop().unwrap().model_id.model_version)
+            .collect();
+        assert_eq!(order, vec![1, 2, 0]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_aged_bulk_work_is_served() {
+        let queue = LightrayPriorityWorkQueue::new(
+            InMemorySimpleLightrayExecutor::new(),
+            false,
+            Duration::from_millis(10),
+        );
+        queue.push(work(LightrayWorkPriority::Bulk, 0));
+        thread::sleep(Duration::from_millis(20));
+        queue.push(work(LightrayWorkPriority::Interactive, 1));
+        assert_eq!(queue.pop().unwrap().model_id.model_version, 0);
+        assert_eq!(queue.pop().unwrap().model_id.model_version, 1);
+        assert!(queue.pop().is_none());
+    }
+}
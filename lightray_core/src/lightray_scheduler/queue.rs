@@ -4,6 +4,86 @@ use crate::lightray_scheduler::errors::LightraySchedulerError;
 use crate::lightray_scheduler::statistics::SchedulerStatistics;
 use crate::lightray_torch::core::TorchScriptInput;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::{Instant, SystemTime};
+use tokio::sync::oneshot::{channel, Receiver, Sender};
+
+/// Priority class of queued work. Queues that do not schedule by priority ignore it.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum LightrayWorkPriority {
+    /// Latency sensitive work, served first.
+    Interactive,
+    /// Throughput oriented work such as backfills.
+    Bulk,
+}
+
+impl LightrayWorkPriority {
+    /// Position of the class in scheduling order, lower is served first.
+    pub fn rank(self) -> usize {
+        match self {
+            LightrayWorkPriority::Interactive => 0,
+            LightrayWorkPriority::Bulk => 1,
+        }
+    }
+}
+
+impl Default for LightrayWorkPriority {
+    fn default() -> Self {
+        LightrayWorkPriority::Interactive
+    }
+}
+
+impl FromStr for LightrayWorkPriority {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "interactive" => Ok(LightrayWorkPriority::Interactive),
+            "bulk" => Ok(LightrayWorkPriority::Bulk),
+            other => Err(format!("unknown priority class {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct LightrayWorkOptions {
+    pub priority: LightrayWorkPriority,
+    /// Work still queued after this instant is answered with `DeadlineExceeded` instead of
+    /// being executed.
+    pub deadline: Option<Instant>,
+}
+
+pub type LightrayScheduledWorkResult = Result<LightrayExecutorResult, LightraySchedulerError>;
+
+pub struct ChannelBasedWork {
+    pub(crate) payload: TorchScriptInput,
+    pub(crate) model_id: LightrayModelId,
+    pub(crate) options: LightrayWorkOptions,
+    pub(crate) sender: Sender<LightrayScheduledWorkResult>,
+}
+
+impl ChannelBasedWork {
+    /// Returns the work if it should still be executed. Expired work is answered with
+    /// `DeadlineExceeded` and work whose caller stopped waiting is dropped.
+    pub(crate) fn into_live(self) -> Option<ChannelBasedWork> {
+        if self.sender.is_closed() {
+            return None;
+        }
+        match self.options.deadline {
+            Some(deadline) if deadline <= Instant::now() => {
+                let _x = self
+                    .sender
+                    .send(Err(LightraySchedulerError::DeadlineExceeded));
+                None
+            }
+            _ => Some(self),
+        }
+    }
+
+    pub(crate) fn respond(self, executed_value: LightrayExecutorResult) {
+        let _x = self.sender.send(Ok(executed_value));
+    }
+}
 
 pub struct LightrayScheduledExecutionResult {
     pub execution_result: Option<LightrayExecutorResult>,
@@ -11,12 +91,138 @@ pub struct LightrayScheduledExecutionResult {
     pub scheduler_metrics: SchedulerStatistics,
 }
 #[async_trait(?Send)]
-pub trait LightrayWorkQueue<T: LightrayExecutor> {
+pub trait LightrayWorkQueue<T: LightrayExecutor>: Send + Sync {
     async fn enqueue(
-        &mut self,
+        &self,
+        payload: TorchScriptInput,
+        model_id: LightrayModelId,
+    ) -> LightrayScheduledExecutionResult {
+        self.enqueue_with_options(payload, model_id, LightrayWorkOptions::default())
+            .await
+    }
+    async fn enqueue_with_options(
+        &self,
         payload: TorchScriptInput,
         model_id: LightrayModelId,
+        options: LightrayWorkOptions,
     ) -> LightrayScheduledExecutionResult;
-    fn worker_loop(&mut self);
+    /// Executes queued work until `shutdown` is called and the queue is drained.
+    /// Several threads may run the worker loop of the same queue concurrently.
+    fn worker_loop(&self);
+    /// Stops accepting new work and lets every worker loop return once the queue is drained.
+    fn shutdown(&self);
     fn get_executor(&self) -> &T;
 }
+
+/// Wraps the payload into a `ChannelBasedWork`, hands it to `push` and waits for a worker
+/// to answer it. Shared by the queue implementations so they only differ in how work is stored.
+pub(crate) async fn enqueue_channel_based_work<P, L>(
+    payload: TorchScriptInput,
+    model_id: LightrayModelId,
+    options: LightrayWorkOptions,
+    push: P,
+    queue_length: L,
+) -> LightrayScheduledExecutionResult
+where
+    P: FnOnce(ChannelBasedWork) -> Result<(), LightraySchedulerError>,
+    L: Fn() -> usize,
+{
+    let (tx, rx): (
+        Sender<LightrayScheduledWorkResult>,
+        Receiver<LightrayScheduledWorkResult>,
+    ) = channel();
+    let work: ChannelBasedWork = ChannelBasedWork {
+        payload,
+        model_id,
+        options,
+        sender: tx,
+    };
+    let queue_instance_start_time = Instant::now();
+    let queue_start_time = SystemTime::now();
+
+    let execution_result = match push(work) {
+        Ok(()) => match rx.await {
+            Ok(scheduled_work_result) => scheduled_work_result,
+            Err(_) => Err(LightraySchedulerError::SchedulerError),
+        },
+        Err(error) => Err(error),
+    };
+
+    let queue_instance_end_time = Instant::now();
+    let queue_end_time = SystemTime::now();
+
+    let metrics = SchedulerStatistics {
+        time_spent_in_queue: queue_instance_end_time - queue_instance_start_time,
+        start_time_in_queue: queue_start_time,
+        end_time_in_queue: queue_end_time,
+        number_of_elements_in_queue: queue_length(),
+    };
+
+    match execution_result {
+        Ok(executor_result) => LightrayScheduledExecutionResult {
+            execution_result: Some(executor_result),
+            scheduler_error: None,
+            scheduler_metrics: metrics,
+        },
+        Err(error) => LightrayScheduledExecutionResult {
+            execution_result: None,
+            scheduler_error: Some(error),
+            scheduler_metrics: metrics,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::oneshot::channel;
+    use uuid::Uuid;
+
+    fn work(
+        deadline: Option<Instant>,
+    ) -> (ChannelBasedWork, Receiver<LightrayScheduledWorkResult>) {
+        let (sender, receiver) = channel();
+        let work = ChannelBasedWork {
+            payload: TorchScriptInput {
+                positional_arguments: vec![],
+                ..Default::default()
+            },
+            model_id: LightrayModelId {
+                model_id: Uuid::nil(),
+                model_version: 0,
+            },
+            options: LightrayWorkOptions {
+                priority: LightrayWorkPriority::Interactive,
+                deadline,
+            },
+            sender,
+        };
+        (work, receiver)
+    }
+
+    #[test]
+    fn test_work_before_deadline_is_live() {
+        let (value, _receiver) = work(Some(Instant::now() + Duration::from_secs(60)));
+        assert!(value.into_live().is_some());
+        let (value, _receiver) = work(None);
+        assert!(value.into_live().is_some());
+    }
+
+    #[test]
+    fn test_expired_work_is_answered() {
+        let (value, mut receiver) = work(Some(Instant::now()));
+        assert!(value.into_live().is_none());
+        match receiver.try_recv() {
+            Ok(Err(LightraySchedulerError::DeadlineExceeded)) => {}
+            _ => panic!("expected DeadlineExceeded"),
+        }
+    }
+
+    #[test]
+    fn test_abandoned_work_is_dropped() {
+        let (value, receiver) = work(None);
+        drop(receiver);
+        assert!(value.into_live().is_none());
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+/// Controls how an idle worker waits for new work.
+#[derive(Clone, Copy, Debug)]
+pub struct LightrayWorkerIdlePolicy {
+    /// Number of times an idle worker re-checks the queue, yielding in between, before it
+    /// parks until the next enqueue. Higher values trade idle CPU usage for wake latency.
+    pub spin_count: u32,
+}
+
+impl Default for LightrayWorkerIdlePolicy {
+    fn default() -> Self {
+        LightrayWorkerIdlePolicy { spin_count: 64 }
+    }
+}
+
+pub(crate) struct LightrayWorkerWakeup {
+    idle_policy: LightrayWorkerIdlePolicy,
+    lock: Mutex<()>,
+    condvar: Condvar,
+}
+
+impl LightrayWorkerWakeup {
+    pub(crate) fn new(idle_policy: LightrayWorkerIdlePolicy) -> LightrayWorkerWakeup {
+        LightrayWorkerWakeup {
+            idle_policy,
+            lock: Mutex::new(()),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Wakes up one parked worker. Must be called after the work was made visible to workers.
+    pub(crate) fn notify_one(&self) {
+        let _guard = self.lock.lock();
+        self.condvar.notify_one();
+    }
+
+    pub(crate) fn notify_all(&self) {
+        let _guard = self.lock.lock();
+        self.condvar.notify_all();
+    }
+
+    /// Spins according to the idle policy and then parks the calling worker until `has_work`
+    /// holds, a notification arrives or `timeout` elapses. Callers must re-check for work
+    /// after this returns.
+    pub(crate) fn wait<F: Fn() -> bool>(&self, has_work: F, timeout: Option<Duration>) {
+        for _ in 0..self.idle_policy.spin_count {
+            if has_work() {
+                return;
+            }
+            thread::yield_now();
+        }
+        // Checking for work while holding the lock pairs with `notify_one` taking the same
+        // lock, so a notification sent between the check and the wait cannot be missed.
+        let guard = match self.lock.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if has_work() {
+            return;
+        }
+        match timeout {
+            Some(timeout) => drop(self.condvar.wait_timeout(guard, timeout)),
+            None => drop(self.condvar.wait(guard)),
+        }
+    }
+}
+
+/// Stops a queue from admitting work once it is shutting down.
+///
+/// Work is admitted under a read lock and the gate closes under the write lock, so every
+/// admitted item is visible to a worker that observes the gate as closed.
+#[derive(Default)]
+pub(crate) struct LightrayShutdownGate {
+    lock: RwLock<()>,
+    closed: AtomicBool,
+}
+
+impl LightrayShutdownGate {
+    /// Runs `admit_work` unless the gate is closed.
+    pub(crate) fn admit<R, F: FnOnce() -> R>(&self, admit_work: F) -> Option<R> {
+        let _guard = match self.lock.read() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if self.closed.load(Ordering::SeqCst) {
+            return None;
+        }
+        Some(admit_work())
+    }
+
+    pub(crate) fn close(&self) {
+        let _guard = match self.lock.write() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
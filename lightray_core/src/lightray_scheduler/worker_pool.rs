@@ -0,0 +1,63 @@
+use crate::lightray_executor::executor::LightrayExecutor;
+use crate::lightray_scheduler::queue::LightrayWorkQueue;
+use std::io;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Runs the worker loop of a shared queue on a fixed number of threads.
+///
+/// Dropping the pool shuts the queue down and waits for the workers to drain it.
+pub struct LightrayWorkerPool<T: LightrayExecutor, Q: LightrayWorkQueue<T> + 'static> {
+    queue: Arc<Q>,
+    workers: Vec<JoinHandle<()>>,
+    executor: PhantomData<T>,
+}
+
+impl<T: LightrayExecutor, Q: LightrayWorkQueue<T> + 'static> LightrayWorkerPool<T, Q> {
+    pub fn new(queue: Arc<Q>, worker_count: usize) -> io::Result<LightrayWorkerPool<T, Q>> {
+        if worker_count == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "worker_count must be positive",
+            ));
+        }
+        // The pool only exists once every worker is running: dropping a partially spawned pool
+        // would shut down the queue the caller still owns.
+        let mut workers = Vec::with_capacity(worker_count);
+        for worker_index in 0..worker_count {
+            let worker_queue = queue.clone();
+            let worker = thread::Builder::new()
+                .name(format!("lightray-worker-{}", worker_index))
+                .spawn(move || worker_queue.worker_loop())?;
+            workers.push(worker);
+        }
+        Ok(LightrayWorkerPool {
+            queue,
+            workers,
+            executor: PhantomData,
+        })
+    }
+
+    pub fn worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn queue(&self) -> &Arc<Q> {
+        &self.queue
+    }
+
+    /// Stops the queue from accepting work and blocks until every queued item was executed.
+    pub fn shutdown(&mut self) {
+        self.queue.shutdown();
+        for worker in self.workers.drain(..) {
+            let _x = worker.join();
+        }
+    }
+}
+
+impl<T: LightrayExecutor, Q: LightrayWorkQueue<T> + 'static> Drop for LightrayWorkerPool<T, Q> {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
@@ -0,0 +1,652 @@
+//! Canonical byte encoding and stable content hash of model inputs.
+//!
+//! Every value encodes as a one byte tag followed by its payload. Integers and lengths are
+//! little-endian and strings, sequences and tensor data are prefixed by their length, so
+//! distinct values never share an encoding. Doubles encode their IEEE 754 bits after mapping
+//! every NaN to a single quiet NaN and `-0.0` to `0.0`. Tensors encode the kind and shape of
+//! their NPY payload followed by its elements in row-major order and little-endian byte order,
+//! with floating point elements canonicalized like doubles and unsigned elements widened as
+//! `read_npy` does. NPY files that only differ in header formatting, byte order, memory order,
+//! base64 padding or in being base64 encoded at all therefore encode alike, and tensor
+//! literals encode as their NPY file. NPZ archives encode the name and tensor of each array
+//! in archive order, independent of compression. Dictionaries encode their entries in order,
+//! matching their `PartialEq`, and keyword arguments in name order after the positional ones.
+//! Inputs calling a method other than `forward` encode its name last.
+//! Payloads that do not decode encode verbatim under separate tags.
+//!
+//! The encoding depends on nothing but the value, which makes `content_hash` stable across
+//! processes and machines. Values equal under `PartialEq` always share an encoding.
+use crate::lightray_torch::core::{SerializableIValue, TorchScriptInput, FORWARD_METHOD};
+use crate::lightray_torch::literal::{element_count, literal_npy_parts};
+use crate::lightray_torch::tensor::{
+    element_size, kind_descr, native_data, read_npz_entries, split_npy, Header,
+};
+use base64;
+use serde_json::Number;
+use std::convert::TryInto;
+use std::hash::{Hash, Hasher};
+use tch::Kind;
+
+const NONE_TAG: u8 = 0;
+const BOOL_TAG: u8 = 1;
+const INT_TAG: u8 = 2;
+const DOUBLE_TAG: u8 = 3;
+const STR_TAG: u8 = 4;
+const TUPLE_TAG: u8 = 5;
+const LIST_TAG: u8 = 6;
+const OPTIONAL_TAG: u8 = 7;
+const TENSOR_TAG: u8 = 8;
+const OPAQUE_TENSOR_TAG: u8 = 9;
+const ARCHIVE_TAG: u8 = 10;
+const OPAQUE_ARCHIVE_TAG: u8 = 11;
+const OPAQUE_NPY_TAG: u8 = 12;
+const OPAQUE_LITERAL_TAG: u8 = 13;
+const DICT_TAG: u8 = 14;
+const OPAQUE_TENSOR_DATA_TAG: u8 = 15;
+
+const CANONICAL_NAN_BITS: u64 = 0x7ff8_0000_0000_0000;
+const CANONICAL_FLOAT_NAN_BITS: u32 = 0x7fc0_0000;
+const CANONICAL_HALF_NAN_BITS: u16 = 0x7e00;
+const HALF_EXPONENT_MASK: u16 = 0x7c00;
+const HALF_MANTISSA_MASK: u16 = 0x03ff;
+const HALF_NEGATIVE_ZERO_BITS: u16 = 0x8000;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 64-bit FNV-1a hash of `bytes`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u64).to_le_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_len(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+fn write_sequence(out: &mut Vec<u8>, tag: u8, values: &[SerializableIValue]) {
+    out.push(tag);
+    write_len(out, values.len());
+    for value in values {
+        value.write_canonical(out);
+    }
+}
+
+fn canonical_double_bits(value: f64) -> u64 {
+    if value.is_nan() {
+        CANONICAL_NAN_BITS
+    } else if value == 0.0 {
+        0
+    } else {
+        value.to_bits()
+    }
+}
+
+fn canonical_float_bits(value: f32) -> u32 {
+    if value.is_nan() {
+        CANONICAL_FLOAT_NAN_BITS
+    } else if value == 0.0 {
+        0
+    } else {
+        value.to_bits()
+    }
+}
+
+fn canonical_half_bits(bits: u16) -> u16 {
+    if bits & HALF_EXPONENT_MASK == HALF_EXPONENT_MASK && bits & HALF_MANTISSA_MASK != 0 {
+        CANONICAL_HALF_NAN_BITS
+    } else if bits == HALF_NEGATIVE_ZERO_BITS {
+        0
+    } else {
+        bits
+    }
+}
+
+/// Reorders the elements of column-major `data` into row-major order.
+fn row_major_data(data: &[u8], shape: &[i64], element_size: usize) -> Vec<u8> {
+    let dimensions = shape
+        .iter()
+        .map(|dimension| *dimension as usize)
+        .collect::<Vec<usize>>();
+    let mut column_major_strides = Vec::with_capacity(dimensions.len());
+    let mut stride = 1;
+    for dimension in &dimensions {
+        column_major_strides.push(stride);
+        stride *= dimension;
+    }
+    let mut index = vec![0usize; dimensions.len()];
+    let mut reordered = Vec::with_capacity(data.len());
+    for _ in 0..stride {
+        let offset = index
+            .iter()
+            .zip(&column_major_strides)
+            .map(|(position, stride)| position * stride)
+            .sum::<usize>()
+            * element_size;
+        reordered.extend_from_slice(&data[offset..offset + element_size]);
+        for axis in (0..dimensions.len()).rev() {
+            index[axis] += 1;
+            if index[axis] < dimensions[axis] {
+                break;
+            }
+            index[axis] = 0;
+        }
+    }
+    reordered
+}
+
+/// Elements of an NPY payload in row-major order and little-endian byte order, with floating
+/// point elements canonicalized. `None` if the data does not match the header.
+fn canonical_tensor_data(header: &Header, data: &[u8]) -> Option<Vec<u8>> {
+    let data = native_data(header, data).ok()?;
+    let element_size = element_size(header.descr)?;
+    let element_count = element_count(&header.shape)?;
+    if element_count.checked_mul(element_size)? != data.len() {
+        return None;
+    }
+    let data = if header.fortran_order && header.shape.len() > 1 {
+        row_major_data(&data, &header.shape, element_size)
+    } else {
+        data.into_owned()
+    };
+    let mut canonical = Vec::with_capacity(data.len());
+    for element in data.chunks(element_size) {
+        match header.descr {
+            Kind::Half => canonical.extend_from_slice(
+                &canonical_half_bits(u16::from_ne_bytes(element.try_into().ok()?)).to_le_bytes(),
+            ),
+            Kind::Float => canonical.extend_from_slice(
+                &canonical_float_bits(f32::from_ne_bytes(element.try_into().ok()?)).to_le_bytes(),
+            ),
+            Kind::Double => canonical.extend_from_slice(
+                &canonical_double_bits(f64::from_ne_bytes(element.try_into().ok()?)).to_le_bytes(),
+            ),
+            _ if cfg!(target_endian = "big") => {
+                canonical.extend(element.iter().rev());
+            }
+            _ => canonical.extend_from_slice(element),
+        }
+    }
+    Some(canonical)
+}
+
+/// Encodes a parsed NPY header with data that does not match it, verbatim.
+fn write_opaque_tensor_data(out: &mut Vec<u8>, header: &Header, data: &[u8]) {
+    out.push(OPAQUE_TENSOR_DATA_TAG);
+    write_bytes(out, format!("{:?}", header.descr).as_bytes());
+    out.push(header.big_endian as u8);
+    out.push(header.unsigned_width.unwrap_or(0) as u8);
+    out.push(header.fortran_order as u8);
+    write_len(out, header.shape.len());
+    for dimension in &header.shape {
+        out.extend_from_slice(&dimension.to_le_bytes());
+    }
+    write_bytes(out, data);
+}
+
+fn write_npy(out: &mut Vec<u8>, header: &Header, data: &[u8]) {
+    let (descr, data) = match (
+        kind_descr(header.descr),
+        canonical_tensor_data(header, data),
+    ) {
+        (Some(descr), Some(data)) => (descr, data),
+        _ => {
+            write_opaque_tensor_data(out, header, data);
+            return;
+        }
+    };
+    out.push(TENSOR_TAG);
+    write_bytes(out, descr.as_bytes());
+    write_len(out, header.shape.len());
+    for dimension in &header.shape {
+        out.extend_from_slice(&dimension.to_le_bytes());
+    }
+    write_bytes(out, &data);
+}
+
+fn write_tensor(out: &mut Vec<u8>, npy: &[u8]) {
+    match split_npy(npy) {
+        Ok((header, data)) => write_npy(out, &header, data),
+        Err(_) => {
+            out.push(OPAQUE_NPY_TAG);
+            write_bytes(out, npy);
+        }
+    }
+}
+
+fn write_base64_tensor(out: &mut Vec<u8>, npy_base64: &str) {
+    match base64::decode(npy_base64) {
+        Ok(npy) => write_tensor(out, &npy),
+        Err(_) => {
+            out.push(OPAQUE_TENSOR_TAG);
+            write_bytes(out, npy_base64.as_bytes());
+        }
+    }
+}
+
+fn write_literal(out: &mut Vec<u8>, dtype: &str, shape: &[i64], data: &[Number]) {
+    match literal_npy_parts(dtype, shape, data) {
+        Ok((header, bytes)) => write_npy(out, &header, &bytes),
+        Err(_) => {
+            out.push(OPAQUE_LITERAL_TAG);
+            write_bytes(out, dtype.as_bytes());
+            write_len(out, shape.len());
+            for dimension in shape {
+                out.extend_from_slice(&dimension.to_le_bytes());
+            }
+            write_len(out, data.len());
+            for element in data {
+                write_bytes(out, element.to_string().as_bytes());
+            }
+        }
+    }
+}
+
+fn write_archive(out: &mut Vec<u8>, npz_base64: &str) {
+    let entries = base64::decode(npz_base64)
+        .ok()
+        .and_then(|npz| read_npz_entries(&npz).ok());
+    match entries {
+        Some(entries) => {
+            out.push(ARCHIVE_TAG);
+            write_len(out, entries.len());
+            for (name, npy) in &entries {
+                write_bytes(out, name.as_bytes());
+                write_tensor(out, npy);
+            }
+        }
+        None => {
+            out.push(OPAQUE_ARCHIVE_TAG);
+            write_bytes(out, npz_base64.as_bytes());
+        }
+    }
+}
+
+impl SerializableIValue {
+    /// Appends the canonical encoding of this value to `out`.
+    pub fn write_canonical(&self, out: &mut Vec<u8>) {
+        match self {
+            SerializableIValue::None => out.push(NONE_TAG),
+            SerializableIValue::Bool(bool_value) => {
+                out.push(BOOL_TAG);
+                out.push(*bool_value as u8);
+            }
+            SerializableIValue::Int(int_value) => {
+                out.push(INT_TAG);
+                out.extend_from_slice(&int_value.to_le_bytes());
+            }
+            SerializableIValue::Double(double_value) => {
+                out.push(DOUBLE_TAG);
+                out.extend_from_slice(&canonical_double_bits(*double_value).to_le_bytes());
+            }
+            SerializableIValue::Str(string_value) => {
+                out.push(STR_TAG);
+                write_bytes(out, string_value.as_bytes());
+            }
+            SerializableIValue::Tuple(tuple_value) => write_sequence(out, TUPLE_TAG, tuple_value),
+            SerializableIValue::List(list_value) => write_sequence(out, LIST_TAG, list_value),
+            SerializableIValue::Optional(optional) => {
+                out.push(OPTIONAL_TAG);
+                match optional {
+                    Option::None => out.push(0),
+                    Option::Some(value) => {
+                        out.push(1);
+                        value.write_canonical(out);
+                    }
+                }
+            }
+            SerializableIValue::TensorNPYBase64(npy_base64) => write_base64_tensor(out, npy_base64),
+            SerializableIValue::TensorNPY(npy) => write_tensor(out, npy),
+            SerializableIValue::Tensor { dtype, shape, data } => {
+                write_literal(out, dtype, shape, data)
+            }
+            SerializableIValue::TensorNPZBase64(npz_base64) => write_archive(out, npz_base64),
+            SerializableIValue::Dict(entries) => {
+                out.push(DICT_TAG);
+                write_len(out, entries.len());
+                for (key, value) in entries {
+                    key.write_canonical(out);
+                    value.write_canonical(out);
+                }
+            }
+        }
+    }
+
+    /// Canonical byte encoding of this value.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.write_canonical(&mut out);
+        out
+    }
+
+    /// Stable 64-bit hash of the canonical encoding of this value.
+    pub fn content_hash(&self) -> u64 {
+        fnv1a(&self.canonical_bytes())
+    }
+}
+
+impl TorchScriptInput {
+    /// Canonical byte encoding of the arguments of this input.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        write_sequence(&mut out, TUPLE_TAG, &self.positional_arguments);
+        // Inputs without keyword arguments keep the encoding they had before keywords existed.
+        if !self.keyword_arguments.is_empty() {
+            out.push(DICT_TAG);
+            write_len(&mut out, self.keyword_arguments.len());
+            for (name, value) in &self.keyword_arguments {
+                out.push(STR_TAG);
+                write_bytes(&mut out, name.as_bytes());
+                value.write_canonical(&mut out);
+            }
+        }
+        if self.method_name() != FORWARD_METHOD {
+            out.push(STR_TAG);
+            write_bytes(&mut out, self.method_name().as_bytes());
+        }
+        out
+    }
+
+    /// Stable 64-bit hash of the canonical encoding of this input, suitable as a fingerprint
+    /// for caching, deduplication and logging.
+    pub fn content_hash(&self) -> u64 {
+        fnv1a(&self.canonical_bytes())
+    }
+}
+
+impl Hash for SerializableIValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write(&self.canonical_bytes());
+    }
+}
+
+impl Hash for TorchScriptInput {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write(&self.canonical_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    fn npy(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+        npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        npy.extend_from_slice(header.as_bytes());
+        npy.extend_from_slice(data);
+        npy
+    }
+
+    fn npy_base64(header: &str, data: &[u8]) -> String {
+        base64::encode(&npy(header, data))
+    }
+
+    fn npz_base64(arrays: &[(&str, &[u8])], compression_method: CompressionMethod) -> String {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for (name, npy) in arrays {
+            let options = FileOptions::default().compression_method(compression_method);
+            writer.start_file(format!("{}.npy", name), options).unwrap();
+            writer.write_all(npy).unwrap();
+        }
+        base64::encode(&writer.finish().unwrap().into_inner())
+    }
+
+    fn input(positional_arguments: Vec<SerializableIValue>) -> TorchScriptInput {
+        TorchScriptInput {
+            positional_arguments,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_doubles() {
+        assert_eq!(
+            SerializableIValue::Double(-0.0).canonical_bytes(),
+            SerializableIValue::Double(0.0).canonical_bytes()
+        );
+        assert_eq!(
+            SerializableIValue::Double(f64::NAN).content_hash(),
+            SerializableIValue::Double(-f64::NAN).content_hash()
+        );
+        assert_eq!(
+            SerializableIValue::Double(f64::NAN).canonical_bytes(),
+            SerializableIValue::Double(f64::from_bits(0x7ff0_0000_0000_0001)).canonical_bytes()
+        );
+        assert_ne!(
+            SerializableIValue::Double(f64::NAN).canonical_bytes(),
+            SerializableIValue::Double(f64::INFINITY).canonical_bytes()
+        );
+        assert_ne!(
+            SerializableIValue::Double(1.0).canonical_bytes(),
+            SerializableIValue::Int(1).canonical_bytes()
+        );
+    }
+
+    #[test]
+    fn test_structure_is_encoded() {
+        let tuple = SerializableIValue::Tuple(vec![SerializableIValue::Int(1)]);
+        let list = SerializableIValue::List(vec![SerializableIValue::Int(1)]);
+        assert_ne!(tuple.canonical_bytes(), list.canonical_bytes());
+
+        let split = input(vec![
+            SerializableIValue::Str("a".to_string()),
+            SerializableIValue::Str("b".to_string()),
+        ]);
+        let joined = input(vec![SerializableIValue::Str("ab".to_string())]);
+        assert_ne!(split.canonical_bytes(), joined.canonical_bytes());
+
+        assert_ne!(
+            SerializableIValue::None.canonical_bytes(),
+            SerializableIValue::Optional(None).canonical_bytes()
+        );
+
+        let entry = (SerializableIValue::Int(1), SerializableIValue::Int(2));
+        assert_ne!(
+            SerializableIValue::Dict(vec![entry.clone()]).canonical_bytes(),
+            SerializableIValue::List(vec![SerializableIValue::Tuple(vec![
+                entry.0.clone(),
+                entry.1.clone(),
+            ])])
+            .canonical_bytes()
+        );
+    }
+
+    #[test]
+    fn test_keyword_arguments_are_encoded() {
+        let positional = input(vec![SerializableIValue::Int(3)]);
+        let mut keyword = input(vec![]);
+        keyword
+            .keyword_arguments
+            .insert("beam_size".to_string(), SerializableIValue::Int(3));
+        assert_ne!(positional.canonical_bytes(), keyword.canonical_bytes());
+
+        let mut other_name = input(vec![]);
+        other_name
+            .keyword_arguments
+            .insert("top_k".to_string(), SerializableIValue::Int(3));
+        assert_ne!(keyword.canonical_bytes(), other_name.canonical_bytes());
+    }
+
+    #[test]
+    fn test_method_is_encoded() {
+        let mut forward = input(vec![SerializableIValue::Int(3)]);
+        let implicit_forward = forward.canonical_bytes();
+        forward.method = Some(FORWARD_METHOD.to_string());
+        assert_eq!(forward.canonical_bytes(), implicit_forward);
+
+        let mut encode = input(vec![SerializableIValue::Int(3)]);
+        encode.method = Some("encode".to_string());
+        assert_ne!(encode.canonical_bytes(), implicit_forward);
+    }
+
+    #[test]
+    fn test_tensor_payloads() {
+        let data = [0u8, 0, 128, 63];
+        let compact = npy_base64(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (1,), }",
+            &data,
+        );
+        let padded = npy_base64(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (1,), }          \n",
+            &data,
+        );
+        assert_ne!(compact, padded);
+        assert_eq!(
+            SerializableIValue::TensorNPYBase64(compact.clone()).content_hash(),
+            SerializableIValue::TensorNPYBase64(padded).content_hash()
+        );
+
+        let reshaped = npy_base64(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (1,1), }",
+            &data,
+        );
+        assert_ne!(
+            SerializableIValue::TensorNPYBase64(compact.clone()).content_hash(),
+            SerializableIValue::TensorNPYBase64(reshaped).content_hash()
+        );
+
+        let raw = npy(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (1,), }",
+            &data,
+        );
+        assert_eq!(
+            SerializableIValue::TensorNPYBase64(compact.clone()).canonical_bytes(),
+            SerializableIValue::TensorNPY(raw).canonical_bytes()
+        );
+
+        let literal = SerializableIValue::Tensor {
+            dtype: "float32".to_string(),
+            shape: vec![1],
+            data: vec![Number::from(1)],
+        };
+        assert_eq!(
+            SerializableIValue::TensorNPYBase64(compact.clone()).canonical_bytes(),
+            literal.canonical_bytes()
+        );
+        let invalid_literal = SerializableIValue::Tensor {
+            dtype: "float32".to_string(),
+            shape: vec![2],
+            data: vec![Number::from(1)],
+        };
+        assert_eq!(invalid_literal.canonical_bytes()[0], OPAQUE_LITERAL_TAG);
+
+        let opaque = SerializableIValue::TensorNPYBase64("not npy".to_string());
+        assert_eq!(opaque.canonical_bytes()[0], OPAQUE_TENSOR_TAG);
+        let opaque = SerializableIValue::TensorNPY(b"not npy".to_vec());
+        assert_eq!(opaque.canonical_bytes()[0], OPAQUE_NPY_TAG);
+        assert_eq!(
+            SerializableIValue::TensorNPYBase64(compact).canonical_bytes()[0],
+            TENSOR_TAG
+        );
+    }
+
+    #[test]
+    fn test_tensor_elements_are_canonicalized() {
+        let hash = |header: &str, data: &[u8]| {
+            SerializableIValue::TensorNPY(npy(header, data)).content_hash()
+        };
+        let little_endian = hash(
+            "{'descr': '<i4', 'fortran_order': False, 'shape': (2,), }",
+            &[1, 0, 0, 0, 2, 0, 0, 0],
+        );
+        let big_endian = hash(
+            "{'descr': '>i4', 'fortran_order': False, 'shape': (2,), }",
+            &[0, 0, 0, 1, 0, 0, 0, 2],
+        );
+        assert_eq!(little_endian, big_endian);
+
+        let row_major = hash(
+            "{'descr': '|u1', 'fortran_order': False, 'shape': (2, 3), }",
+            &[1, 2, 3, 4, 5, 6],
+        );
+        let column_major = hash(
+            "{'descr': '|u1', 'fortran_order': True, 'shape': (2, 3), }",
+            &[1, 4, 2, 5, 3, 6],
+        );
+        assert_eq!(row_major, column_major);
+        let transposed = hash(
+            "{'descr': '|u1', 'fortran_order': False, 'shape': (3, 2), }",
+            &[1, 4, 2, 5, 3, 6],
+        );
+        assert_ne!(row_major, transposed);
+
+        let float_header = "{'descr': '<f4', 'fortran_order': False, 'shape': (2,), }";
+        let nan_and_zero = [f32::NAN.to_le_bytes(), 0f32.to_le_bytes()].concat();
+        let other_nan_and_negative_zero = [
+            f32::from_bits(0xffc0_0001).to_le_bytes(),
+            (-0f32).to_le_bytes(),
+        ]
+        .concat();
+        assert_eq!(
+            hash(float_header, &nan_and_zero),
+            hash(float_header, &other_nan_and_negative_zero)
+        );
+        let half_header = "{'descr': '<f2', 'fortran_order': False, 'shape': (2,), }";
+        assert_eq!(
+            hash(half_header, &[0x00, 0x7e, 0x00, 0x00]),
+            hash(half_header, &[0x01, 0xfe, 0x00, 0x80])
+        );
+        assert_ne!(
+            hash(half_header, &[0x00, 0x7c, 0x00, 0x00]),
+            hash(half_header, &[0x00, 0x7e, 0x00, 0x00])
+        );
+
+        let truncated = SerializableIValue::TensorNPY(npy(float_header, &nan_and_zero[..6]));
+        assert_eq!(truncated.canonical_bytes()[0], OPAQUE_TENSOR_DATA_TAG);
+    }
+
+    #[test]
+    fn test_archive_payloads() {
+        let vector = npy(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (1,), }",
+            &[0, 0, 128, 63],
+        );
+        let padded_vector = npy(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (1,), }    \n",
+            &[0, 0, 128, 63],
+        );
+        let stored = npz_base64(&[("x", &vector)], CompressionMethod::Stored);
+        let deflated = npz_base64(&[("x", &padded_vector)], CompressionMethod::Deflated);
+        assert_ne!(stored, deflated);
+        assert_eq!(
+            SerializableIValue::TensorNPZBase64(stored.clone()).content_hash(),
+            SerializableIValue::TensorNPZBase64(deflated).content_hash()
+        );
+
+        let renamed = npz_base64(&[("y", &vector)], CompressionMethod::Stored);
+        assert_ne!(
+            SerializableIValue::TensorNPZBase64(stored.clone()).content_hash(),
+            SerializableIValue::TensorNPZBase64(renamed).content_hash()
+        );
+        assert_ne!(
+            SerializableIValue::TensorNPZBase64(stored).canonical_bytes(),
+            SerializableIValue::TensorNPYBase64(base64::encode(&vector)).canonical_bytes()
+        );
+
+        let opaque = SerializableIValue::TensorNPZBase64("not npz".to_string());
+        assert_eq!(opaque.canonical_bytes()[0], OPAQUE_ARCHIVE_TAG);
+    }
+
+    #[test]
+    fn test_content_hash_is_stable() {
+        let model_input = input(vec![
+            SerializableIValue::List(vec![
+                SerializableIValue::Str("<bos>".to_string()),
+                SerializableIValue::Str("<eos>".to_string()),
+            ]),
+            SerializableIValue::Int(3),
+            SerializableIValue::Double(0.5),
+        ]);
+        assert_eq!(model_input.content_hash(), 16_027_931_559_956_605_125);
+    }
+}
@@ -1,10 +1,17 @@
 use crate::lightray_torch::errors::InternalTorchError;
-use crate::lightray_torch::tensor::read_npy;
+use crate::lightray_torch::literal::read_literal;
+use crate::lightray_torch::schema::{
+    parse_default_value, TorchScriptMethodSchema, TorchScriptSchema,
+};
+use crate::lightray_torch::tensor::{read_npy, read_npz_entries, write_npy};
 use base64;
 use serde::{Deserialize, Serialize};
+use serde_json::Number;
+use std::borrow::{Borrow, Cow};
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
-use tch::IValue;
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+use tch::{IValue, Tensor};
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum SerializableIValue {
     None,
     Bool(bool),
@@ -15,6 +22,41 @@ pub enum SerializableIValue {
     List(Vec<SerializableIValue>),
     Optional(Option<Box<SerializableIValue>>),
     TensorNPYBase64(String),
+    /// NPY file, as decoded from binary frames. Serialized as base64 like `TensorNPYBase64`.
+    TensorNPY(#[serde(with = "base64_bytes")] Vec<u8>),
+    /// Tensor literal: `dtype` is one of `bool`, `uint8`, `int8`, `int16`, `int32`, `int64`,
+    /// `float32` or `float64` and `data` holds the elements in row-major order.
+    Tensor {
+        dtype: String,
+        shape: Vec<i64>,
+        data: Vec<Number>,
+    },
+    /// Base64 encoded NPZ archive. As a positional argument it expands into one tensor
+    /// argument per array: arrays saved positionally (`arr_0`, `arr_1`, ...) are positional
+    /// arguments in archive order, other arrays are keyword arguments named after the array.
+    TensorNPZBase64(String),
+    /// Dictionary as a list of `[key, value]` pairs. Keys are all strings or all integers.
+    Dict(Vec<(SerializableIValue, SerializableIValue)>),
+}
+
+/// Serde representation of bytes as a base64 string.
+mod base64_bytes {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        base64::decode(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+fn serialize_tensor(tensor: &Tensor) -> Result<SerializableIValue, String> {
+    Ok(SerializableIValue::TensorNPYBase64(base64::encode(
+        &write_npy(tensor)?,
+    )))
 }
 
 impl TryFrom<&IValue> for SerializableIValue {
@@ -56,7 +98,24 @@ impl TryFrom<&IValue> for SerializableIValue {
                     .map(|x| SerializableIValue::Bool(*x))
                     .collect(),
             )),
-            _ => unimplemented!(),
+            IValue::Tensor(tensor_value) => serialize_tensor(tensor_value),
+            IValue::TensorList(tensors_value) => Ok(SerializableIValue::List(
+                tensors_value
+                    .iter()
+                    .map(serialize_tensor)
+                    .collect::<Result<Vec<SerializableIValue>, String>>()?,
+            )),
+            IValue::GenericDict(dict_value) => Ok(SerializableIValue::Dict(
+                dict_value
+                    .iter()
+                    .map(|(key, value)| {
+                        Ok((
+                            SerializableIValue::try_from(key)?,
+                            SerializableIValue::try_from(value)?,
+                        ))
+                    })
+                    .collect::<Result<Vec<(SerializableIValue, SerializableIValue)>, String>>()?,
+            )),
         }
     }
 }
@@ -89,63 +148,455 @@ impl TryFrom<&SerializableIValue> for IValue {
                 Result::Ok(byte_array) => Ok(IValue::Tensor(read_npy(byte_array)?)),
                 Result::Err(y) => Err(y.to_string()),
             },
+            SerializableIValue::TensorNPY(npy) => Ok(IValue::Tensor(read_npy(npy)?)),
+            SerializableIValue::Tensor { dtype, shape, data } => {
+                Ok(IValue::Tensor(read_literal(dtype, shape, data)?))
+            }
+            SerializableIValue::TensorNPZBase64(_) => {
+                Err("NPZ archives are only supported as positional arguments".to_string())
+            }
+            SerializableIValue::Dict(entries) => {
+                let string_keys = entries
+                    .iter()
+                    .all(|(key, _)| matches!(key, SerializableIValue::Str(_)));
+                let int_keys = entries
+                    .iter()
+                    .all(|(key, _)| matches!(key, SerializableIValue::Int(_)));
+                if !string_keys && !int_keys {
+                    return Err("dictionary keys must be all strings or all integers".to_string());
+                }
+                Ok(IValue::GenericDict(
+                    entries
+                        .iter()
+                        .map(|(key, value)| Ok((IValue::try_from(key)?, IValue::try_from(value)?)))
+                        .collect::<Result<Vec<(IValue, IValue)>, String>>()?,
+                ))
+            }
         }
     }
 }
-#[derive(Serialize, Deserialize, Debug)]
+/// Name of the method inputs call unless they name another one.
+pub const FORWARD_METHOD: &str = "forward";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default)]
 pub struct TorchScriptInput {
+    /// Exported method of the module to call, `forward` if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
     pub positional_arguments: Vec<SerializableIValue>,
+    /// Arguments passed by name, resolved against the schema of the method. Arguments after
+    /// the positional ones that are not named take their default value.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub keyword_arguments: BTreeMap<String, SerializableIValue>,
 }
 
-impl PartialEq for TorchScriptInput {
-    fn eq(&self, other: &TorchScriptInput) -> bool {
-        if self.positional_arguments.len() != other.positional_arguments.len() {
-            return false;
-        }
-        for i in 0..self.positional_arguments.len() {
-            if self.positional_arguments[i] != other.positional_arguments[i] {
-                return false;
-            }
-        }
-        true
+impl TorchScriptInput {
+    /// Name of the method this input calls.
+    pub fn method_name(&self) -> &str {
+        self.method.as_deref().unwrap_or(FORWARD_METHOD)
     }
 }
+
 pub struct TorchScriptGraph {
     pub batchable: bool,
+    /// Value used to pad tensor arguments whose non-batch dimensions differ within a batch.
+    /// When `None`, every tensor argument in a batch must have the same non-batch shape.
+    pub batch_padding_value: Option<f64>,
+    /// Whether `forward` always returns the same output for the same input. Outputs of
+    /// deterministic graphs may be served from a response cache.
+    pub deterministic: bool,
+    /// Method schemas of the module, required to resolve keyword arguments.
+    pub schema: Option<TorchScriptSchema>,
     pub module: tch::CModule,
 }
 
+/// A model argument paired with the input argument it was read from.
+type ModelArgument<'a> = (IValue, Cow<'a, SerializableIValue>);
+
+/// Whether `name` is the name numpy gives to arrays saved positionally, e.g. `arr_0`.
+fn is_positional_array_name(name: &str) -> bool {
+    match name.strip_prefix("arr_") {
+        Some(index) => !index.is_empty() && index.bytes().all(|byte| byte.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// Argument of a method call, resolved from a `TorchScriptInput`.
+#[derive(Debug, PartialEq)]
+pub struct ResolvedArgument<'a> {
+    /// Where the argument is given in the input, e.g. `$.keyword_arguments.beam_size` or
+    /// `$.positional_arguments[0].features` for an array of an NPZ argument. Default values are
+    /// located at the keyword argument that was not given.
+    pub path: String,
+    pub value: Cow<'a, SerializableIValue>,
+}
+
+/// Resolves the arguments of `inputs` into the positional arguments of a call to the method
+/// described by `schema`. NPZ archives expand into one NPY tensor per array.
+///
+/// Keyword arguments are placed after the positional ones, at the position of the method
+/// argument of the same name. Arguments between the last positional and the last keyword
+/// argument that are not given take their default value.
+pub fn resolve_arguments<'a>(
+    inputs: &'a TorchScriptInput,
+    schema: Option<&TorchScriptMethodSchema>,
+) -> Result<Vec<ResolvedArgument<'a>>, String> {
+    let mut arguments: Vec<ResolvedArgument<'a>> =
+        Vec::with_capacity(inputs.positional_arguments.len());
+    let mut keyword_arguments: Vec<(String, ResolvedArgument<'a>)> = vec![];
+    for (position, argument) in inputs.positional_arguments.iter().enumerate() {
+        let path = format!("$.positional_arguments[{}]", position);
+        match argument {
+            SerializableIValue::TensorNPZBase64(npz_base64) => {
+                let npz = base64::decode(npz_base64).map_err(|error| error.to_string())?;
+                for (name, npy) in read_npz_entries(&npz)? {
+                    let array = ResolvedArgument {
+                        path: format!("{}.{}", path, name),
+                        value: Cow::Owned(SerializableIValue::TensorNPY(npy)),
+                    };
+                    if is_positional_array_name(&name) {
+                        arguments.push(array);
+                    } else {
+                        keyword_arguments.push((name, array));
+                    }
+                }
+            }
+            _ => arguments.push(ResolvedArgument {
+                path,
+                value: Cow::Borrowed(argument),
+            }),
+        }
+    }
+    for (name, argument) in &inputs.keyword_arguments {
+        keyword_arguments.push((
+            name.clone(),
+            ResolvedArgument {
+                path: format!("$.keyword_arguments.{}", name),
+                value: Cow::Borrowed(argument),
+            },
+        ));
+    }
+    if keyword_arguments.is_empty() {
+        return Ok(arguments);
+    }
+
+    let schema =
+        schema.ok_or_else(|| "keyword arguments require the schema of the method".to_string())?;
+    let positional_count = arguments.len();
+    let mut resolved: Vec<Option<ResolvedArgument<'a>>> = vec![];
+    for (name, argument) in keyword_arguments {
+        let index = schema
+            .arguments
+            .iter()
+            .position(|argument| argument.name == name)
+            .ok_or_else(|| format!("method {} has no argument {}", schema.name, name))?;
+        if index < positional_count {
+            return Err(format!(
+                "argument {} is given both positionally and by keyword",
+                name
+            ));
+        }
+        if resolved.len() <= index - positional_count {
+            resolved.resize_with(index - positional_count + 1, || None);
+        }
+        let slot = &mut resolved[index - positional_count];
+        if slot.is_some() {
+            return Err(format!("argument {} is given more than once", name));
+        }
+        *slot = Some(argument);
+    }
+    for (offset, argument) in resolved.into_iter().enumerate() {
+        match argument {
+            Some(argument) => arguments.push(argument),
+            None => {
+                let argument = &schema.arguments[positional_count + offset];
+                let default_value = argument
+                    .default_value
+                    .as_deref()
+                    .and_then(parse_default_value)
+                    .ok_or_else(|| format!("missing argument {}", argument.name))?;
+                arguments.push(ResolvedArgument {
+                    path: format!("$.keyword_arguments.{}", argument.name),
+                    value: Cow::Owned(default_value),
+                });
+            }
+        }
+    }
+    Ok(arguments)
+}
+
+/// Converts the arguments of `inputs` into the positional arguments of a call to the method
+/// described by `schema`, see `resolve_arguments`.
+fn to_model_inputs<'a>(
+    inputs: &'a TorchScriptInput,
+    schema: Option<&TorchScriptMethodSchema>,
+) -> Result<Vec<ModelArgument<'a>>, String> {
+    resolve_arguments(inputs, schema)?
+        .into_iter()
+        .map(|argument| Ok((IValue::try_from(&*argument.value)?, argument.value)))
+        .collect()
+}
+
+fn batch_tensors(tensors: &[&Tensor], padding_value: Option<f64>) -> Result<Tensor, String> {
+    let sizes: Vec<Vec<i64>> = tensors.iter().map(|tensor| tensor.size()).collect();
+    if sizes.iter().any(|size| size.is_empty()) {
+        return Err("batched tensor arguments require a batch dimension".to_string());
+    }
+    if sizes.iter().any(|size| size.len() != sizes[0].len()) {
+        return Err("batched tensor arguments must have the same number of dimensions".to_string());
+    }
+    if sizes.iter().all(|size| size[1..] == sizes[0][1..]) {
+        return Tensor::f_cat(tensors, 0).map_err(|error| error.to_string());
+    }
+    let padding_value = padding_value.ok_or_else(|| {
+        "batched tensor arguments have different shapes and the model declares no padding"
+            .to_string()
+    })?;
+    let mut padded_size = sizes[0].clone();
+    for size in &sizes[1..] {
+        for (padded_dim, dim) in padded_size.iter_mut().zip(size.iter()).skip(1) {
+            *padded_dim = (*padded_dim).max(*dim);
+        }
+    }
+    let mut padded_tensors: Vec<Tensor> = Vec::with_capacity(tensors.len());
+    for (tensor, size) in tensors.iter().zip(sizes.iter()) {
+        padded_size[0] = size[0];
+        let padded = Tensor::full(
+            &padded_size,
+            padding_value,
+            (tensor.kind(), tensor.device()),
+        );
+        let mut region = padded.shallow_clone();
+        for (dim, length) in size.iter().enumerate() {
+            region = region
+                .f_narrow(dim as i64, 0, *length)
+                .map_err(|error| error.to_string())?;
+        }
+        region.f_copy_(tensor).map_err(|error| error.to_string())?;
+        padded_tensors.push(padded);
+    }
+    Tensor::f_cat(&padded_tensors, 0).map_err(|error| error.to_string())
+}
+
+fn split_batched_elements(
+    elements: &[IValue],
+    batch_sizes: &[i64],
+) -> Result<Vec<Vec<IValue>>, String> {
+    let mut per_input: Vec<Vec<IValue>> = (0..batch_sizes.len())
+        .map(|_| Vec::with_capacity(elements.len()))
+        .collect();
+    for element in elements {
+        for (split, values) in split_batched_output(element, batch_sizes)?
+            .into_iter()
+            .zip(per_input.iter_mut())
+        {
+            values.push(split);
+        }
+    }
+    Ok(per_input)
+}
+
+fn split_batched_output(output: &IValue, batch_sizes: &[i64]) -> Result<Vec<IValue>, String> {
+    match output {
+        IValue::Tensor(tensor) => {
+            let total_batch_size: i64 = batch_sizes.iter().sum();
+            if tensor.size().first() != Some(&total_batch_size) {
+                return Err(format!(
+                    "batched output has batch dimension {:?} but {} inputs were batched",
+                    tensor.size().first(),
+                    total_batch_size
+                ));
+            }
+            let mut offset = 0;
+            let mut splits = Vec::with_capacity(batch_sizes.len());
+            for batch_size in batch_sizes {
+                let split = tensor
+                    .f_narrow(0, offset, *batch_size)
+                    .map_err(|error| error.to_string())?;
+                splits.push(IValue::Tensor(split));
+                offset += batch_size;
+            }
+            Ok(splits)
+        }
+        IValue::TensorList(tensors) => {
+            let elements: Vec<IValue> = tensors
+                .iter()
+                .map(|tensor| IValue::Tensor(tensor.shallow_clone()))
+                .collect();
+            Ok(split_batched_elements(&elements, batch_sizes)?
+                .into_iter()
+                .map(|values| {
+                    IValue::TensorList(
+                        values
+                            .into_iter()
+                            .filter_map(|value| match value {
+                                IValue::Tensor(tensor) => Some(tensor),
+                                _ => None,
+                            })
+                            .collect(),
+                    )
+                })
+                .collect())
+        }
+        IValue::Tuple(elements) => Ok(split_batched_elements(elements, batch_sizes)?
+            .into_iter()
+            .map(IValue::Tuple)
+            .collect()),
+        IValue::GenericList(elements) => Ok(split_batched_elements(elements, batch_sizes)?
+            .into_iter()
+            .map(IValue::GenericList)
+            .collect()),
+        _ => Err("batched output must be a tensor, or a tuple or list of tensors".to_string()),
+    }
+}
+
 impl TorchScriptGraph {
+    fn method_schema(&self, method: &str) -> Option<&TorchScriptMethodSchema> {
+        self.schema
+            .as_ref()
+            .and_then(|schema| schema.method(method))
+    }
+
+    fn call_method(&self, method: &str, model_inputs: &[IValue]) -> Result<IValue, String> {
+        let model_output = if method == FORWARD_METHOD {
+            self.module.forward_is(model_inputs)
+        } else {
+            self.module.method_is(method, model_inputs)
+        };
+        model_output.map_err(|error| error.to_string())
+    }
+
+    /// Whether the module exports `method`. Without a schema every method is assumed to be
+    /// exported, calling one that is not fails.
+    pub fn has_method(&self, method: &str) -> bool {
+        match &self.schema {
+            Some(schema) => schema.method(method).is_some(),
+            None => true,
+        }
+    }
+
+    /// Calls the method named by `inputs`, `forward` unless it names another one.
     pub fn forward(
         &self,
         inputs: &TorchScriptInput,
     ) -> Result<SerializableIValue, InternalTorchError> {
-        let model_inputs: Vec<IValue> = inputs
-            .positional_arguments
+        let method = inputs.method_name();
+        let model_inputs: Vec<IValue> = to_model_inputs(inputs, self.method_schema(method))?
+            .into_iter()
+            .map(|(model_input, _)| model_input)
+            .collect();
+
+        let model_output = self.call_method(method, &model_inputs)?;
+        Ok(SerializableIValue::try_from(&model_output)?)
+    }
+
+    /// Runs every input through a single call of the method they name, which must be the
+    /// same for all inputs.
+    ///
+    /// Tensor arguments are concatenated along their first (batch) dimension, non-tensor
+    /// arguments must be identical across all inputs. The output is split back along the
+    /// batch dimension so that each input receives its own result. Padded dimensions of
+    /// the output are not narrowed back. Graphs that are not batchable return an error.
+    pub fn forward_batched<T: Borrow<TorchScriptInput>>(
+        &self,
+        inputs: &[T],
+    ) -> Result<Vec<SerializableIValue>, InternalTorchError> {
+        if !self.batchable {
+            return Err(InternalTorchError::from(
+                "forward_batched can only be called on batchable TorchScriptGraph's".to_string(),
+            ));
+        }
+        if inputs.is_empty() {
+            return Ok(vec![]);
+        }
+        let method = inputs[0].borrow().method_name();
+        if inputs
             .iter()
-            .map(IValue::try_from)
-            .collect::<Result<Vec<IValue>, String>>()?;
-
-        let model_output = self.module.forward_is(&model_inputs);
-        match model_output {
-            Result::Ok(true_model_output) => Ok(SerializableIValue::try_from(&true_model_output)?),
-            Result::Err(error) => Err(InternalTorchError {
-                internal_error: error.to_string(),
-            }),
+            .any(|input| input.borrow().method_name() != method)
+        {
+            return Err(InternalTorchError::from(
+                "batched inputs must call the same method".to_string(),
+            ));
         }
-    }
-    pub fn forward_batched(&self) {
-        assert!(
-            self.batchable,
-            r#"forward_batched can only be called on batchable TorchScriptGraph's"#
-        );
-        todo!()
+        let schema = self.method_schema(method);
+        let per_input_arguments: Vec<Vec<ModelArgument>> = inputs
+            .iter()
+            .map(|input| to_model_inputs(input.borrow(), schema))
+            .collect::<Result<Vec<Vec<ModelArgument>>, String>>()?;
+        let argument_count = per_input_arguments[0].len();
+        if per_input_arguments
+            .iter()
+            .any(|arguments| arguments.len() != argument_count)
+        {
+            return Err(InternalTorchError::from(
+                "batched inputs must have the same number of arguments".to_string(),
+            ));
+        }
+
+        let mut batch_sizes: Option<Vec<i64>> = None;
+        let mut model_inputs: Vec<IValue> = Vec::with_capacity(argument_count);
+        for position in 0..argument_count {
+            if let IValue::Tensor(_) = &per_input_arguments[0][position].0 {
+                let tensors = per_input_arguments
+                    .iter()
+                    .map(|arguments| match &arguments[position].0 {
+                        IValue::Tensor(tensor) => Ok(tensor),
+                        _ => Err(format!(
+                            "argument at position {} must be a tensor for every batched input",
+                            position
+                        )),
+                    })
+                    .collect::<Result<Vec<&Tensor>, String>>()?;
+                let sizes: Vec<i64> = tensors
+                    .iter()
+                    .map(|tensor| tensor.size().first().copied().unwrap_or(0))
+                    .collect();
+                match &batch_sizes {
+                    Some(expected_sizes) if *expected_sizes != sizes => {
+                        return Err(InternalTorchError::from(format!(
+                            "argument at position {} has a different batch size than previous tensor arguments",
+                            position
+                        )));
+                    }
+                    Some(_) => {}
+                    None => batch_sizes = Some(sizes),
+                }
+                model_inputs.push(IValue::Tensor(batch_tensors(
+                    &tensors,
+                    self.batch_padding_value,
+                )?));
+            } else {
+                let shared_argument: &SerializableIValue = &per_input_arguments[0][position].1;
+                if per_input_arguments
+                    .iter()
+                    .any(|arguments| *arguments[position].1 != *shared_argument)
+                {
+                    return Err(InternalTorchError::from(format!(
+                        "non-tensor argument at position {} must be equal for every batched input",
+                        position
+                    )));
+                }
+                model_inputs.push(IValue::try_from(shared_argument)?);
+            }
+        }
+        let batch_sizes = batch_sizes.ok_or_else(|| {
+            InternalTorchError::from(
+                "batched inputs require at least one tensor argument".to_string(),
+            )
+        })?;
+
+        let model_output = self.call_method(method, &model_inputs)?;
+        Ok(split_batched_output(&model_output, &batch_sizes)?
+            .iter()
+            .map(SerializableIValue::try_from)
+            .collect::<Result<Vec<SerializableIValue>, String>>()?)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lightray_torch::schema::TorchScriptArgument;
 
     #[test]
     fn test_serialization() {
@@ -161,9 +612,236 @@ mod tests {
                 SerializableIValue::Int(3),
                 SerializableIValue::Int(3),
             ],
+            ..Default::default()
         };
         let serialized = serde_json::to_string(&torchscript_input).unwrap();
         let unserialized: TorchScriptInput = serde_json::from_str(&serialized).unwrap();
         assert_eq!(torchscript_input, unserialized)
     }
+
+    #[test]
+    fn test_keyword_arguments_serialization() {
+        let positional_only: TorchScriptInput =
+            serde_json::from_str(r#"{"positional_arguments": [{"Int": 1}]}"#).unwrap();
+        assert!(positional_only.keyword_arguments.is_empty());
+        assert_eq!(
+            serde_json::to_string(&positional_only).unwrap(),
+            r#"{"positional_arguments":[{"Int":1}]}"#
+        );
+
+        let with_keywords: TorchScriptInput = serde_json::from_str(
+            r#"{"positional_arguments": [], "keyword_arguments": {"top_k": {"Int": 3}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            with_keywords.keyword_arguments.get("top_k"),
+            Some(&SerializableIValue::Int(3))
+        );
+    }
+
+    #[test]
+    fn test_dict_input() {
+        let dict: SerializableIValue =
+            serde_json::from_str(r#"{"Dict": [[{"Str": "a"}, {"Int": 1}]]}"#).unwrap();
+        assert_eq!(
+            dict,
+            SerializableIValue::Dict(vec![(
+                SerializableIValue::Str("a".to_string()),
+                SerializableIValue::Int(1),
+            )])
+        );
+        match IValue::try_from(&dict).unwrap() {
+            IValue::GenericDict(entries) => match &entries[..] {
+                [(IValue::String(key), IValue::Int(1))] => assert_eq!(key, "a"),
+                _ => panic!("expected a single entry"),
+            },
+            _ => panic!("expected a dictionary"),
+        }
+
+        let mixed_keys = SerializableIValue::Dict(vec![
+            (
+                SerializableIValue::Str("a".to_string()),
+                SerializableIValue::None,
+            ),
+            (SerializableIValue::Int(1), SerializableIValue::None),
+        ]);
+        assert!(IValue::try_from(&mixed_keys).is_err());
+        let double_keys = SerializableIValue::Dict(vec![(
+            SerializableIValue::Double(1.0),
+            SerializableIValue::None,
+        )]);
+        assert!(IValue::try_from(&double_keys).is_err());
+    }
+
+    fn method_schema() -> TorchScriptMethodSchema {
+        let argument = |name: &str, default_value: Option<&str>| TorchScriptArgument {
+            name: name.to_string(),
+            type_name: "int".to_string(),
+            default_value: default_value.map(str::to_string),
+        };
+        TorchScriptMethodSchema {
+            name: "forward".to_string(),
+            arguments: vec![
+                argument("tokens", None),
+                argument("beam_size", Some("3")),
+                argument("top_k", None),
+                argument("temperature", Some("torch.zeros([1])")),
+            ],
+            return_type: Some("List[str]".to_string()),
+        }
+    }
+
+    fn sources<'a>(model_inputs: &'a [ModelArgument]) -> Vec<&'a SerializableIValue> {
+        model_inputs.iter().map(|(_, source)| &**source).collect()
+    }
+
+    #[test]
+    fn test_resolve_keyword_arguments() {
+        let schema = method_schema();
+        let mut input = TorchScriptInput {
+            positional_arguments: vec![SerializableIValue::Int(1)],
+            ..Default::default()
+        };
+        input
+            .keyword_arguments
+            .insert("top_k".to_string(), SerializableIValue::Int(5));
+        let model_inputs = to_model_inputs(&input, Some(&schema)).unwrap();
+        assert_eq!(
+            sources(&model_inputs),
+            vec![
+                &SerializableIValue::Int(1),
+                &SerializableIValue::Int(3),
+                &SerializableIValue::Int(5),
+            ]
+        );
+        assert!(to_model_inputs(&input, None).is_err());
+
+        input
+            .keyword_arguments
+            .insert("tokens".to_string(), SerializableIValue::Int(2));
+        assert!(to_model_inputs(&input, Some(&schema)).is_err());
+
+        let mut unknown = TorchScriptInput::default();
+        unknown
+            .keyword_arguments
+            .insert("length".to_string(), SerializableIValue::Int(2));
+        assert!(to_model_inputs(&unknown, Some(&schema)).is_err());
+
+        // Defaults that are not literals cannot be filled in, required arguments never are.
+        let mut skips_required = TorchScriptInput::default();
+        skips_required
+            .keyword_arguments
+            .insert("beam_size".to_string(), SerializableIValue::Int(2));
+        assert!(to_model_inputs(&skips_required, Some(&schema)).is_err());
+    }
+
+    #[test]
+    fn test_positional_array_names() {
+        assert!(is_positional_array_name("arr_0"));
+        assert!(is_positional_array_name("arr_12"));
+        assert!(!is_positional_array_name("arr_"));
+        assert!(!is_positional_array_name("arr_x"));
+        assert!(!is_positional_array_name("features"));
+    }
+
+    #[test]
+    fn test_tensor_literal_serialization() {
+        let literal: SerializableIValue = serde_json::from_str(
+            r#"{"Tensor": {"dtype": "float32", "shape": [2], "data": [1, 2.5]}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            literal,
+            SerializableIValue::Tensor {
+                dtype: "float32".to_string(),
+                shape: vec![2],
+                data: vec![Number::from(1), Number::from_f64(2.5).unwrap()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_npy_bytes_serialize_as_base64() {
+        let tensor = SerializableIValue::TensorNPY(b"\x93NUMPY".to_vec());
+        let serialized = serde_json::to_string(&tensor).unwrap();
+        assert_eq!(serialized, r#"{"TensorNPY":"k05VTVBZ"}"#);
+        assert_eq!(
+            serde_json::from_str::<SerializableIValue>(&serialized).unwrap(),
+            tensor
+        );
+    }
+
+    #[test]
+    fn test_npz_arguments_are_positional() {
+        let archive = SerializableIValue::TensorNPZBase64(String::new());
+        assert!(IValue::try_from(&archive).is_err());
+        assert!(IValue::try_from(&SerializableIValue::List(vec![archive])).is_err());
+    }
+
+    #[test]
+    fn test_tensor_output() {
+        let tensor = Tensor::of_slice(&[1f32, 2.0, 3.0, 4.0]).reshape(&[2, 2]);
+        let serialized = SerializableIValue::try_from(&IValue::Tensor(tensor)).unwrap();
+        match IValue::try_from(&serialized).unwrap() {
+            IValue::Tensor(tensor) => {
+                assert_eq!(tensor.size(), &[2, 2]);
+                assert_eq!(Vec::<f32>::from(&tensor), vec![1f32, 2.0, 3.0, 4.0]);
+            }
+            _ => panic!("expected a tensor"),
+        }
+
+        let tensors =
+            IValue::TensorList(vec![Tensor::of_slice(&[1i64]), Tensor::of_slice(&[2i64])]);
+        match SerializableIValue::try_from(&tensors).unwrap() {
+            SerializableIValue::List(values) => assert_eq!(values.len(), 2),
+            _ => panic!("expected a list"),
+        }
+    }
+
+    #[test]
+    fn test_dict_output() {
+        let dict = IValue::GenericDict(vec![(
+            IValue::String("answer".to_string()),
+            IValue::Int(42),
+        )]);
+        assert_eq!(
+            SerializableIValue::try_from(&dict).unwrap(),
+            SerializableIValue::Dict(vec![(
+                SerializableIValue::Str("answer".to_string()),
+                SerializableIValue::Int(42),
+            )])
+        );
+    }
+
+    #[test]
+    fn test_batch_tensors_with_padding() {
+        let first = Tensor::of_slice(&[1f32, 2.0]).reshape(&[1, 2]);
+        let second = Tensor::of_slice(&[3f32, 4.0, 5.0, 6.0, 7.0, 8.0]).reshape(&[2, 3]);
+        assert!(batch_tensors(&[&first, &second], None).is_err());
+
+        let batched = batch_tensors(&[&first, &second], Some(0.0)).unwrap();
+        assert_eq!(batched.size(), &[3, 3]);
+        assert_eq!(
+            Vec::<f32>::from(&batched.narrow(0, 0, 1).reshape(&[3])),
+            &[1.0, 2.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_split_batched_output() {
+        let output = IValue::Tuple(vec![
+            IValue::Tensor(Tensor::of_slice(&[1i64, 2, 3])),
+            IValue::Tensor(Tensor::of_slice(&[4i64, 5, 6])),
+        ]);
+        let splits = split_batched_output(&output, &[1, 2]).unwrap();
+        assert_eq!(splits.len(), 2);
+        match &splits[1] {
+            IValue::Tuple(elements) => match &elements[1] {
+                IValue::Tensor(tensor) => assert_eq!(Vec::<i64>::from(tensor), &[5, 6]),
+                _ => panic!("split should keep tensors"),
+            },
+            _ => panic!("split should keep tuples"),
+        }
+        assert!(split_batched_output(&IValue::Int(3), &[1, 2]).is_err());
+    }
 }
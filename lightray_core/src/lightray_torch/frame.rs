@@ -0,0 +1,415 @@
+//! Binary frames: a compact alternative to JSON for transporting model inputs and outputs.
+//!
+//! A frame is the magic string `LRF1` followed by one encoded value. Every value encodes as a
+//! one byte tag followed by its payload, integers, doubles and lengths are little-endian and
+//! strings, sequences and byte payloads are prefixed by their length. Tensors, including tensor
+//! literals, travel as raw NPY files and decode into `SerializableIValue::TensorNPY`, so no
+//! base64 step is involved. NPZ archives travel as their base64 string.
+//!
+//! The positional arguments of a `TorchScriptInput` are framed as a tuple, followed by a
+//! dictionary with string keys if the input has keyword arguments. Frames carry arguments
+//! only, the method an input calls is named outside of the frame.
+use crate::lightray_torch::core::{SerializableIValue, TorchScriptInput};
+use crate::lightray_torch::literal::literal_npy;
+use base64;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+const FRAME_MAGIC: &[u8] = b"LRF1";
+/// Frames nest at most this deep, matching the recursion limit of the JSON parser.
+const MAX_FRAME_DEPTH: usize = 128;
+
+const NONE_TAG: u8 = 0;
+const BOOL_TAG: u8 = 1;
+const INT_TAG: u8 = 2;
+const DOUBLE_TAG: u8 = 3;
+const STR_TAG: u8 = 4;
+const TUPLE_TAG: u8 = 5;
+const LIST_TAG: u8 = 6;
+const OPTIONAL_TAG: u8 = 7;
+const NPY_TAG: u8 = 8;
+const NPZ_BASE64_TAG: u8 = 9;
+const DICT_TAG: u8 = 10;
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u64).to_le_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_len(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+fn write_sequence(out: &mut Vec<u8>, tag: u8, values: &[SerializableIValue]) -> Result<(), String> {
+    out.push(tag);
+    write_len(out, values.len());
+    for value in values {
+        write_value(out, value)?;
+    }
+    Ok(())
+}
+
+fn write_value(out: &mut Vec<u8>, value: &SerializableIValue) -> Result<(), String> {
+    match value {
+        SerializableIValue::None => out.push(NONE_TAG),
+        SerializableIValue::Bool(bool_value) => {
+            out.push(BOOL_TAG);
+            out.push(*bool_value as u8);
+        }
+        SerializableIValue::Int(int_value) => {
+            out.push(INT_TAG);
+            out.extend_from_slice(&int_value.to_le_bytes());
+        }
+        SerializableIValue::Double(double_value) => {
+            out.push(DOUBLE_TAG);
+            out.extend_from_slice(&double_value.to_bits().to_le_bytes());
+        }
+        SerializableIValue::Str(string_value) => {
+            out.push(STR_TAG);
+            write_bytes(out, string_value.as_bytes());
+        }
+        SerializableIValue::Tuple(tuple_value) => write_sequence(out, TUPLE_TAG, tuple_value)?,
+        SerializableIValue::List(list_value) => write_sequence(out, LIST_TAG, list_value)?,
+        SerializableIValue::Optional(optional) => {
+            out.push(OPTIONAL_TAG);
+            match optional {
+                Option::None => out.push(0),
+                Option::Some(value) => {
+                    out.push(1);
+                    write_value(out, value)?;
+                }
+            }
+        }
+        SerializableIValue::TensorNPYBase64(npy_base64) => {
+            out.push(NPY_TAG);
+            write_bytes(
+                out,
+                &base64::decode(npy_base64).map_err(|error| error.to_string())?,
+            );
+        }
+        SerializableIValue::TensorNPY(npy) => {
+            out.push(NPY_TAG);
+            write_bytes(out, npy);
+        }
+        SerializableIValue::Tensor { dtype, shape, data } => {
+            out.push(NPY_TAG);
+            write_bytes(out, &literal_npy(dtype, shape, data)?);
+        }
+        SerializableIValue::TensorNPZBase64(npz_base64) => {
+            out.push(NPZ_BASE64_TAG);
+            write_bytes(out, npz_base64.as_bytes());
+        }
+        SerializableIValue::Dict(entries) => {
+            out.push(DICT_TAG);
+            write_len(out, entries.len());
+            for (key, value) in entries {
+                write_value(out, key)?;
+                write_value(out, value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+struct FrameReader<'a> {
+    frame: &'a [u8],
+    position: usize,
+}
+
+impl<'a> FrameReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.frame.len() - self.position < len {
+            return Err(format!("truncated frame at byte {}", self.position));
+        }
+        let bytes = &self.frame[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn read_len(&mut self) -> Result<usize, String> {
+        let len = self.read_u64()?;
+        if len > (self.frame.len() - self.position) as u64 {
+            return Err(format!("truncated frame at byte {}", self.position));
+        }
+        Ok(len as usize)
+    }
+
+    fn read_bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.read_len()?;
+        self.take(len)
+    }
+
+    fn read_string(&mut self) -> Result<String, String> {
+        let position = self.position;
+        String::from_utf8(self.read_bytes()?.to_vec())
+            .map_err(|_| format!("invalid UTF-8 string at byte {}", position))
+    }
+
+    fn read_sequence(&mut self, depth: usize) -> Result<Vec<SerializableIValue>, String> {
+        // Every value takes at least one byte, which bounds the length by the frame size.
+        let len = self.read_len()?;
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len {
+            values.push(self.read_value(depth + 1)?);
+        }
+        Ok(values)
+    }
+
+    fn read_dict(
+        &mut self,
+        depth: usize,
+    ) -> Result<Vec<(SerializableIValue, SerializableIValue)>, String> {
+        let len = self.read_len()?;
+        let mut entries = Vec::with_capacity(len);
+        for _ in 0..len {
+            let key = self.read_value(depth + 1)?;
+            entries.push((key, self.read_value(depth + 1)?));
+        }
+        Ok(entries)
+    }
+
+    fn read_value(&mut self, depth: usize) -> Result<SerializableIValue, String> {
+        if depth > MAX_FRAME_DEPTH {
+            return Err(format!(
+                "frame nests deeper than {} levels",
+                MAX_FRAME_DEPTH
+            ));
+        }
+        let position = self.position;
+        match self.read_u8()? {
+            NONE_TAG => Ok(SerializableIValue::None),
+            BOOL_TAG => match self.read_u8()? {
+                0 => Ok(SerializableIValue::Bool(false)),
+                1 => Ok(SerializableIValue::Bool(true)),
+                other => Err(format!("invalid bool {} at byte {}", other, position + 1)),
+            },
+            INT_TAG => Ok(SerializableIValue::Int(self.read_u64()? as i64)),
+            DOUBLE_TAG => Ok(SerializableIValue::Double(f64::from_bits(self.read_u64()?))),
+            STR_TAG => Ok(SerializableIValue::Str(self.read_string()?)),
+            TUPLE_TAG => Ok(SerializableIValue::Tuple(self.read_sequence(depth)?)),
+            LIST_TAG => Ok(SerializableIValue::List(self.read_sequence(depth)?)),
+            OPTIONAL_TAG => match self.read_u8()? {
+                0 => Ok(SerializableIValue::Optional(None)),
+                1 => Ok(SerializableIValue::Optional(Some(Box::new(
+                    self.read_value(depth + 1)?,
+                )))),
+                other => Err(format!(
+                    "invalid optional marker {} at byte {}",
+                    other,
+                    position + 1
+                )),
+            },
+            NPY_TAG => Ok(SerializableIValue::TensorNPY(self.read_bytes()?.to_vec())),
+            NPZ_BASE64_TAG => Ok(SerializableIValue::TensorNPZBase64(self.read_string()?)),
+            DICT_TAG => Ok(SerializableIValue::Dict(self.read_dict(depth)?)),
+            other => Err(format!("unknown tag {} at byte {}", other, position)),
+        }
+    }
+}
+
+/// Encodes `value` as a frame. Fails if a base64 tensor payload does not decode or a tensor
+/// literal is invalid.
+pub fn encode_frame(value: &SerializableIValue) -> Result<Vec<u8>, String> {
+    let mut out = FRAME_MAGIC.to_vec();
+    write_value(&mut out, value)?;
+    Ok(out)
+}
+
+/// Decodes a frame written by `encode_frame`.
+pub fn decode_frame(frame: &[u8]) -> Result<SerializableIValue, String> {
+    if !frame.starts_with(FRAME_MAGIC) {
+        return Err("magic string mismatch".to_string());
+    }
+    let mut reader = FrameReader {
+        frame,
+        position: FRAME_MAGIC.len(),
+    };
+    let value = reader.read_value(0)?;
+    if reader.position != frame.len() {
+        return Err(format!("trailing data at byte {}", reader.position));
+    }
+    Ok(value)
+}
+
+impl TorchScriptInput {
+    /// Encodes the arguments of this input as a frame.
+    pub fn encode_frame(&self) -> Result<Vec<u8>, String> {
+        let mut out = FRAME_MAGIC.to_vec();
+        write_sequence(&mut out, TUPLE_TAG, &self.positional_arguments)?;
+        if !self.keyword_arguments.is_empty() {
+            out.push(DICT_TAG);
+            write_len(&mut out, self.keyword_arguments.len());
+            for (name, value) in &self.keyword_arguments {
+                out.push(STR_TAG);
+                write_bytes(&mut out, name.as_bytes());
+                write_value(&mut out, value)?;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Decodes an input from a frame holding a tuple of its positional arguments, optionally
+    /// followed by a dictionary of its keyword arguments.
+    pub fn decode_frame(frame: &[u8]) -> Result<TorchScriptInput, String> {
+        if !frame.starts_with(FRAME_MAGIC) {
+            return Err("magic string mismatch".to_string());
+        }
+        let mut reader = FrameReader {
+            frame,
+            position: FRAME_MAGIC.len(),
+        };
+        let positional_arguments = match reader.read_value(0)? {
+            SerializableIValue::Tuple(positional_arguments) => positional_arguments,
+            _ => return Err("input frame must hold a tuple of arguments".to_string()),
+        };
+        let mut keyword_arguments = BTreeMap::new();
+        if reader.position != frame.len() {
+            let position = reader.position;
+            let entries = match reader.read_value(0)? {
+                SerializableIValue::Dict(entries) => entries,
+                _ => {
+                    return Err(format!(
+                        "keyword arguments at byte {} must be a dictionary",
+                        position
+                    ))
+                }
+            };
+            for (name, value) in entries {
+                match name {
+                    SerializableIValue::Str(name) => {
+                        keyword_arguments.insert(name, value);
+                    }
+                    _ => {
+                        return Err(format!(
+                            "keyword argument names at byte {} must be strings",
+                            position
+                        ))
+                    }
+                }
+            }
+        }
+        if reader.position != frame.len() {
+            return Err(format!("trailing data at byte {}", reader.position));
+        }
+        Ok(TorchScriptInput {
+            positional_arguments,
+            keyword_arguments,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input() -> TorchScriptInput {
+        let mut keyword_arguments = BTreeMap::new();
+        keyword_arguments.insert("top_k".to_string(), SerializableIValue::Int(3));
+        TorchScriptInput {
+            positional_arguments: vec![
+                SerializableIValue::List(vec![
+                    SerializableIValue::Str("<bos>".to_string()),
+                    SerializableIValue::Str("<eos>".to_string()),
+                ]),
+                SerializableIValue::Optional(Some(Box::new(SerializableIValue::Double(-0.0)))),
+                SerializableIValue::Optional(None),
+                SerializableIValue::Tuple(vec![
+                    SerializableIValue::Bool(true),
+                    SerializableIValue::Int(-3),
+                    SerializableIValue::None,
+                ]),
+                SerializableIValue::TensorNPY(b"\x93NUMPY".to_vec()),
+                SerializableIValue::TensorNPZBase64("UEsFBg==".to_string()),
+                SerializableIValue::Dict(vec![(
+                    SerializableIValue::Int(7),
+                    SerializableIValue::Str("seven".to_string()),
+                )]),
+            ],
+            keyword_arguments,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = input();
+        let frame = input.encode_frame().unwrap();
+        assert!(frame.starts_with(FRAME_MAGIC));
+        assert_eq!(TorchScriptInput::decode_frame(&frame).unwrap(), input);
+
+        let value = SerializableIValue::Double(f64::NAN);
+        match decode_frame(&encode_frame(&value).unwrap()).unwrap() {
+            SerializableIValue::Double(double_value) => assert!(double_value.is_nan()),
+            _ => panic!("expected a double"),
+        }
+    }
+
+    #[test]
+    fn test_base64_tensors_travel_raw() {
+        let npy = b"\x93NUMPY\x01\x00".to_vec();
+        let frame =
+            encode_frame(&SerializableIValue::TensorNPYBase64(base64::encode(&npy))).unwrap();
+        assert_eq!(
+            decode_frame(&frame).unwrap(),
+            SerializableIValue::TensorNPY(npy)
+        );
+        assert!(encode_frame(&SerializableIValue::TensorNPYBase64("@@".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_malformed_frames() {
+        let frame = input().encode_frame().unwrap();
+        let positional_only = TorchScriptInput {
+            positional_arguments: input().positional_arguments,
+            ..Default::default()
+        };
+        let positional_len = positional_only.encode_frame().unwrap().len();
+        for len in 0..frame.len() {
+            // The positional arguments alone form a valid input.
+            if len != positional_len {
+                assert!(TorchScriptInput::decode_frame(&frame[..len]).is_err());
+            }
+        }
+        let mut trailing = frame.clone();
+        trailing.push(NONE_TAG);
+        assert!(TorchScriptInput::decode_frame(&trailing).is_err());
+
+        let mut not_a_dict = positional_only.encode_frame().unwrap();
+        not_a_dict.push(NONE_TAG);
+        assert!(TorchScriptInput::decode_frame(&not_a_dict).is_err());
+        let mut int_names = positional_only.encode_frame().unwrap();
+        int_names.extend_from_slice(
+            &encode_frame(&SerializableIValue::Dict(vec![(
+                SerializableIValue::Int(1),
+                SerializableIValue::None,
+            )]))
+            .unwrap()[FRAME_MAGIC.len()..],
+        );
+        assert!(TorchScriptInput::decode_frame(&int_names).is_err());
+
+        let not_a_tuple = encode_frame(&SerializableIValue::Int(1)).unwrap();
+        assert!(TorchScriptInput::decode_frame(&not_a_tuple).is_err());
+
+        let mut huge_list = FRAME_MAGIC.to_vec();
+        huge_list.push(LIST_TAG);
+        huge_list.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert!(decode_frame(&huge_list).is_err());
+
+        let mut nested = FRAME_MAGIC.to_vec();
+        for _ in 0..=MAX_FRAME_DEPTH {
+            nested.push(OPTIONAL_TAG);
+            nested.push(1);
+        }
+        nested.push(NONE_TAG);
+        assert!(decode_frame(&nested).is_err());
+    }
+}
@@ -0,0 +1,479 @@
+//! Tensor literals: tensors written out in JSON as `SerializableIValue::Tensor`, with their
+//! elements as plain numbers in row-major order.
+//!
+//! A literal is interpreted as the NPY file of the same dtype, shape and little-endian data,
+//! which lets literals share the tensor conversion, hashing and framing of NPY payloads.
+use crate::lightray_torch::core::SerializableIValue;
+use crate::lightray_torch::tensor::{element_size, encode_npy, native_data, split_npy, Header};
+use serde_json::Number;
+use std::convert::{TryFrom, TryInto};
+use tch::{Kind, Tensor};
+
+/// Kind of the literal dtype `dtype`.
+fn dtype_kind(dtype: &str) -> Option<Kind> {
+    match dtype {
+        "bool" => Some(Kind::Bool),
+        "uint8" => Some(Kind::Uint8),
+        "int8" => Some(Kind::Int8),
+        "int16" => Some(Kind::Int16),
+        "int32" => Some(Kind::Int),
+        "int64" => Some(Kind::Int64),
+        "float32" => Some(Kind::Float),
+        "float64" => Some(Kind::Double),
+        _ => None,
+    }
+}
+
+/// Literal dtype of `kind`, for kinds that can be written as literals.
+fn kind_dtype(kind: Kind) -> Option<&'static str> {
+    match kind {
+        Kind::Bool => Some("bool"),
+        Kind::Uint8 => Some("uint8"),
+        Kind::Int8 => Some("int8"),
+        Kind::Int16 => Some("int16"),
+        Kind::Int => Some("int32"),
+        Kind::Int64 => Some("int64"),
+        Kind::Float => Some("float32"),
+        Kind::Double => Some("float64"),
+        _ => None,
+    }
+}
+
+/// Dtype name of `kind`: its literal dtype, or `float16` for half precision tensors, which
+/// have no literal.
+fn dtype_name(kind: Kind) -> Option<&'static str> {
+    match kind {
+        Kind::Half => Some("float16"),
+        kind => kind_dtype(kind),
+    }
+}
+
+/// Number of elements of a tensor of `shape`, `None` for invalid shapes.
+pub(crate) fn element_count(shape: &[i64]) -> Option<usize> {
+    shape.iter().try_fold(1usize, |count, dimension| {
+        usize::try_from(*dimension)
+            .ok()
+            .and_then(|dimension| count.checked_mul(dimension))
+    })
+}
+
+fn integer_element<T: TryFrom<i64>>(dtype: &str, element: &Number) -> Result<T, String> {
+    element
+        .as_i64()
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or_else(|| format!("{} is not a valid {} element", element, dtype))
+}
+
+fn write_element(
+    out: &mut Vec<u8>,
+    dtype: &str,
+    kind: Kind,
+    element: &Number,
+) -> Result<(), String> {
+    match kind {
+        Kind::Bool => match element.as_u64() {
+            Some(value) if value <= 1 => out.push(value as u8),
+            _ => return Err(format!("{} is not a valid bool element", element)),
+        },
+        Kind::Uint8 => out.push(integer_element::<u8>(dtype, element)?),
+        Kind::Int8 => out.extend_from_slice(&integer_element::<i8>(dtype, element)?.to_le_bytes()),
+        Kind::Int16 => {
+            out.extend_from_slice(&integer_element::<i16>(dtype, element)?.to_le_bytes())
+        }
+        Kind::Int => out.extend_from_slice(&integer_element::<i32>(dtype, element)?.to_le_bytes()),
+        Kind::Int64 => {
+            out.extend_from_slice(&integer_element::<i64>(dtype, element)?.to_le_bytes())
+        }
+        Kind::Float | Kind::Double => {
+            let value = element
+                .as_f64()
+                .ok_or_else(|| format!("{} is not a valid {} element", element, dtype))?;
+            if kind == Kind::Float {
+                // Doubles beyond the float32 range would silently become infinite.
+                let value = value as f32;
+                if !value.is_finite() {
+                    return Err(format!("{} is not a valid {} element", element, dtype));
+                }
+                out.extend_from_slice(&value.to_le_bytes());
+            } else {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        _ => return Err(format!("unsupported tensor dtype {}", dtype)),
+    }
+    Ok(())
+}
+
+/// NPY header and little-endian data of a literal.
+pub(crate) fn literal_npy_parts(
+    dtype: &str,
+    shape: &[i64],
+    data: &[Number],
+) -> Result<(Header, Vec<u8>), String> {
+    let kind = dtype_kind(dtype).ok_or_else(|| format!("unsupported tensor dtype {}", dtype))?;
+    let numel = element_count(shape).ok_or_else(|| format!("invalid tensor shape {:?}", shape))?;
+    if numel != data.len() {
+        return Err(format!(
+            "tensor of shape {:?} needs {} elements, got {}",
+            shape,
+            numel,
+            data.len()
+        ));
+    }
+    let mut bytes = Vec::with_capacity(data.len() * element_size(kind).unwrap_or(1));
+    for element in data {
+        write_element(&mut bytes, dtype, kind, element)?;
+    }
+    let header = Header {
+        descr: kind,
+        fortran_order: false,
+        shape: shape.to_vec(),
+        big_endian: false,
+        unsigned_width: None,
+    };
+    Ok((header, bytes))
+}
+
+/// Encodes a literal as an NPY file.
+pub(crate) fn literal_npy(dtype: &str, shape: &[i64], data: &[Number]) -> Result<Vec<u8>, String> {
+    let (header, bytes) = literal_npy_parts(dtype, shape, data)?;
+    encode_npy(&header, &bytes)
+}
+
+/// Decodes a literal into a tensor.
+pub fn read_literal(dtype: &str, shape: &[i64], data: &[Number]) -> Result<Tensor, String> {
+    let (header, bytes) = literal_npy_parts(dtype, shape, data)?;
+    let bytes = native_data(&header, &bytes)?;
+    match Tensor::f_of_data_size(&bytes, &header.shape, header.descr) {
+        Result::Ok(tensor) => Ok(tensor),
+        Result::Err(x) => Err(x.to_string()),
+    }
+}
+
+/// Writes an NPY file of at most `max_elements` elements as a literal. Returns `None` for
+/// other files, files whose dtype has no literal and files holding non-finite numbers, which
+/// JSON cannot represent.
+fn npy_literal(npy: &[u8], max_elements: usize) -> Option<SerializableIValue> {
+    let (header, data) = split_npy(npy).ok()?;
+    if header.fortran_order && header.shape.len() > 1 {
+        return None;
+    }
+    let dtype = kind_dtype(header.descr)?;
+    let numel = element_count(&header.shape)?;
+    if numel > max_elements {
+        return None;
+    }
+    let data = native_data(&header, data).ok()?;
+    let element_size = element_size(header.descr)?;
+    if data.len() != numel * element_size {
+        return None;
+    }
+    // `native_data` returns whole elements in native byte order.
+    let elements: Option<Vec<Number>> = match header.descr {
+        Kind::Bool | Kind::Uint8 => data
+            .iter()
+            .map(|value| Some(Number::from(*value)))
+            .collect(),
+        Kind::Int8 => data
+            .iter()
+            .map(|value| Some(Number::from(*value as i8)))
+            .collect(),
+        Kind::Int16 => data
+            .chunks(element_size)
+            .map(|bytes| Some(Number::from(i16::from_ne_bytes(bytes.try_into().unwrap()))))
+            .collect(),
+        Kind::Int => data
+            .chunks(element_size)
+            .map(|bytes| Some(Number::from(i32::from_ne_bytes(bytes.try_into().unwrap()))))
+            .collect(),
+        Kind::Int64 => data
+            .chunks(element_size)
+            .map(|bytes| Some(Number::from(i64::from_ne_bytes(bytes.try_into().unwrap()))))
+            .collect(),
+        Kind::Float => data
+            .chunks(element_size)
+            .map(|bytes| Number::from_f64(f64::from(f32::from_ne_bytes(bytes.try_into().unwrap()))))
+            .collect(),
+        Kind::Double => data
+            .chunks(element_size)
+            .map(|bytes| Number::from_f64(f64::from_ne_bytes(bytes.try_into().unwrap())))
+            .collect(),
+        _ => None,
+    };
+    Some(SerializableIValue::Tensor {
+        dtype: dtype.to_string(),
+        shape: header.shape,
+        data: elements?,
+    })
+}
+
+impl SerializableIValue {
+    /// Rewrites the NPY tensors of this value that hold at most `max_elements` elements as
+    /// tensor literals. Tensors that cannot be written as literals are left unchanged.
+    pub fn with_tensor_literals(self, max_elements: usize) -> SerializableIValue {
+        let with_literals = |values: Vec<SerializableIValue>| -> Vec<SerializableIValue> {
+            values
+                .into_iter()
+                .map(|value| value.with_tensor_literals(max_elements))
+                .collect()
+        };
+        match self {
+            SerializableIValue::Tuple(tuple_value) => {
+                SerializableIValue::Tuple(with_literals(tuple_value))
+            }
+            SerializableIValue::List(list_value) => {
+                SerializableIValue::List(with_literals(list_value))
+            }
+            SerializableIValue::Dict(entries) => SerializableIValue::Dict(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, value.with_tensor_literals(max_elements)))
+                    .collect(),
+            ),
+            SerializableIValue::Optional(Some(value)) => SerializableIValue::Optional(Some(
+                Box::new(value.with_tensor_literals(max_elements)),
+            )),
+            SerializableIValue::TensorNPYBase64(npy_base64) => base64::decode(&npy_base64)
+                .ok()
+                .and_then(|npy| npy_literal(&npy, max_elements))
+                .unwrap_or(SerializableIValue::TensorNPYBase64(npy_base64)),
+            SerializableIValue::TensorNPY(npy) => {
+                npy_literal(&npy, max_elements).unwrap_or(SerializableIValue::TensorNPY(npy))
+            }
+            other => other,
+        }
+    }
+}
+
+/// Dtype name and shape stored in the header of an NPY file.
+fn npy_dtype_and_shape(npy: &[u8]) -> Option<(String, Vec<i64>)> {
+    let (header, _) = split_npy(npy).ok()?;
+    Some((dtype_name(header.descr)?.to_string(), header.shape))
+}
+
+impl SerializableIValue {
+    /// Dtype name, as written in tensor literals, and shape of a tensor. NPY files are only
+    /// read up to their header. `None` for other values and tensors of other dtypes.
+    pub fn tensor_dtype_and_shape(&self) -> Option<(String, Vec<i64>)> {
+        match self {
+            SerializableIValue::Tensor { dtype, shape, .. } => Some((dtype.clone(), shape.clone())),
+            SerializableIValue::TensorNPYBase64(npy_base64) => {
+                npy_dtype_and_shape(&base64::decode(npy_base64).ok()?)
+            }
+            SerializableIValue::TensorNPY(npy) => npy_dtype_and_shape(npy),
+            _ => None,
+        }
+    }
+}
+
+/// Value of the IEEE 754 half precision float `bits`.
+fn half_to_f64(bits: u16) -> f64 {
+    let sign = if bits >> 15 == 1 { -1.0 } else { 1.0 };
+    let exponent = i32::from((bits >> 10) & 0x1f);
+    let fraction = f64::from(bits & 0x3ff);
+    match exponent {
+        0 => sign * fraction * 2f64.powi(-24),
+        0x1f if fraction == 0.0 => sign * f64::INFINITY,
+        0x1f => f64::NAN,
+        _ => sign * (1.0 + fraction / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+/// Elements of an NPY file as doubles, in storage order.
+fn npy_elements(npy: &[u8]) -> Option<Vec<f64>> {
+    let (header, data) = split_npy(npy).ok()?;
+    let data = native_data(&header, data).ok()?;
+    let element_size = element_size(header.descr)?;
+    // `native_data` returns whole elements in native byte order.
+    let elements = data.chunks(element_size);
+    match header.descr {
+        Kind::Bool | Kind::Uint8 => Some(data.iter().map(|value| f64::from(*value)).collect()),
+        Kind::Int8 => Some(data.iter().map(|value| f64::from(*value as i8)).collect()),
+        Kind::Int16 => Some(
+            elements
+                .map(|bytes| f64::from(i16::from_ne_bytes(bytes.try_into().unwrap())))
+                .collect(),
+        ),
+        Kind::Int => Some(
+            elements
+                .map(|bytes| f64::from(i32::from_ne_bytes(bytes.try_into().unwrap())))
+                .collect(),
+        ),
+        Kind::Int64 => Some(
+            elements
+                .map(|bytes| i64::from_ne_bytes(bytes.try_into().unwrap()) as f64)
+                .collect(),
+        ),
+        Kind::Half => Some(
+            elements
+                .map(|bytes| half_to_f64(u16::from_ne_bytes(bytes.try_into().unwrap())))
+                .collect(),
+        ),
+        Kind::Float => Some(
+            elements
+                .map(|bytes| f64::from(f32::from_ne_bytes(bytes.try_into().unwrap())))
+                .collect(),
+        ),
+        Kind::Double => Some(
+            elements
+                .map(|bytes| f64::from_ne_bytes(bytes.try_into().unwrap()))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+impl SerializableIValue {
+    /// Elements of a tensor as doubles, in storage order. `None` for other values and tensors
+    /// of other dtypes.
+    pub fn tensor_elements(&self) -> Option<Vec<f64>> {
+        match self {
+            SerializableIValue::Tensor { data, .. } => data.iter().map(Number::as_f64).collect(),
+            SerializableIValue::TensorNPYBase64(npy_base64) => {
+                npy_elements(&base64::decode(npy_base64).ok()?)
+            }
+            SerializableIValue::TensorNPY(npy) => npy_elements(npy),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(values: &[f64]) -> Vec<Number> {
+        values
+            .iter()
+            .map(|value| Number::from_f64(*value).unwrap())
+            .collect()
+    }
+
+    fn literal(dtype: &str, shape: Vec<i64>, data: Vec<Number>) -> SerializableIValue {
+        SerializableIValue::Tensor {
+            dtype: dtype.to_string(),
+            shape,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_literal_npy_parts() {
+        let (header, bytes) =
+            literal_npy_parts("int16", &[2, 1], &[Number::from(1), Number::from(-2)]).unwrap();
+        assert_eq!(header.descr, Kind::Int16);
+        assert_eq!(header.shape, vec![2, 1]);
+        assert_eq!(bytes, vec![1, 0, 0xfe, 0xff]);
+
+        let (_, bytes) = literal_npy_parts("float32", &[1], &numbers(&[1.0])).unwrap();
+        assert_eq!(bytes, 1f32.to_le_bytes().to_vec());
+        let (_, bytes) = literal_npy_parts("float64", &[1], &[Number::from(2)]).unwrap();
+        assert_eq!(bytes, 2f64.to_le_bytes().to_vec());
+        let (header, bytes) = literal_npy_parts("bool", &[], &[Number::from(1)]).unwrap();
+        assert_eq!(header.shape, Vec::<i64>::new());
+        assert_eq!(bytes, vec![1]);
+
+        assert!(literal_npy_parts("complex64", &[1], &[Number::from(1)]).is_err());
+        assert!(literal_npy_parts("int32", &[3], &[Number::from(1)]).is_err());
+        assert!(literal_npy_parts("int32", &[-1], &[]).is_err());
+        assert!(literal_npy_parts("uint8", &[1], &[Number::from(256)]).is_err());
+        assert!(literal_npy_parts("int64", &[1], &numbers(&[1.5])).is_err());
+        assert!(literal_npy_parts("bool", &[1], &[Number::from(2)]).is_err());
+        assert!(literal_npy_parts("float32", &[1], &numbers(&[1e300])).is_err());
+        assert!(literal_npy_parts("float64", &[1], &numbers(&[1e300])).is_ok());
+    }
+
+    #[test]
+    fn test_small_tensors_become_literals() {
+        let matrix = literal("int32", vec![2, 2], (1..=4).map(Number::from).collect());
+        let npy = match &matrix {
+            SerializableIValue::Tensor { dtype, shape, data } => {
+                literal_npy(dtype, shape, data).unwrap()
+            }
+            _ => unreachable!(),
+        };
+        let output = SerializableIValue::Tuple(vec![
+            SerializableIValue::TensorNPYBase64(base64::encode(&npy)),
+            SerializableIValue::List(vec![SerializableIValue::TensorNPY(npy.clone())]),
+            SerializableIValue::Int(3),
+        ]);
+        assert_eq!(
+            output.clone().with_tensor_literals(4),
+            SerializableIValue::Tuple(vec![
+                matrix.clone(),
+                SerializableIValue::List(vec![matrix]),
+                SerializableIValue::Int(3),
+            ])
+        );
+        assert_eq!(output.clone().with_tensor_literals(3), output);
+    }
+
+    #[test]
+    fn test_tensor_dtype_and_shape() {
+        let matrix = literal("int32", vec![2, 2], (1..=4).map(Number::from).collect());
+        assert_eq!(
+            matrix.tensor_dtype_and_shape(),
+            Some(("int32".to_string(), vec![2, 2]))
+        );
+        let (header, _) = literal_npy_parts("float64", &[3], &numbers(&[0.0; 3])).unwrap();
+        let half_header = Header {
+            descr: Kind::Half,
+            ..header
+        };
+        let npy = encode_npy(&half_header, &[0; 6]).unwrap();
+        assert_eq!(
+            SerializableIValue::TensorNPYBase64(base64::encode(&npy)).tensor_dtype_and_shape(),
+            Some(("float16".to_string(), vec![3]))
+        );
+        assert_eq!(SerializableIValue::Int(3).tensor_dtype_and_shape(), None);
+    }
+
+    #[test]
+    fn test_tensor_elements() {
+        let matrix = literal("int32", vec![2, 2], (1..=4).map(Number::from).collect());
+        assert_eq!(matrix.tensor_elements(), Some(vec![1.0, 2.0, 3.0, 4.0]));
+
+        let (header, _) = literal_npy_parts("float64", &[2], &numbers(&[0.0; 2])).unwrap();
+        let mut data = 1.5f64.to_le_bytes().to_vec();
+        data.extend_from_slice(&f64::INFINITY.to_le_bytes());
+        let npy = encode_npy(&header, &data).unwrap();
+        assert_eq!(
+            SerializableIValue::TensorNPYBase64(base64::encode(&npy)).tensor_elements(),
+            Some(vec![1.5, f64::INFINITY])
+        );
+
+        let half_header = Header {
+            descr: Kind::Half,
+            shape: vec![4],
+            ..header
+        };
+        let half_bits: [u16; 4] = [0x3c00, 0xc000, 0x7c00, 0x7e00];
+        let data: Vec<u8> = half_bits
+            .iter()
+            .flat_map(|bits| bits.to_le_bytes().to_vec())
+            .collect();
+        let elements = SerializableIValue::TensorNPY(encode_npy(&half_header, &data).unwrap())
+            .tensor_elements()
+            .unwrap();
+        assert_eq!(elements[..3], [1.0, -2.0, f64::INFINITY]);
+        assert!(elements[3].is_nan());
+        assert_eq!(SerializableIValue::Int(3).tensor_elements(), None);
+    }
+
+    #[test]
+    fn test_non_finite_tensors_stay_npy() {
+        let (header, _) = literal_npy_parts("float64", &[2], &numbers(&[0.0, 0.0])).unwrap();
+        let mut data = 1f64.to_le_bytes().to_vec();
+        data.extend_from_slice(&f64::NAN.to_le_bytes());
+        let npy = SerializableIValue::TensorNPY(encode_npy(&header, &data).unwrap());
+        assert_eq!(npy.clone().with_tensor_literals(16), npy);
+
+        let finite = SerializableIValue::TensorNPY(
+            literal_npy("float64", &[2], &numbers(&[1.0, 0.5])).unwrap(),
+        );
+        assert_eq!(
+            finite.with_tensor_literals(16),
+            literal("float64", vec![2], numbers(&[1.0, 0.5]))
+        );
+    }
+}
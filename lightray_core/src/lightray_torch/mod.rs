@@ -1,4 +1,8 @@
+pub mod canonical;
 pub mod core;
 pub mod errors;
+pub mod frame;
+pub mod literal;
+pub mod schema;
 pub mod tensor;
 pub use self::core::*;
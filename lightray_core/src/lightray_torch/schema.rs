@@ -0,0 +1,443 @@
+//! Method schemas of TorchScript modules, read from the code that `torch.jit.save` stores in
+//! the module archive.
+//!
+//! The archive pickles the root module in `data.pkl`, whose first global names the class of
+//! the module, e.g. `__torch__.Model`. The class is defined in `code/__torch__.py` (or
+//! `code/__torch__/<submodule>.py` for nested qualified names) as Python source whose method
+//! signatures carry the name, type and default value of every argument and the return type:
+//!
+//! ```text
+//! class Model(Module):
+//!   def forward(self: __torch__.Model,
+//!     tokens: List[str],
+//!     beam_size: int=3) -> List[str]:
+//! ```
+use crate::lightray_torch::core::SerializableIValue;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+use zip::ZipArchive;
+
+const GLOBAL_OPCODE: u8 = b'c';
+const SELF_ARGUMENT: &str = "self";
+
+/// An argument of a TorchScript method.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TorchScriptArgument {
+    pub name: String,
+    /// TorchScript type of the argument, e.g. `Dict[str, Tensor]`.
+    pub type_name: String,
+    /// Source of the default value, if the argument has one.
+    pub default_value: Option<String>,
+}
+
+/// Signature of a TorchScript method, without its `self` argument.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TorchScriptMethodSchema {
+    pub name: String,
+    pub arguments: Vec<TorchScriptArgument>,
+    /// TorchScript type of the returned value, `None` if the method is not annotated.
+    pub return_type: Option<String>,
+}
+
+/// Method schemas of the root module of a TorchScript archive.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct TorchScriptSchema {
+    pub methods: Vec<TorchScriptMethodSchema>,
+}
+
+impl TorchScriptSchema {
+    /// Reads the method schemas of the module saved at `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<TorchScriptSchema, String> {
+        let file = File::open(path).map_err(|error| error.to_string())?;
+        TorchScriptSchema::read_archive(file)
+    }
+
+    /// Reads the method schemas of the module archive `reader`.
+    pub fn read_archive<R: Read + Seek>(reader: R) -> Result<TorchScriptSchema, String> {
+        let mut archive = ZipArchive::new(reader).map_err(|error| error.to_string())?;
+        let data = read_archive_file(&mut archive, "data.pkl")?;
+        let (module, class_name) = root_class(&data)?;
+        let code_path = format!("code/{}.py", module.replace('.', "/"));
+        let code = String::from_utf8(read_archive_file(&mut archive, &code_path)?)
+            .map_err(|_| format!("{} is not valid UTF-8", code_path))?;
+        Ok(TorchScriptSchema {
+            methods: parse_class_methods(&code, &class_name)?,
+        })
+    }
+
+    /// Schema of the method `name`.
+    pub fn method(&self, name: &str) -> Option<&TorchScriptMethodSchema> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+}
+
+/// Reads `path` from an archive whose files are stored under a single top-level directory.
+fn read_archive_file<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    path: &str,
+) -> Result<Vec<u8>, String> {
+    let suffix = format!("/{}", path);
+    let index = (0..archive.len())
+        .find(|index| {
+            archive
+                .by_index(*index)
+                .map(|file| file.name().ends_with(&suffix) || file.name() == path)
+                .unwrap_or(false)
+        })
+        .ok_or_else(|| format!("module archive has no {}", path))?;
+    let mut file = archive.by_index(index).map_err(|error| error.to_string())?;
+    let mut contents = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut contents)
+        .map_err(|error| error.to_string())?;
+    Ok(contents)
+}
+
+/// Module and class name of the first global of a pickle, the class of the root module.
+fn root_class(pickle: &[u8]) -> Result<(String, String), String> {
+    let start = pickle
+        .iter()
+        .position(|opcode| *opcode == GLOBAL_OPCODE)
+        .ok_or_else(|| "data.pkl does not name the module class".to_string())?;
+    let mut lines = pickle[start + 1..].split(|byte| *byte == b'\n');
+    match (lines.next(), lines.next()) {
+        (Some(module), Some(class_name)) => Ok((
+            String::from_utf8_lossy(module).into_owned(),
+            String::from_utf8_lossy(class_name).into_owned(),
+        )),
+        _ => Err("data.pkl does not name the module class".to_string()),
+    }
+}
+
+/// Splits `text` at top-level occurrences of `separator`, outside brackets and string literals.
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, character) in text.char_indices() {
+        if let Some(open_quote) = quote {
+            if escaped {
+                escaped = false;
+            } else if character == '\\' {
+                escaped = true;
+            } else if character == open_quote {
+                quote = None;
+            }
+            continue;
+        }
+        match character {
+            '\'' | '"' => quote = Some(character),
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth -= 1,
+            _ if character == separator && depth == 0 => {
+                parts.push(&text[start..index]);
+                start = index + character.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Byte index of the parenthesis closing the one opened at `open`.
+fn closing_parenthesis(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (index, character) in text[open..].char_indices() {
+        if let Some(open_quote) = quote {
+            if escaped {
+                escaped = false;
+            } else if character == '\\' {
+                escaped = true;
+            } else if character == open_quote {
+                quote = None;
+            }
+            continue;
+        }
+        match character {
+            '\'' | '"' => quote = Some(character),
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_argument(argument: &str) -> Result<TorchScriptArgument, String> {
+    let mut name_and_type = argument.splitn(2, ':');
+    let name = name_and_type.next().unwrap_or("").trim();
+    let type_and_default = name_and_type
+        .next()
+        .ok_or_else(|| format!("argument {} has no type", name))?;
+    let mut parts = split_top_level(type_and_default, '=').into_iter();
+    let type_name = parts.next().unwrap_or("").trim();
+    let default_value = parts.next().map(|default| default.trim().to_string());
+    if name.is_empty() || type_name.is_empty() {
+        return Err(format!("invalid argument {}", argument.trim()));
+    }
+    Ok(TorchScriptArgument {
+        name: name.to_string(),
+        type_name: type_name.to_string(),
+        default_value,
+    })
+}
+
+/// Parses the methods of the class `class_name` defined in `code`.
+pub(crate) fn parse_class_methods(
+    code: &str,
+    class_name: &str,
+) -> Result<Vec<TorchScriptMethodSchema>, String> {
+    let class_header = format!("class {}(", class_name);
+    let class_start = code
+        .lines()
+        .scan(0, |offset, line| {
+            let line_start = *offset;
+            *offset += line.len() + 1;
+            Some((line_start, line))
+        })
+        .find(|(_, line)| line.starts_with(&class_header))
+        .map(|(line_start, _)| line_start)
+        .ok_or_else(|| format!("class {} is not defined", class_name))?;
+    // The class body ends at the next line that is not indented.
+    let body_start = class_start
+        + code[class_start..]
+            .find('\n')
+            .unwrap_or(code.len() - class_start);
+    let body_len = code[body_start..]
+        .match_indices('\n')
+        .map(|(index, _)| index + 1)
+        .find(|index| {
+            code[body_start + index..].starts_with(|character: char| !character.is_whitespace())
+        })
+        .unwrap_or(code.len() - body_start);
+    let body = &code[body_start..body_start + body_len];
+
+    let class_indentation = body
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .map_or("", |line| &line[..line.len() - line.trim_start().len()]);
+
+    let mut methods = vec![];
+    let mut search_from = 0;
+    while let Some(def_index) = body[search_from..].find("def ") {
+        let def_start = search_from + def_index;
+        search_from = def_start + 4;
+        // Only method definitions directly in the class body, not nested functions.
+        let line_start = body[..def_start].rfind('\n').map_or(0, |index| index + 1);
+        if &body[line_start..def_start] != class_indentation {
+            continue;
+        }
+        let open = match body[def_start..].find('(') {
+            Some(open) => def_start + open,
+            None => break,
+        };
+        let name = body[def_start + 4..open].trim().to_string();
+        let close = closing_parenthesis(body, open)
+            .ok_or_else(|| format!("unterminated signature of method {}", name))?;
+        let mut arguments = vec![];
+        for argument in split_top_level(&body[open + 1..close], ',') {
+            if argument.trim().is_empty() {
+                continue;
+            }
+            let argument = parse_argument(argument)?;
+            if argument.name != SELF_ARGUMENT {
+                arguments.push(argument);
+            }
+        }
+        // The signature ends at the first top-level colon, after the optional return type.
+        let signature_rest = split_top_level(&body[close + 1..], ':')[0].trim();
+        let return_type = signature_rest
+            .strip_prefix("->")
+            .map(|return_type| return_type.trim().to_string());
+        methods.push(TorchScriptMethodSchema {
+            name,
+            arguments,
+            return_type,
+        });
+        search_from = close;
+    }
+    Ok(methods)
+}
+
+/// Parses the default value source of an argument. Returns `None` for defaults that are not
+/// literals: `None`, booleans, numbers, strings, or lists and tuples of literals.
+pub(crate) fn parse_default_value(source: &str) -> Option<SerializableIValue> {
+    let source = source.trim();
+    let parse_elements = |elements: &str| -> Option<Vec<SerializableIValue>> {
+        if elements.trim().is_empty() {
+            return Some(vec![]);
+        }
+        split_top_level(elements, ',')
+            .into_iter()
+            .filter(|element| !element.trim().is_empty())
+            .map(parse_default_value)
+            .collect()
+    };
+    match source {
+        "None" => Some(SerializableIValue::None),
+        "True" => Some(SerializableIValue::Bool(true)),
+        "False" => Some(SerializableIValue::Bool(false)),
+        _ if source.len() >= 2 && source.starts_with('[') && source.ends_with(']') => Some(
+            SerializableIValue::List(parse_elements(&source[1..source.len() - 1])?),
+        ),
+        _ if source.len() >= 2 && source.starts_with('(') && source.ends_with(')') => Some(
+            SerializableIValue::Tuple(parse_elements(&source[1..source.len() - 1])?),
+        ),
+        _ if source.len() >= 2
+            && (source.starts_with('"') && source.ends_with('"')
+                || source.starts_with('\'') && source.ends_with('\'')) =>
+        {
+            let contents = &source[1..source.len() - 1];
+            if contents.contains('\\') {
+                return None;
+            }
+            Some(SerializableIValue::Str(contents.to_string()))
+        }
+        _ => match source.parse::<i64>() {
+            Ok(int_value) => Some(SerializableIValue::Int(int_value)),
+            Err(_) => source.parse::<f64>().ok().map(SerializableIValue::Double),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = r#"op_version_set = 1
+class Helper(Module):
+  def forward(self: __torch__.Helper,
+    x: Tensor) -> Tensor:
+    return x
+class Model(Module):
+  __parameters__ = []
+  training : bool
+  helper : __torch__.Helper
+  def forward(self: __torch__.Model,
+    features: Dict[str, Tensor],
+    shape: Tuple[int, int]=(2, 3),
+    label: str="a, b",
+    scale: float=0.5) -> Tensor:
+    def inner(y: int) -> int:
+      return y
+    return features["x"]
+  def encode(self: __torch__.Model,
+    tokens: List[str]) -> List[int]:
+    return [1]
+def unrelated(z: int) -> int:
+  return z
+"#;
+
+    #[test]
+    fn test_parse_class_methods() {
+        let methods = parse_class_methods(CODE, "Model").unwrap();
+        assert_eq!(methods.len(), 2);
+        assert_eq!(methods[0].name, "forward");
+        assert_eq!(
+            methods[0].arguments,
+            vec![
+                TorchScriptArgument {
+                    name: "features".to_string(),
+                    type_name: "Dict[str, Tensor]".to_string(),
+                    default_value: None,
+                },
+                TorchScriptArgument {
+                    name: "shape".to_string(),
+                    type_name: "Tuple[int, int]".to_string(),
+                    default_value: Some("(2, 3)".to_string()),
+                },
+                TorchScriptArgument {
+                    name: "label".to_string(),
+                    type_name: "str".to_string(),
+                    default_value: Some("\"a, b\"".to_string()),
+                },
+                TorchScriptArgument {
+                    name: "scale".to_string(),
+                    type_name: "float".to_string(),
+                    default_value: Some("0.5".to_string()),
+                },
+            ]
+        );
+        assert_eq!(methods[0].return_type, Some("Tensor".to_string()));
+        assert_eq!(methods[1].name, "encode");
+        assert_eq!(methods[1].arguments[0].name, "tokens");
+        assert_eq!(methods[1].return_type, Some("List[int]".to_string()));
+
+        let helper = parse_class_methods(CODE, "Helper").unwrap();
+        assert_eq!(helper.len(), 1);
+        assert_eq!(helper[0].arguments[0].name, "x");
+        assert!(parse_class_methods(CODE, "Missing").is_err());
+    }
+
+    #[test]
+    fn test_parse_escaped_quote() {
+        let code = r#"class Model(Module):
+  def forward(self: __torch__.Model,
+    text: str,
+    sep: str="\")") -> str:
+    return text
+"#;
+        let methods = parse_class_methods(code, "Model").unwrap();
+        assert_eq!(methods[0].arguments.len(), 2);
+        assert_eq!(
+            methods[0].arguments[1].default_value,
+            Some(r#""\")""#.to_string())
+        );
+        assert_eq!(methods[0].return_type, Some("str".to_string()));
+    }
+
+    #[test]
+    fn test_root_class() {
+        let pickle = b"\x80\x02c__torch__.models\nModel\nq\x00)\x81}";
+        assert_eq!(
+            root_class(pickle).unwrap(),
+            ("__torch__.models".to_string(), "Model".to_string())
+        );
+        assert!(root_class(b"\x80\x02").is_err());
+    }
+
+    #[test]
+    fn test_parse_default_value() {
+        assert_eq!(parse_default_value("None"), Some(SerializableIValue::None));
+        assert_eq!(
+            parse_default_value("False"),
+            Some(SerializableIValue::Bool(false))
+        );
+        assert_eq!(parse_default_value("-3"), Some(SerializableIValue::Int(-3)));
+        assert_eq!(
+            parse_default_value("1e-05"),
+            Some(SerializableIValue::Double(1e-05))
+        );
+        assert_eq!(
+            parse_default_value("'a, b'"),
+            Some(SerializableIValue::Str("a, b".to_string()))
+        );
+        assert_eq!(
+            parse_default_value("[1, 2]"),
+            Some(SerializableIValue::List(vec![
+                SerializableIValue::Int(1),
+                SerializableIValue::Int(2),
+            ]))
+        );
+        assert_eq!(
+            parse_default_value("([], 0.5)"),
+            Some(SerializableIValue::Tuple(vec![
+                SerializableIValue::List(vec![]),
+                SerializableIValue::Double(0.5),
+            ]))
+        );
+        assert_eq!(parse_default_value("torch.zeros([2])"), None);
+        assert_eq!(parse_default_value("[1, x]"), None);
+    }
+}
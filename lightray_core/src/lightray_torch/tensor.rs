@@ -1,23 +1,33 @@
-//! Numpy support for tensors.
+//! Numpy support for tensors: `read_npy` decodes NPY files into tensors and `write_npy`
+//! encodes tensors as NPY files. `read_npz` decodes the NPZ archives written by
+//! `numpy.savez` and `numpy.savez_compressed`.
 //!
 //! Format spec:
 //! https://docs.scipy.org/doc/numpy-1.14.2/neps/npy-format.html
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::{BufReader, Read};
-use tch::{Kind, Tensor};
+use std::io::{Cursor, Read};
+use tch::{Device, Kind, Tensor};
+use zip::ZipArchive;
 
 const NPY_MAGIC_STRING: &[u8] = b"\x93NUMPY";
+const NPY_EXTENSION: &str = ".npy";
+/// Written files are padded so that the tensor data starts at a multiple of this alignment.
+const NPY_HEADER_ALIGNMENT: usize = 64;
+/// Largest total size of the arrays extracted from one NPZ archive, sixteen times the default
+/// request size limit. Compressed entries are never trusted to be as small as they claim.
+const MAX_NPZ_ARRAY_BYTES: u64 = 256 * 1024 * 1024;
 
-fn read_header<R: Read>(buf_reader: &mut BufReader<R>) -> Result<String, String> {
+fn read_header<R: Read>(reader: &mut R) -> Result<String, String> {
     let mut magic_string = vec![0u8; NPY_MAGIC_STRING.len()];
-    if let Err(x) = buf_reader.read_exact(&mut magic_string) {
+    if let Err(x) = reader.read_exact(&mut magic_string) {
         return Err(x.to_string());
     }
     if magic_string != NPY_MAGIC_STRING {
         return Err("magic string mismatch".to_string());
     };
     let mut version = [0u8; 2];
-    if let Err(error) = buf_reader.read_exact(&mut version) {
+    if let Err(error) = reader.read_exact(&mut version) {
         return Err(error.to_string());
     }
     let header_len_len = match version[0] {
@@ -26,7 +36,7 @@ fn read_header<R: Read>(buf_reader: &mut BufReader<R>) -> Result<String, String>
         otherwise => return Err(format!("unsupported version {}", otherwise)),
     };
     let mut header_len = vec![0u8; header_len_len];
-    if let Err(error) = buf_reader.read_exact(&mut header_len) {
+    if let Err(error) = reader.read_exact(&mut header_len) {
         return Err(error.to_string());
     }
     let header_len = header_len
@@ -34,17 +44,60 @@ fn read_header<R: Read>(buf_reader: &mut BufReader<R>) -> Result<String, String>
         .rev()
         .fold(0 as usize, |acc, &v| 256 * acc + v as usize);
     let mut header = vec![0u8; header_len];
-    if let Err(error) = buf_reader.read_exact(&mut header) {
+    if let Err(error) = reader.read_exact(&mut header) {
         return Err(error.to_string());
     }
     Ok(String::from_utf8_lossy(&header).to_string())
 }
 
 #[derive(Debug, PartialEq)]
-struct Header {
-    descr: Kind,
-    fortran_order: bool,
-    shape: Vec<i64>,
+pub(crate) struct Header {
+    /// Kind of the tensor the data is read into.
+    pub(crate) descr: Kind,
+    pub(crate) fortran_order: bool,
+    pub(crate) shape: Vec<i64>,
+    /// Whether multi-byte elements are stored most significant byte first.
+    pub(crate) big_endian: bool,
+    /// Byte width of unsigned elements that tch has no kind for. They are widened to `descr`.
+    pub(crate) unsigned_width: Option<usize>,
+}
+
+/// NPY type code of `kind`, without byte order. tch has no bfloat16 kind, and numpy no
+/// complex half type, so those have no code.
+pub(crate) fn kind_descr(kind: Kind) -> Option<&'static str> {
+    match kind {
+        Kind::Half => Some("f2"),
+        Kind::Float => Some("f4"),
+        Kind::Double => Some("f8"),
+        Kind::Int => Some("i4"),
+        Kind::Int64 => Some("i8"),
+        Kind::Int16 => Some("i2"),
+        Kind::Int8 => Some("i1"),
+        Kind::Uint8 => Some("u1"),
+        Kind::Bool => Some("b1"),
+        _ => None,
+    }
+}
+
+/// Size in bytes of an element of `kind`, for kinds that have an NPY type code.
+pub(crate) fn element_size(kind: Kind) -> Option<usize> {
+    kind_descr(kind).and_then(|descr| descr[1..].parse::<usize>().ok())
+}
+
+/// Kind of the NPY type code `descr`, without byte order.
+fn descr_kind(descr: &str) -> Option<Kind> {
+    match descr {
+        "f2" => Some(Kind::Half),
+        "f4" => Some(Kind::Float),
+        "f8" => Some(Kind::Double),
+        "i4" => Some(Kind::Int),
+        "i8" => Some(Kind::Int64),
+        "i2" => Some(Kind::Int16),
+        "i1" => Some(Kind::Int8),
+        "u1" => Some(Kind::Uint8),
+        "b1" => Some(Kind::Bool),
+        _ => None,
+    }
 }
 
 impl Header {
@@ -91,24 +144,24 @@ impl Header {
                 _ => return Err(format!("unknown fortran_order {}", fortran_order)),
             },
         };
-        let descr = match part_map.get("descr") {
+        let (descr, big_endian, unsigned_width) = match part_map.get("descr") {
             None => return Err("no descr in header".to_string()),
             Some(descr) => {
                 if descr.is_empty() {
                     return Err("empty descr".to_string());
                 }
-                if descr.starts_with('>') {
-                    return Err(format!("little-endian descr {}", descr));
-                }
-                match descr.trim_matches(|c: char| c == '=' || c == '<') {
-                    "f4" => Kind::Float,
-                    "f8" => Kind::Double,
-                    "i4" => Kind::Int,
-                    "i8" => Kind::Int64,
-                    "i2" => Kind::Int16,
-                    "i1" => Kind::Int8,
-                    "u1" => Kind::Uint8,
-                    descr => return Err(format!("unrecognized descr {}", descr)),
+                let big_endian = match descr.chars().next() {
+                    Some('>') => true,
+                    Some('<') => false,
+                    _ => cfg!(target_endian = "big"),
+                };
+                let type_code = descr.trim_start_matches(|c: char| "<>|=".contains(c));
+                match (descr_kind(type_code), type_code) {
+                    (Some(kind), _) => (kind, big_endian, None),
+                    (None, "u2") => (Kind::Int, big_endian, Some(2)),
+                    (None, "u4") => (Kind::Int64, big_endian, Some(4)),
+                    (None, "u8") => (Kind::Int64, big_endian, Some(8)),
+                    (None, _) => return Err(format!("unrecognized descr {}", descr)),
                 }
             }
         };
@@ -135,59 +188,225 @@ impl Header {
             descr,
             fortran_order,
             shape,
+            big_endian,
+            unsigned_width,
         })
     }
 }
 
+impl Header {
+    fn to_string(&self) -> Option<String> {
+        let fortran_order = if self.fortran_order { "True" } else { "False" };
+        let mut shape = self
+            .shape
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        if self.unsigned_width.is_some() {
+            return None;
+        }
+        let descr = kind_descr(self.descr)?;
+        let byte_order = if descr.ends_with('1') {
+            '|'
+        } else if self.big_endian {
+            '>'
+        } else {
+            '<'
+        };
+        if !shape.is_empty() {
+            shape.push(',')
+        }
+        Some(format!(
+            "{{'descr': '{}{}', 'fortran_order': {}, 'shape': ({}), }}",
+            byte_order, descr, fortran_order, shape
+        ))
+    }
+}
+
+/// Splits an NPY file into its parsed header and raw data.
+pub(crate) fn split_npy(value: &[u8]) -> Result<(Header, &[u8]), String> {
+    let mut data = value;
+    let header = read_header(&mut data)?;
+    Ok((Header::parse(&header)?, data))
+}
+
+/// Converts NPY data to native byte order, widening unsigned elements to `header.descr`.
+pub(crate) fn native_data<'a>(header: &Header, data: &'a [u8]) -> Result<Cow<'a, [u8]>, String> {
+    let stored_size = match header.unsigned_width {
+        Some(width) => width,
+        None => element_size(header.descr)
+            .ok_or_else(|| format!("unsupported tensor kind {:?}", header.descr))?,
+    };
+    if data.len() % stored_size != 0 {
+        return Err(format!(
+            "data length {} is not a multiple of the element size {}",
+            data.len(),
+            stored_size
+        ));
+    }
+    let swap_bytes = stored_size > 1 && header.big_endian != cfg!(target_endian = "big");
+    match header.unsigned_width {
+        None if !swap_bytes => Ok(Cow::Borrowed(data)),
+        None => Ok(Cow::Owned(
+            data.chunks(stored_size)
+                .flat_map(|element| element.iter().rev().cloned())
+                .collect(),
+        )),
+        Some(_) => {
+            let mut widened = Vec::with_capacity(data.len() * 2);
+            for element in data.chunks(stored_size) {
+                let value = if header.big_endian {
+                    element
+                        .iter()
+                        .fold(0u64, |acc, &v| (acc << 8) | u64::from(v))
+                } else {
+                    element
+                        .iter()
+                        .rev()
+                        .fold(0u64, |acc, &v| (acc << 8) | u64::from(v))
+                };
+                match header.descr {
+                    Kind::Int => widened.extend_from_slice(&(value as i32).to_ne_bytes()),
+                    _ if value > i64::MAX as u64 => {
+                        return Err(format!("unsigned value {} does not fit in int64", value))
+                    }
+                    _ => widened.extend_from_slice(&(value as i64).to_ne_bytes()),
+                }
+            }
+            Ok(Cow::Owned(widened))
+        }
+    }
+}
+
+/// Decodes an NPY file into a tensor. Fortran ordered and big-endian data is converted to a
+/// native tensor, unsigned elements wider than a byte are widened to the next signed kind.
 pub fn read_npy(value: &[u8]) -> Result<Tensor, String> {
-    let mut buf_reader = BufReader::new(value);
-    let header = read_header(&mut buf_reader)?;
-    let header = Header::parse(&header)?;
-    if header.fortran_order {
-        return Err("fortran order not supported".to_string());
-    }
-    let mut data: Vec<u8> = vec![];
-    if let Err(error) = buf_reader.read_to_end(&mut data) {
-        return Err(error.to_string());
+    let (header, data) = split_npy(value)?;
+    let data = native_data(&header, data)?;
+    if !header.fortran_order || header.shape.len() < 2 {
+        return match Tensor::f_of_data_size(&data, &header.shape, header.descr) {
+            Result::Ok(tensor) => Ok(tensor),
+            Result::Err(x) => Err(x.to_string()),
+        };
     }
-    match Tensor::f_of_data_size(&data, &header.shape, header.descr) {
+    // Column-major data is the row-major layout of the tensor with its dimensions reversed.
+    let reversed_shape: Vec<i64> = header.shape.iter().rev().cloned().collect();
+    let reversed_dims: Vec<i64> = (0..header.shape.len() as i64).rev().collect();
+    match Tensor::f_of_data_size(&data, &reversed_shape, header.descr)
+        .and_then(|tensor| tensor.f_permute(&reversed_dims))
+        .and_then(|tensor| tensor.f_contiguous())
+    {
         Result::Ok(tensor) => Ok(tensor),
         Result::Err(x) => Err(x.to_string()),
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::Header;
-    use tch::Kind;
-    impl Header {
-        fn to_string(&self) -> Option<String> {
-            let fortran_order = if self.fortran_order { "True" } else { "False" };
-            let mut shape = self
-                .shape
-                .iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<_>>()
-                .join(",");
-            let descr = match self.descr {
-                Kind::Float => "f4",
-                Kind::Double => "f8",
-                Kind::Int => "i4",
-                Kind::Int64 => "i8",
-                Kind::Int16 => "i2",
-                Kind::Int8 => "i1",
-                Kind::Uint8 => "u1",
-                _ => return None,
-            };
-            if !shape.is_empty() {
-                shape.push(',')
-            }
-            Some(format!(
-                "{{'descr': '<{}', 'fortran_order': {}, 'shape': ({}), }}",
-                descr, fortran_order, shape
-            ))
+/// Extracts the NPY files of an NPZ archive in archive order, each paired with the name of its
+/// array. Stored and deflate compressed entries are supported.
+pub(crate) fn read_npz_entries(value: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    read_limited_npz_entries(value, MAX_NPZ_ARRAY_BYTES)
+}
+
+/// Like `read_npz_entries`, but fails once the extracted arrays exceed `max_bytes` in total.
+fn read_limited_npz_entries(
+    value: &[u8],
+    max_bytes: u64,
+) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut archive = ZipArchive::new(Cursor::new(value)).map_err(|error| error.to_string())?;
+    let mut entries = Vec::with_capacity(archive.len());
+    let mut remaining_bytes = max_bytes;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(|error| error.to_string())?;
+        if entry.is_dir() {
+            continue;
         }
+        let name = entry.name();
+        let name = name.strip_suffix(NPY_EXTENSION).unwrap_or(name).to_string();
+        let mut npy = Vec::new();
+        (&mut entry)
+            .take(remaining_bytes + 1)
+            .read_to_end(&mut npy)
+            .map_err(|error| format!("array {}: {}", name, error))?;
+        remaining_bytes = remaining_bytes
+            .checked_sub(npy.len() as u64)
+            .ok_or_else(|| format!("arrays exceed {} bytes", max_bytes))?;
+        entries.push((name, npy));
+    }
+    Ok(entries)
+}
+
+/// Decodes an NPZ archive into its named tensors, in archive order. Arrays passed to
+/// `numpy.savez` positionally are named `arr_0`, `arr_1`, ..., arrays passed by keyword are
+/// named after the keyword.
+pub fn read_npz(value: &[u8]) -> Result<Vec<(String, Tensor)>, String> {
+    read_npz_entries(value)?
+        .into_iter()
+        .map(|(name, npy)| match read_npy(&npy) {
+            Result::Ok(tensor) => Ok((name, tensor)),
+            Result::Err(error) => Err(format!("array {}: {}", name, error)),
+        })
+        .collect()
+}
+
+/// Encodes `header` and the raw tensor `data` as a version 1.0 NPY file.
+pub(crate) fn encode_npy(header: &Header, data: &[u8]) -> Result<Vec<u8>, String> {
+    let header = header
+        .to_string()
+        .ok_or_else(|| format!("unsupported tensor kind {:?}", header.descr))?;
+    // The header is terminated by a newline and padded with spaces to align the data.
+    let unpadded_len = NPY_MAGIC_STRING.len() + 4 + header.len() + 1;
+    let padding =
+        (NPY_HEADER_ALIGNMENT - unpadded_len % NPY_HEADER_ALIGNMENT) % NPY_HEADER_ALIGNMENT;
+    let header_len = header.len() + padding + 1;
+    if header_len > u16::MAX as usize {
+        return Err(format!("npy header too long: {}", header_len));
     }
+
+    let mut npy = Vec::with_capacity(unpadded_len + padding + data.len());
+    npy.extend_from_slice(NPY_MAGIC_STRING);
+    npy.extend_from_slice(&[1u8, 0u8]);
+    npy.extend_from_slice(&(header_len as u16).to_le_bytes());
+    npy.extend_from_slice(header.as_bytes());
+    npy.extend(std::iter::repeat(b' ').take(padding));
+    npy.push(b'\n');
+    npy.extend_from_slice(data);
+    Ok(npy)
+}
+
+/// Encodes `tensor` as a version 1.0 NPY file in C order, the inverse of `read_npy`.
+pub fn write_npy(tensor: &Tensor) -> Result<Vec<u8>, String> {
+    let kind = tensor.kind();
+    let element_size =
+        element_size(kind).ok_or_else(|| format!("unsupported tensor kind {:?}", kind))?;
+    let tensor = tensor.to_device(Device::Cpu).contiguous();
+    let header = Header {
+        descr: kind,
+        fortran_order: false,
+        shape: tensor.size(),
+        big_endian: cfg!(target_endian = "big"),
+        unsigned_width: None,
+    };
+    let numel: i64 = header.shape.iter().product();
+    let data_len = numel as usize * element_size;
+    let data = if data_len == 0 {
+        &[][..]
+    } else {
+        // The tensor is contiguous on the CPU, so its data is `data_len` bytes at `data_ptr`.
+        unsafe { std::slice::from_raw_parts(tensor.data_ptr() as *const u8, data_len) }
+    };
+    encode_npy(&header, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encode_npy, native_data, read_limited_npz_entries, read_npz_entries, split_npy, Header,
+        NPY_HEADER_ALIGNMENT,
+    };
+    use std::io::{Cursor, Write};
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod, ZipWriter};
     #[test]
     fn parse() {
         let h = "{'descr': '<f8', 'fortran_order': False, 'shape': (128,), }";
@@ -196,7 +415,9 @@ mod tests {
             Header {
                 descr: tch::Kind::Double,
                 fortran_order: false,
-                shape: vec![128]
+                shape: vec![128],
+                big_endian: false,
+                unsigned_width: None,
             }
         );
         let h = "{'descr': '<f4', 'fortran_order': True, 'shape': (256,1,128), }";
@@ -206,7 +427,9 @@ mod tests {
             Header {
                 descr: tch::Kind::Float,
                 fortran_order: true,
-                shape: vec![256, 1, 128]
+                shape: vec![256, 1, 128],
+                big_endian: false,
+                unsigned_width: None,
             }
         );
         assert_eq!(
@@ -218,10 +441,167 @@ mod tests {
             descr: tch::Kind::Int64,
             fortran_order: false,
             shape: vec![],
+            big_endian: false,
+            unsigned_width: None,
         };
         assert_eq!(
             h.to_string().unwrap(),
             "{'descr': '<i8', 'fortran_order': False, 'shape': (), }"
         );
     }
+
+    #[test]
+    fn parse_bool_and_half() {
+        let h = "{'descr': '|b1', 'fortran_order': False, 'shape': (4,), }";
+        assert_eq!(Header::parse(h).unwrap().descr, tch::Kind::Bool);
+        let h = "{'descr': '<f2', 'fortran_order': False, 'shape': (4,), }";
+        assert_eq!(Header::parse(h).unwrap().descr, tch::Kind::Half);
+        let h = Header {
+            descr: tch::Kind::Bool,
+            fortran_order: false,
+            shape: vec![4],
+            big_endian: false,
+            unsigned_width: None,
+        };
+        assert_eq!(
+            h.to_string().unwrap(),
+            "{'descr': '|b1', 'fortran_order': False, 'shape': (4,), }"
+        );
+    }
+
+    #[test]
+    fn encode() {
+        let header = Header {
+            descr: tch::Kind::Int16,
+            fortran_order: false,
+            shape: vec![2, 1],
+            big_endian: false,
+            unsigned_width: None,
+        };
+        let data = [1u8, 0, 2, 0];
+        let npy = encode_npy(&header, &data).unwrap();
+        assert_eq!(npy.len() % NPY_HEADER_ALIGNMENT, data.len());
+        assert_eq!(npy[npy.len() - data.len() - 1], b'\n');
+        let (parsed_header, parsed_data) = split_npy(&npy).unwrap();
+        assert_eq!(parsed_header, header);
+        assert_eq!(parsed_data, &data);
+
+        let header = Header {
+            descr: tch::Kind::Uint8,
+            fortran_order: false,
+            shape: vec![],
+            big_endian: false,
+            unsigned_width: None,
+        };
+        let npy = encode_npy(&header, &[7u8]).unwrap();
+        assert!(String::from_utf8_lossy(&npy).contains("'descr': '|u1'"));
+        assert_eq!(split_npy(&npy).unwrap().0, header);
+
+        let header = Header {
+            descr: tch::Kind::ComplexFloat,
+            fortran_order: false,
+            shape: vec![],
+            big_endian: false,
+            unsigned_width: None,
+        };
+        assert!(encode_npy(&header, &[]).is_err());
+    }
+
+    #[test]
+    fn parse_byte_orders_and_unsigned() {
+        let h = "{'descr': '>i4', 'fortran_order': False, 'shape': (2,), }";
+        let h = Header::parse(h).unwrap();
+        assert_eq!(h.descr, tch::Kind::Int);
+        assert!(h.big_endian);
+        let h = "{'descr': '<u2', 'fortran_order': False, 'shape': (2,), }";
+        let h = Header::parse(h).unwrap();
+        assert_eq!((h.descr, h.unsigned_width), (tch::Kind::Int, Some(2)));
+        let h = "{'descr': '>u8', 'fortran_order': False, 'shape': (2,), }";
+        let h = Header::parse(h).unwrap();
+        assert_eq!((h.descr, h.unsigned_width), (tch::Kind::Int64, Some(8)));
+        let h = "{'descr': '<c8', 'fortran_order': False, 'shape': (2,), }";
+        assert_eq!(
+            Header::parse(h).unwrap_err(),
+            "unrecognized descr <c8".to_string()
+        );
+    }
+
+    #[test]
+    fn convert_to_native_data() {
+        let mut header = Header {
+            descr: tch::Kind::Int16,
+            fortran_order: false,
+            shape: vec![2],
+            big_endian: !cfg!(target_endian = "big"),
+            unsigned_width: None,
+        };
+        let data = [0u8, 1, 0, 2];
+        let native = native_data(&header, &data).unwrap();
+        assert_eq!(
+            native.as_ref(),
+            [1i16.to_ne_bytes(), 2i16.to_ne_bytes()].concat().as_slice()
+        );
+        assert!(native_data(&header, &data[..3]).is_err());
+
+        header.descr = tch::Kind::Int;
+        header.unsigned_width = Some(2);
+        header.big_endian = true;
+        let native = native_data(&header, &[0xff, 0xff, 0, 1]).unwrap();
+        assert_eq!(
+            native.as_ref(),
+            [65535i32.to_ne_bytes(), 1i32.to_ne_bytes()]
+                .concat()
+                .as_slice()
+        );
+
+        header.descr = tch::Kind::Int64;
+        header.unsigned_width = Some(8);
+        header.big_endian = false;
+        assert!(native_data(&header, &[0xff; 8]).is_err());
+        let native = native_data(&header, &[7, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!(native.as_ref(), &7i64.to_ne_bytes());
+    }
+
+    #[test]
+    fn extract_npz_entries() {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        writer.start_file("arr_0.npy", stored).unwrap();
+        writer.write_all(b"first").unwrap();
+        writer.start_file("mask.npy", deflated).unwrap();
+        writer.write_all(&[7u8; 100]).unwrap();
+        writer.start_file("raw", stored).unwrap();
+        let npz = writer.finish().unwrap().into_inner();
+
+        let entries = read_npz_entries(&npz).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                ("arr_0".to_string(), b"first".to_vec()),
+                ("mask".to_string(), vec![7u8; 100]),
+                ("raw".to_string(), vec![]),
+            ]
+        );
+        assert!(read_npz_entries(b"not an archive").is_err());
+    }
+
+    #[test]
+    fn limit_extracted_npz_bytes() {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        writer.start_file("a.npy", deflated).unwrap();
+        writer.write_all(&[0u8; 600]).unwrap();
+        writer.start_file("b.npy", deflated).unwrap();
+        writer.write_all(&[0u8; 400]).unwrap();
+        let npz = writer.finish().unwrap().into_inner();
+        assert!(npz.len() < 1000);
+
+        assert_eq!(read_limited_npz_entries(&npz, 1000).unwrap().len(), 2);
+        assert_eq!(
+            read_limited_npz_entries(&npz, 999).unwrap_err(),
+            "arrays exceed 999 bytes".to_string()
+        );
+        assert!(read_limited_npz_entries(&npz, 500).is_err());
+    }
 }
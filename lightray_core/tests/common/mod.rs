@@ -1,7 +1,10 @@
 use lightray_core::lightray_executor::{
     LightrayIValueSemantic, LightrayModel, LightrayModelId, LightrayModelSemantics,
 };
-use lightray_core::lightray_torch::{SerializableIValue, TorchScriptGraph, TorchScriptInput};
+use lightray_core::lightray_torch::schema::TorchScriptSchema;
+use lightray_core::lightray_torch::{
+    SerializableIValue, TorchScriptGraph, TorchScriptInput, FORWARD_METHOD,
+};
 use tch::CModule;
 use uuid::Uuid;
 pub static GENERIC_TEXT_BASED_MODEL: &'static str =
@@ -24,6 +27,7 @@ pub fn generic_text_based_model_input() -> TorchScriptInput {
             SerializableIValue::Int(3),
             SerializableIValue::Int(3),
         ],
+        ..Default::default()
     }
 }
 pub fn generic_text_based_model_semantics() -> LightrayModelSemantics {
@@ -33,13 +37,19 @@ pub fn generic_text_based_model_semantics() -> LightrayModelSemantics {
             LightrayIValueSemantic::ExactValueMatch,
             LightrayIValueSemantic::ExactValueMatch,
         ],
+        ..Default::default()
     }
 }
-pub fn generic_text_based_model() -> LightrayModel {
-    let graph = TorchScriptGraph {
+pub fn generic_text_based_model_graph() -> TorchScriptGraph {
+    TorchScriptGraph {
         batchable: false,
+        batch_padding_value: None,
+        deterministic: true,
+        schema: TorchScriptSchema::read(GENERIC_TEXT_BASED_MODEL).ok(),
         module: CModule::load(GENERIC_TEXT_BASED_MODEL).unwrap(),
-    };
+    }
+}
+pub fn generic_text_based_model() -> LightrayModel {
     let lightray_id = LightrayModelId {
         model_id: Uuid::new_v4(),
         model_version: 0,
@@ -47,9 +57,14 @@ pub fn generic_text_based_model() -> LightrayModel {
 
     LightrayModel::new(
         lightray_id,
-        graph,
+        generic_text_based_model_graph(),
         vec![generic_text_based_model_input()],
-        generic_text_based_model_semantics(),
+        vec![(
+            FORWARD_METHOD.to_string(),
+            generic_text_based_model_semantics(),
+        )]
+        .into_iter()
+        .collect(),
     )
     .unwrap()
 }
@@ -1,10 +1,17 @@
+use lightray_core::lightray_caching::lru_cache::LightrayLruCache;
 use lightray_core::lightray_executor::errors::{
-    LightrayModelExecutionError, LightrayModelInputSemanticError,
+    LightrayModelExecutionError, LightrayModelInputSemanticError, LightrayModelVerificationError,
 };
 use lightray_core::lightray_executor::executor::{
     InMemorySimpleLightrayExecutor, LightrayExecutedExample, LightrayExecutor,
 };
+use lightray_core::lightray_executor::{
+    LightrayIValueSemantic, LightrayModel, LightrayModelId, LightrayModelSemantics,
+    LightrayVerificationPolicy,
+};
 use lightray_core::lightray_torch::{SerializableIValue, TorchScriptInput};
+use std::collections::HashMap;
+use uuid::Uuid;
 
 static GENERIC_TEXT_BASED_MODEL: &'static str =
     "tests/torchscript_models/generic_text_based_model.pt";
@@ -101,6 +108,7 @@ fn test_lightray_model_semantics() {
             SerializableIValue::Int(3),
             SerializableIValue::Int(3),
         ],
+        ..Default::default()
     };
     let wrong_sized_input = TorchScriptInput {
         positional_arguments: vec![
@@ -114,16 +122,21 @@ fn test_lightray_model_semantics() {
             SerializableIValue::Int(3),
             SerializableIValue::Int(3),
         ],
+        ..Default::default()
     };
 
     match model.execute(&wrong_first_type_input, true) {
-        Result::Ok(_x) => assert!(false, "failed for LightrayVerificationInputTypes"),
+        Result::Ok(_x) => assert!(false, "failed for LightrayVerificationReport"),
         Result::Err(y) => match y {
             LightrayModelExecutionError::LightrayModelInputSemanticError(z) => match z {
-                LightrayModelInputSemanticError::LightrayVerificationInputTypes(_) => (),
-                _ => assert!(false, "failed for LightrayVerificationInputTypes"),
+                LightrayModelInputSemanticError::LightrayVerificationReport(report) => {
+                    assert_eq!(report.violations.len(), 1);
+                    assert_eq!(report.violations[0].path, "$.positional_arguments[0]");
+                    assert_eq!(report.violations[0].rule, "TypeMatch");
+                }
+                _ => assert!(false, "failed for LightrayVerificationReport"),
             },
-            _ => assert!(false, "failed for LightrayVerificationInputTypes"),
+            _ => assert!(false, "failed for LightrayVerificationReport"),
         },
     }
     match model.execute(&wrong_sized_input, true) {
@@ -137,3 +150,329 @@ fn test_lightray_model_semantics() {
         },
     }
 }
+
+#[test]
+fn lightray_model_methods() {
+    let model = common::generic_text_based_model();
+    let mut encode_input = common::generic_text_based_model_input();
+    encode_input.method = Some("encode".to_string());
+    match model.execute(&encode_input, true) {
+        Err(LightrayModelExecutionError::LightrayUnknownMethod(error)) => {
+            assert_eq!(error.method, "encode")
+        }
+        _ => panic!("encode is not a registered method"),
+    }
+
+    let model_id = LightrayModelId {
+        model_id: Uuid::new_v4(),
+        model_version: 0,
+    };
+    let mut semantics = HashMap::new();
+    semantics.insert(
+        "encode".to_string(),
+        common::generic_text_based_model_semantics(),
+    );
+    match LightrayModel::new(
+        model_id,
+        common::generic_text_based_model_graph(),
+        vec![encode_input],
+        semantics,
+    ) {
+        Err(LightrayModelVerificationError::LightrayUnknownMethod(error)) => {
+            assert_eq!(error.method, "encode")
+        }
+        _ => panic!("the module does not export encode"),
+    }
+
+    let mut fewer_arguments = common::generic_text_based_model_input();
+    fewer_arguments.positional_arguments.pop();
+    match LightrayModel::new(
+        model_id,
+        common::generic_text_based_model_graph(),
+        vec![common::generic_text_based_model_input(), fewer_arguments],
+        HashMap::new(),
+    ) {
+        Err(LightrayModelVerificationError::LightrayMissingSemantics(error)) => {
+            assert_eq!(error.method, "forward")
+        }
+        _ => panic!("semantics cannot be derived from samples of different lengths"),
+    }
+}
+
+#[test]
+fn lightray_model_derives_semantics() {
+    let model_id = LightrayModelId {
+        model_id: Uuid::new_v4(),
+        model_version: 0,
+    };
+    let model = LightrayModel::new(
+        model_id,
+        common::generic_text_based_model_graph(),
+        vec![
+            common::generic_text_based_model_input(),
+            common::generic_text_based_model_input(),
+        ],
+        HashMap::new(),
+    )
+    .unwrap();
+    assert_eq!(
+        model.methods["forward"].semantics.positional_semantics,
+        vec![LightrayIValueSemantic::ExactValueMatch; 3]
+    );
+}
+
+#[test]
+fn lightray_model_rejects_inconsistent_samples() {
+    let model_id = LightrayModelId {
+        model_id: Uuid::new_v4(),
+        model_version: 0,
+    };
+    let mut semantics = HashMap::new();
+    semantics.insert(
+        "forward".to_string(),
+        LightrayModelSemantics {
+            positional_semantics: vec![
+                LightrayIValueSemantic::EachElement(Box::new(LightrayIValueSemantic::OneOf(vec![
+                    "<bos>".to_string(),
+                    "<eos>".to_string(),
+                ]))),
+                LightrayIValueSemantic::TypeMatch,
+                LightrayIValueSemantic::TypeMatch,
+            ],
+            ..Default::default()
+        },
+    );
+    match LightrayModel::new(
+        model_id,
+        common::generic_text_based_model_graph(),
+        vec![common::generic_text_based_model_input()],
+        semantics.clone(),
+    ) {
+        Err(LightrayModelVerificationError::LightrayInconsistentSample(error)) => {
+            assert_eq!(error.method, "forward");
+            assert_eq!(error.sample_position, 0);
+        }
+        _ => panic!("the sample passes tokens the semantics do not allow"),
+    }
+
+    let forward = semantics.get_mut("forward").unwrap();
+    forward.positional_semantics[0] = LightrayIValueSemantic::TypeMatch;
+    forward
+        .signature_variants
+        .push(vec![LightrayIValueSemantic::TypeMatch]);
+    match LightrayModel::new(
+        model_id,
+        common::generic_text_based_model_graph(),
+        vec![common::generic_text_based_model_input()],
+        semantics,
+    ) {
+        Err(LightrayModelVerificationError::LightrayUnsampledSignature(error)) => {
+            assert_eq!(error.signature_position, 1)
+        }
+        _ => panic!("no sample passes a single argument"),
+    }
+}
+
+#[test]
+fn lightray_model_output_semantics() {
+    let model_id = LightrayModelId {
+        model_id: Uuid::new_v4(),
+        model_version: 0,
+    };
+    let semantics_with_output = |output_semantics: LightrayIValueSemantic| {
+        let mut semantics = HashMap::new();
+        semantics.insert(
+            "forward".to_string(),
+            LightrayModelSemantics {
+                output_semantics: Some(output_semantics),
+                ..common::generic_text_based_model_semantics()
+            },
+        );
+        semantics
+    };
+    match LightrayModel::new(
+        model_id,
+        common::generic_text_based_model_graph(),
+        vec![common::generic_text_based_model_input()],
+        semantics_with_output(LightrayIValueSemantic::Type("Tuple".to_string())),
+    ) {
+        Err(LightrayModelVerificationError::LightrayInconsistentSampleOutput(error)) => {
+            assert_eq!(error.sample_position, 0);
+            assert_eq!(error.error.report.violations[0].rule, "Type");
+        }
+        _ => panic!("the model returns a list"),
+    }
+
+    let model = LightrayModel::new(
+        model_id,
+        common::generic_text_based_model_graph(),
+        vec![common::generic_text_based_model_input()],
+        semantics_with_output(LightrayIValueSemantic::EachElement(Box::new(
+            LightrayIValueSemantic::MaxLength(5),
+        ))),
+    )
+    .unwrap();
+    assert!(model
+        .execute(&common::generic_text_based_model_input(), true)
+        .is_ok());
+    assert_eq!(model.describe().output_violations, 0);
+}
+
+#[test]
+fn lightray_model_verification_policy() {
+    let mut unseen_input = common::generic_text_based_model_input();
+    unseen_input.positional_arguments[1] = SerializableIValue::Int(2);
+
+    let model = common::generic_text_based_model()
+        .with_verification_policy(LightrayVerificationPolicy::Always);
+    match model.execute(&unseen_input, false) {
+        Err(LightrayModelExecutionError::LightrayModelInputSemanticError(_)) => (),
+        _ => panic!("the policy verifies every input"),
+    }
+
+    let model = common::generic_text_based_model()
+        .with_verification_policy(LightrayVerificationPolicy::Shadow);
+    assert!(model.execute(&unseen_input, false).is_ok());
+    assert!(model.execute(&unseen_input, true).is_err());
+    let description = model.describe();
+    assert_eq!(
+        description.verification_policy,
+        LightrayVerificationPolicy::Shadow
+    );
+    assert_eq!(description.shadow_violations, 1);
+}
+
+#[test]
+fn describe_generic_text_based_model() {
+    let executor = InMemorySimpleLightrayExecutor::new();
+    let model_id = executor
+        .register_model(common::generic_text_based_model())
+        .unwrap();
+    let description = executor.describe_model(&model_id).unwrap();
+    assert_eq!(description.id, model_id);
+    assert!(description.deterministic);
+
+    let forward = &description.methods["forward"];
+    assert_eq!(
+        forward.semantics,
+        common::generic_text_based_model_semantics()
+    );
+    let schema = forward.schema.as_ref().unwrap();
+    assert_eq!(schema.return_type, Some("List[str]".to_string()));
+    assert_eq!(schema.arguments.len(), 3);
+    assert_eq!(schema.arguments[2].name, "top_k");
+
+    executor.delete_model(model_id).unwrap();
+    assert!(executor.describe_model(&model_id).is_err());
+}
+
+#[test]
+fn simple_executor_batched_generic_text_based_model() {
+    let executor = InMemorySimpleLightrayExecutor::new();
+    let model_id = executor
+        .register_model(common::generic_text_based_model())
+        .unwrap();
+    assert!(!executor.is_batchable(&model_id));
+
+    let examples = vec![
+        common::generic_text_based_model_input(),
+        common::generic_text_based_model_input(),
+    ];
+    let raw_outputs = executor.execute_batched(&model_id, &examples, true);
+    assert_eq!(raw_outputs.len(), 2);
+    for raw_output in raw_outputs {
+        let raw_output: LightrayExecutedExample = raw_output.unwrap();
+        assert_eq!(raw_output.execution_statistic.batch_size, 1);
+        assert_eq!(
+            raw_output.execution_result,
+            SerializableIValue::List(vec![
+                SerializableIValue::Str("<bos>".to_string()),
+                SerializableIValue::Str("call".to_string()),
+                SerializableIValue::Str("mom".to_string()),
+                SerializableIValue::Str("<eos>".to_string()),
+            ])
+        );
+    }
+}
+
+#[test]
+fn simple_executor_batched_inputs_that_cannot_be_stacked() {
+    let executor = InMemorySimpleLightrayExecutor::new();
+    let mut graph = common::generic_text_based_model_graph();
+    graph.batchable = true;
+    let model_id = LightrayModelId {
+        model_id: Uuid::new_v4(),
+        model_version: 0,
+    };
+    let model = LightrayModel::new(
+        model_id,
+        graph,
+        vec![common::generic_text_based_model_input()],
+        HashMap::new(),
+    )
+    .unwrap();
+    executor.register_model(model).unwrap();
+
+    let short_tokens = SerializableIValue::List(vec![
+        SerializableIValue::Str("<bos>".to_string()),
+        SerializableIValue::Str("<eos>".to_string()),
+    ]);
+    let mut short_input = common::generic_text_based_model_input();
+    short_input.positional_arguments[0] = short_tokens.clone();
+    let examples = vec![common::generic_text_based_model_input(), short_input];
+    let raw_outputs = executor.execute_batched(&model_id, &examples, false);
+    assert!(raw_outputs[0].is_ok());
+    assert_eq!(
+        raw_outputs[1].as_ref().unwrap().execution_result,
+        short_tokens
+    );
+}
+
+#[test]
+fn cached_executor_generic_text_based_model() {
+    let executor =
+        InMemorySimpleLightrayExecutor::with_response_cache(LightrayLruCache::new(16, None));
+    let model_id = executor
+        .register_model(common::generic_text_based_model())
+        .unwrap();
+
+    let first_output = executor
+        .execute(&model_id, &common::generic_text_based_model_input(), true)
+        .unwrap();
+    assert!(!first_output.execution_statistic.cache_hit);
+    let second_output = executor
+        .execute(&model_id, &common::generic_text_based_model_input(), true)
+        .unwrap();
+    assert!(second_output.execution_statistic.cache_hit);
+    assert_eq!(
+        first_output.execution_result,
+        second_output.execution_result
+    );
+
+    let examples = vec![common::generic_text_based_model_input()];
+    for raw_output in executor.execute_batched(&model_id, &examples, true) {
+        assert!(raw_output.unwrap().execution_statistic.cache_hit);
+    }
+}
+
+#[test]
+fn cached_executor_verifies_input() {
+    let executor =
+        InMemorySimpleLightrayExecutor::with_response_cache(LightrayLruCache::new(16, None));
+    let model_id = executor
+        .register_model(common::generic_text_based_model())
+        .unwrap();
+    let mut unseen_input = common::generic_text_based_model_input();
+    unseen_input.positional_arguments[1] = SerializableIValue::Int(2);
+
+    assert!(executor.execute(&model_id, &unseen_input, false).is_ok());
+    match executor.execute(&model_id, &unseen_input, true) {
+        Err(LightrayModelExecutionError::LightrayModelInputSemanticError(_)) => (),
+        _ => panic!("a cached response must not skip verification"),
+    }
+    let examples = vec![unseen_input];
+    match executor.execute_batched(&model_id, &examples, true).pop() {
+        Some(Err(LightrayModelExecutionError::LightrayModelInputSemanticError(_))) => (),
+        _ => panic!("a cached response must not skip verification"),
+    }
+}
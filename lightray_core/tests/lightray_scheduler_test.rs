@@ -0,0 +1,194 @@
+use lightray_core::lightray_executor::executor::{
+    InMemorySimpleLightrayExecutor, LightrayExecutedExample, LightrayExecutor,
+};
+use lightray_core::lightray_executor::LightrayModelId;
+use lightray_core::lightray_scheduler::admission::LightrayQueueLimits;
+use lightray_core::lightray_scheduler::batching_queue::LightrayBatchingWorkQueue;
+use lightray_core::lightray_scheduler::configured_queue::LightrayConfiguredWorkQueue;
+use lightray_core::lightray_scheduler::errors::{LightrayQueueLimit, LightraySchedulerError};
+use lightray_core::lightray_scheduler::greedy_fifo_queue::LightrayFIFOWorkQueue;
+use lightray_core::lightray_scheduler::queue::LightrayWorkQueue;
+use lightray_core::lightray_scheduler::worker_pool::LightrayWorkerPool;
+use lightray_core::lightray_torch::SerializableIValue;
+use std::io;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+#[allow(dead_code)]
+mod common;
+
+fn expected_output() -> SerializableIValue {
+    SerializableIValue::List(vec![
+        SerializableIValue::Str("<bos>".to_string()),
+        SerializableIValue::Str("call".to_string()),
+        SerializableIValue::Str("mom".to_string()),
+        SerializableIValue::Str("<eos>".to_string()),
+    ])
+}
+
+#[test]
+fn fifo_queue_generic_text_based_model() {
+    let queue = Arc::new(LightrayFIFOWorkQueue::new(
+        InMemorySimpleLightrayExecutor::new(),
+        true,
+    ));
+    let model_id = queue
+        .get_executor()
+        .register_model(common::generic_text_based_model())
+        .unwrap();
+    let worker_queue = queue.clone();
+    thread::spawn(move || worker_queue.worker_loop());
+
+    let mut runtime = Runtime::new().unwrap();
+    let scheduled_result =
+        runtime.block_on(queue.enqueue(common::generic_text_based_model_input(), model_id));
+    assert!(scheduled_result.scheduler_error.is_none());
+    let executed_example: LightrayExecutedExample =
+        scheduled_result.execution_result.unwrap().unwrap();
+    assert_eq!(executed_example.execution_result, expected_output());
+}
+
+#[test]
+fn fifo_queue_missing_model() {
+    let queue = Arc::new(LightrayFIFOWorkQueue::new(
+        InMemorySimpleLightrayExecutor::new(),
+        true,
+    ));
+    let worker_queue = queue.clone();
+    thread::spawn(move || worker_queue.worker_loop());
+
+    let missing_model_id = LightrayModelId {
+        model_id: Uuid::new_v4(),
+        model_version: 0,
+    };
+    let mut runtime = Runtime::new().unwrap();
+    let scheduled_result =
+        runtime.block_on(queue.enqueue(common::generic_text_based_model_input(), missing_model_id));
+    assert!(scheduled_result.execution_result.unwrap().is_err());
+}
+
+#[test]
+fn fifo_queue_sheds_load_when_full() {
+    let queue = LightrayFIFOWorkQueue::new(InMemorySimpleLightrayExecutor::new(), true)
+        .with_queue_limits(LightrayQueueLimits {
+            max_queue_depth: Some(0),
+            max_queue_depth_per_model: None,
+        });
+    let model_id = LightrayModelId {
+        model_id: Uuid::new_v4(),
+        model_version: 0,
+    };
+    let mut runtime = Runtime::new().unwrap();
+    let scheduled_result =
+        runtime.block_on(queue.enqueue(common::generic_text_based_model_input(), model_id));
+    assert!(scheduled_result.execution_result.is_none());
+    match scheduled_result.scheduler_error {
+        Some(LightraySchedulerError::QueueFull(LightrayQueueLimit::Global)) => {}
+        _ => panic!("expected QueueFull"),
+    }
+}
+
+#[test]
+fn batching_queue_generic_text_based_model() {
+    let queue = Arc::new(
+        LightrayBatchingWorkQueue::new(
+            InMemorySimpleLightrayExecutor::new(),
+            true,
+            8,
+            Duration::from_millis(5),
+        )
+        .unwrap(),
+    );
+    let model_id = queue
+        .get_executor()
+        .register_model(common::generic_text_based_model())
+        .unwrap();
+    let worker_queue = queue.clone();
+    thread::spawn(move || worker_queue.worker_loop());
+
+    let mut runtime = Runtime::new().unwrap();
+    let scheduled_result =
+        runtime.block_on(queue.enqueue(common::generic_text_based_model_input(), model_id));
+    let executed_example: LightrayExecutedExample =
+        scheduled_result.execution_result.unwrap().unwrap();
+    assert_eq!(executed_example.execution_result, expected_output());
+}
+
+#[test]
+fn configured_batching_queue_worker_pool() {
+    let queue = Arc::new(LightrayConfiguredWorkQueue::Batching(Box::new(
+        LightrayBatchingWorkQueue::new(
+            InMemorySimpleLightrayExecutor::new(),
+            true,
+            4,
+            Duration::from_millis(5),
+        )
+        .unwrap(),
+    )));
+    let model_id = queue
+        .get_executor()
+        .register_model(common::generic_text_based_model())
+        .unwrap();
+    let mut pool = LightrayWorkerPool::new(queue.clone(), 3).unwrap();
+
+    let mut runtime = Runtime::new().unwrap();
+    for _ in 0..4 {
+        let scheduled_result =
+            runtime.block_on(queue.enqueue(common::generic_text_based_model_input(), model_id));
+        assert_eq!(
+            scheduled_result
+                .execution_result
+                .unwrap()
+                .unwrap()
+                .execution_result,
+            expected_output()
+        );
+    }
+    pool.shutdown();
+}
+
+#[test]
+fn worker_pool_shutdown() {
+    let queue = Arc::new(LightrayFIFOWorkQueue::new(
+        InMemorySimpleLightrayExecutor::new(),
+        true,
+    ));
+    let model_id = queue
+        .get_executor()
+        .register_model(common::generic_text_based_model())
+        .unwrap();
+    let mut pool = LightrayWorkerPool::new(queue.clone(), 4).unwrap();
+    assert_eq!(pool.worker_count(), 4);
+
+    let mut runtime = Runtime::new().unwrap();
+    for _ in 0..8 {
+        let scheduled_result =
+            runtime.block_on(queue.enqueue(common::generic_text_based_model_input(), model_id));
+        assert!(scheduled_result.execution_result.unwrap().is_ok());
+    }
+
+    pool.shutdown();
+    assert_eq!(pool.worker_count(), 0);
+    let scheduled_result =
+        runtime.block_on(queue.enqueue(common::generic_text_based_model_input(), model_id));
+    assert!(scheduled_result.execution_result.is_none());
+    match scheduled_result.scheduler_error {
+        Some(LightraySchedulerError::SchedulerShutdown) => (),
+        _ => panic!("enqueue after shutdown should be rejected"),
+    }
+}
+
+#[test]
+fn worker_pool_without_workers() {
+    let queue = Arc::new(LightrayFIFOWorkQueue::new(
+        InMemorySimpleLightrayExecutor::new(),
+        true,
+    ));
+    match LightrayWorkerPool::new(queue, 0) {
+        Err(error) => assert_eq!(error.kind(), io::ErrorKind::InvalidInput),
+        Ok(_) => panic!("a pool without workers should be rejected"),
+    }
+}
@@ -1,7 +1,12 @@
+use lightray_core::lightray_torch::schema::TorchScriptSchema;
+use lightray_core::lightray_torch::tensor::{read_npy, read_npz, write_npy};
 use lightray_core::lightray_torch::{SerializableIValue, TorchScriptGraph, TorchScriptInput};
 use std::convert::TryFrom;
 use std::fs::read_to_string;
-use tch::{CModule, IValue};
+use std::io::{Cursor, Write};
+use tch::{CModule, IValue, Kind, Tensor};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
 static GENERIC_TEXT_BASED_MODEL: &'static str =
     "tests/torchscript_models/generic_text_based_model.pt";
 static NPY_VECTOR_3: &'static str = "tests/torchscript_models/single_vector_numpy.npy";
@@ -13,6 +18,9 @@ static NPY_TENSOR_3X5X7X9: &'static str = "tests/torchscript_models/single_tenso
 fn torchscript_generic_text_based_model() {
     let graph = TorchScriptGraph {
         batchable: false,
+        batch_padding_value: None,
+        deterministic: false,
+        schema: None,
         module: CModule::load(GENERIC_TEXT_BASED_MODEL).unwrap(),
     };
 
@@ -27,6 +35,7 @@ fn torchscript_generic_text_based_model() {
             SerializableIValue::Int(3),
             SerializableIValue::Int(3),
         ],
+        ..Default::default()
     };
     let expected_output = SerializableIValue::List(vec![
         SerializableIValue::Str("<bos>".to_string()),
@@ -38,6 +47,63 @@ fn torchscript_generic_text_based_model() {
     assert_eq!(model_output, expected_output);
 }
 
+#[test]
+fn torchscript_schema_generic_text_based_model() {
+    let schema = TorchScriptSchema::read(GENERIC_TEXT_BASED_MODEL).unwrap();
+    let forward = schema.method("forward").unwrap();
+    let argument_names: Vec<&str> = forward
+        .arguments
+        .iter()
+        .map(|argument| argument.name.as_str())
+        .collect();
+    assert_eq!(argument_names, vec!["input_tokens", "beam_size", "top_k"]);
+    assert_eq!(forward.arguments[0].type_name, "List[str]");
+    assert_eq!(forward.arguments[1].default_value, None);
+    assert_eq!(forward.return_type, Some("List[str]".to_string()));
+}
+
+#[test]
+fn torchscript_keyword_arguments() {
+    let graph = TorchScriptGraph {
+        batchable: false,
+        batch_padding_value: None,
+        deterministic: false,
+        schema: TorchScriptSchema::read(GENERIC_TEXT_BASED_MODEL).ok(),
+        module: CModule::load(GENERIC_TEXT_BASED_MODEL).unwrap(),
+    };
+    let mut input: TorchScriptInput = serde_json::from_str(
+        r#"{"positional_arguments": [{"List": [{"Str": "<bos>"}, {"Str": "<eos>"}]}],
+            "keyword_arguments": {"top_k": {"Int": 3}, "beam_size": {"Int": 3}}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        graph.forward(&input).unwrap(),
+        SerializableIValue::List(vec![
+            SerializableIValue::Str("<bos>".to_string()),
+            SerializableIValue::Str("<eos>".to_string()),
+        ])
+    );
+
+    input
+        .keyword_arguments
+        .insert("length".to_string(), SerializableIValue::Int(3));
+    assert!(graph.forward(&input).is_err());
+}
+
+#[test]
+fn torchscript_forward_batched_requires_batchable_graph() {
+    let graph = TorchScriptGraph {
+        batchable: false,
+        batch_padding_value: None,
+        deterministic: false,
+        schema: None,
+        module: CModule::load(GENERIC_TEXT_BASED_MODEL).unwrap(),
+    };
+    assert!(graph
+        .forward_batched(&[TorchScriptInput::default()])
+        .is_err());
+}
+
 #[test]
 fn tensor_serialization_vector() {
     let value: String = read_to_string(NPY_VECTOR_3).unwrap();
@@ -90,3 +156,165 @@ fn tensor_serialization_tensor4() {
         _ => panic!("unpacking should be to Tensor"),
     }
 }
+
+fn tensor_values(tensor: &Tensor) -> Vec<f64> {
+    Vec::<f64>::from(&tensor.to_kind(Kind::Double).reshape(&[-1]))
+}
+
+#[test]
+fn npy_round_trip_fixtures() {
+    for fixture in &[
+        NPY_VECTOR_3,
+        NPY_MATRIX_3X5,
+        NPY_TENSOR_3X5X7,
+        NPY_TENSOR_3X5X7X9,
+    ] {
+        let npy = base64::decode(&read_to_string(fixture).unwrap()).unwrap();
+        let tensor = read_npy(&npy).unwrap();
+        let written = write_npy(&tensor).unwrap();
+        // Both files align the data to 64 bytes, so only the header text may differ.
+        let data_len = tensor.numel() * 4;
+        assert_eq!(written.len(), npy.len());
+        assert_eq!(
+            &written[written.len() - data_len..],
+            &npy[npy.len() - data_len..]
+        );
+
+        let read_back = read_npy(&written).unwrap();
+        assert_eq!(read_back.size(), tensor.size());
+        assert_eq!(read_back.kind(), Kind::Float);
+        assert_eq!(tensor_values(&read_back), tensor_values(&tensor));
+    }
+}
+
+#[test]
+fn npy_round_trip_kinds() {
+    let values = Tensor::of_slice(&[0i64, 1, 0, 1, 1, 0]).reshape(&[2, 3]);
+    for kind in &[
+        Kind::Uint8,
+        Kind::Int8,
+        Kind::Int16,
+        Kind::Int,
+        Kind::Int64,
+        Kind::Half,
+        Kind::Float,
+        Kind::Double,
+        Kind::Bool,
+    ] {
+        let tensor = values.to_kind(*kind);
+        let read_back = read_npy(&write_npy(&tensor).unwrap()).unwrap();
+        assert_eq!(read_back.kind(), *kind);
+        assert_eq!(read_back.size(), &[2, 3]);
+        assert_eq!(tensor_values(&read_back), tensor_values(&tensor));
+    }
+
+    let scalar = Tensor::of_slice(&[2.5f64]).reshape(&[]);
+    let read_back = read_npy(&write_npy(&scalar).unwrap()).unwrap();
+    assert!(read_back.size().is_empty());
+    assert_eq!(tensor_values(&read_back), vec![2.5]);
+
+    let transposed = Tensor::of_slice(&[1f32, 2.0, 3.0, 4.0, 5.0, 6.0])
+        .reshape(&[2, 3])
+        .transpose(0, 1);
+    let read_back = read_npy(&write_npy(&transposed).unwrap()).unwrap();
+    assert_eq!(read_back.size(), &[3, 2]);
+    assert_eq!(
+        tensor_values(&read_back),
+        vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]
+    );
+
+    assert!(write_npy(&values.to_kind(Kind::ComplexFloat)).is_err());
+}
+
+fn npy(header: &str, data: &[u8]) -> Vec<u8> {
+    let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+    npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    npy.extend_from_slice(header.as_bytes());
+    npy.extend_from_slice(data);
+    npy
+}
+
+#[test]
+fn npy_read_fortran_order() {
+    // [[1, 2, 3], [4, 5, 6]] stored column by column.
+    let data: Vec<u8> = [1f32, 4.0, 2.0, 5.0, 3.0, 6.0]
+        .iter()
+        .flat_map(|value| value.to_le_bytes().to_vec())
+        .collect();
+    let tensor = read_npy(&npy(
+        "{'descr': '<f4', 'fortran_order': True, 'shape': (2, 3), }",
+        &data,
+    ))
+    .unwrap();
+    assert_eq!(tensor.size(), &[2, 3]);
+    assert_eq!(tensor_values(&tensor), vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+}
+
+#[test]
+fn npy_read_big_endian_and_unsigned() {
+    let data: Vec<u8> = [1i32, -2, 300]
+        .iter()
+        .flat_map(|value| value.to_be_bytes().to_vec())
+        .collect();
+    let tensor = read_npy(&npy(
+        "{'descr': '>i4', 'fortran_order': False, 'shape': (3,), }",
+        &data,
+    ))
+    .unwrap();
+    assert_eq!(tensor.kind(), Kind::Int);
+    assert_eq!(tensor_values(&tensor), vec![1.0, -2.0, 300.0]);
+
+    let tensor = read_npy(&npy(
+        "{'descr': '<u2', 'fortran_order': False, 'shape': (2,), }",
+        &[0xff, 0xff, 1, 0],
+    ))
+    .unwrap();
+    assert_eq!(tensor.kind(), Kind::Int);
+    assert_eq!(tensor_values(&tensor), vec![65535.0, 1.0]);
+
+    let tensor = read_npy(&npy(
+        "{'descr': '|b1', 'fortran_order': False, 'shape': (2,), }",
+        &[1, 0],
+    ))
+    .unwrap();
+    assert_eq!(tensor.kind(), Kind::Bool);
+}
+
+#[test]
+fn npz_read_named_arrays() {
+    let vector = write_npy(&Tensor::of_slice(&[1f32, 2.0, 3.0])).unwrap();
+    let mask = write_npy(&Tensor::of_slice(&[true, false])).unwrap();
+    let mut writer = ZipWriter::new(Cursor::new(vec![]));
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    writer.start_file("arr_0.npy", deflated).unwrap();
+    writer.write_all(&vector).unwrap();
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    writer.start_file("mask.npy", stored).unwrap();
+    writer.write_all(&mask).unwrap();
+    let npz = writer.finish().unwrap().into_inner();
+
+    let arrays = read_npz(&npz).unwrap();
+    let names: Vec<&str> = arrays.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, vec!["arr_0", "mask"]);
+    assert_eq!(tensor_values(&arrays[0].1), vec![1.0, 2.0, 3.0]);
+    assert_eq!(arrays[1].1.kind(), Kind::Bool);
+    assert!(read_npz(&vector).is_err());
+}
+
+#[test]
+fn tensor_literal_round_trip() {
+    let literal: SerializableIValue = serde_json::from_str(
+        r#"{"Tensor": {"dtype": "int64", "shape": [2, 2], "data": [1, 2, 3, -4]}}"#,
+    )
+    .unwrap();
+    let tensor = match IValue::try_from(&literal).unwrap() {
+        IValue::Tensor(tensor) => tensor,
+        _ => panic!("expected a tensor"),
+    };
+    assert_eq!(tensor.kind(), Kind::Int64);
+    assert_eq!(tensor.size(), &[2, 2]);
+    assert_eq!(tensor_values(&tensor), vec![1.0, 2.0, 3.0, -4.0]);
+
+    let output = SerializableIValue::try_from(&IValue::Tensor(tensor)).unwrap();
+    assert_eq!(output.with_tensor_literals(4), literal);
+}
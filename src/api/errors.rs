@@ -1,21 +1,47 @@
-use actix_web::{error::ResponseError, HttpResponse};
+use actix_web::{error::ResponseError, http::header, HttpResponse};
 use derive_more::Display;
 
 use lightray_core::lightray_executor::errors::{
     LightrayModelExecutionError, LightrayModelInputSemanticError, LightrayModelVerificationError,
-    LightrayRegistrationError,
+    LightrayRegistrationError, LightrayVerificationReport,
 };
+use lightray_core::lightray_scheduler::errors::{LightrayQueueLimit, LightraySchedulerError};
+
+/// Seconds a client is asked to wait before retrying work that was shed because a queue
+/// was full.
+const QUEUE_FULL_RETRY_AFTER_SECONDS: u64 = 1;
 
 #[derive(Debug, Display)]
 pub enum ServiceError {
     #[display(fmt = "Internal Server Error")]
     InternalServerError,
 
+    #[display(fmt = "Internal Server Error: {}", _0)]
+    InvalidOutput(String),
+
     #[display(fmt = "BadRequest: {}", _0)]
     BadRequest(String),
 
+    #[display(fmt = "BadRequest: {}", _0)]
+    InvalidInput(LightrayVerificationReport),
+
     #[display(fmt = "Unauthorized")]
     Unauthorized,
+
+    #[display(fmt = "Not Found")]
+    NotFound,
+
+    #[display(fmt = "Service Unavailable")]
+    ServiceUnavailable,
+
+    #[display(fmt = "Gateway Timeout")]
+    GatewayTimeout,
+
+    #[display(fmt = "Too Many Requests")]
+    TooManyRequests,
+
+    #[display(fmt = "Service Overloaded")]
+    ServiceOverloaded,
 }
 
 impl ResponseError for ServiceError {
@@ -24,8 +50,31 @@ impl ResponseError for ServiceError {
             ServiceError::InternalServerError => {
                 HttpResponse::InternalServerError().json("Internal Server Error, Please try later")
             }
+            ServiceError::InvalidOutput(ref message) => {
+                HttpResponse::InternalServerError().json(message)
+            }
             ServiceError::BadRequest(ref message) => HttpResponse::BadRequest().json(message),
+            ServiceError::InvalidInput(ref report) => HttpResponse::BadRequest().json(report),
             ServiceError::Unauthorized => HttpResponse::Unauthorized().json("Unauthorized"),
+            ServiceError::NotFound => HttpResponse::NotFound().json("Model not found"),
+            ServiceError::ServiceUnavailable => {
+                HttpResponse::ServiceUnavailable().json("Service Unavailable, Please try later")
+            }
+            ServiceError::GatewayTimeout => {
+                HttpResponse::GatewayTimeout().json("Request deadline exceeded before execution")
+            }
+            ServiceError::TooManyRequests => HttpResponse::TooManyRequests()
+                .header(
+                    header::RETRY_AFTER,
+                    QUEUE_FULL_RETRY_AFTER_SECONDS.to_string(),
+                )
+                .json("Too many queued requests for this model, Please retry later"),
+            ServiceError::ServiceOverloaded => HttpResponse::ServiceUnavailable()
+                .header(
+                    header::RETRY_AFTER,
+                    QUEUE_FULL_RETRY_AFTER_SECONDS.to_string(),
+                )
+                .json("Service Overloaded, Please retry later"),
         }
     }
 }
@@ -41,6 +90,21 @@ impl From<LightrayRegistrationError> for ServiceError {
                 LightrayModelVerificationError::LightrayMissingSamples(_) => {
                     ServiceError::BadRequest(String::from("Missing samples"))
                 }
+                LightrayModelVerificationError::LightrayMissingSemantics(err) => {
+                    ServiceError::BadRequest(err.to_string())
+                }
+                LightrayModelVerificationError::LightrayUnknownMethod(err) => {
+                    ServiceError::BadRequest(err.to_string())
+                }
+                LightrayModelVerificationError::LightrayInconsistentSample(err) => {
+                    ServiceError::BadRequest(err.to_string())
+                }
+                LightrayModelVerificationError::LightrayUnsampledSignature(err) => {
+                    ServiceError::BadRequest(err.to_string())
+                }
+                LightrayModelVerificationError::LightrayInconsistentSampleOutput(err) => {
+                    ServiceError::BadRequest(err.to_string())
+                }
             },
             _ => ServiceError::InternalServerError,
         }
@@ -53,20 +117,39 @@ impl From<LightrayModelExecutionError> for ServiceError {
             LightrayModelExecutionError::LightrayModelInputSemanticError(model_err) => {
                 match model_err {
                     LightrayModelInputSemanticError::LightrayVerificationInputSize(err) => {
-                        ServiceError::BadRequest(err.to_string())
-                    }
-                    LightrayModelInputSemanticError::LightrayVerificationInputTypes(err) => {
-                        ServiceError::BadRequest(err.to_string())
+                        ServiceError::InvalidInput(err.into())
                     }
-                    LightrayModelInputSemanticError::LightrayVerificationInputDoesNotEqual(err) => {
-                        ServiceError::BadRequest(err.to_string())
+                    LightrayModelInputSemanticError::LightrayVerificationReport(report) => {
+                        ServiceError::InvalidInput(report)
                     }
-                    LightrayModelInputSemanticError::LightrayVerificationInputSizeDoesNotEqual(
-                        err,
-                    ) => ServiceError::BadRequest(err.to_string()),
                 }
             }
+            LightrayModelExecutionError::LightrayModelOutputSemanticError(err) => {
+                ServiceError::InvalidOutput(err.to_string())
+            }
+            LightrayModelExecutionError::LightrayUnknownMethod(err) => {
+                ServiceError::BadRequest(err.to_string())
+            }
             _ => ServiceError::InternalServerError,
         }
     }
 }
+
+impl From<LightraySchedulerError> for ServiceError {
+    fn from(error: LightraySchedulerError) -> ServiceError {
+        match error {
+            LightraySchedulerError::LightrayModelExecutionError(execution_err) => {
+                execution_err.into()
+            }
+            LightraySchedulerError::SchedulerError => ServiceError::InternalServerError,
+            LightraySchedulerError::SchedulerShutdown => ServiceError::ServiceUnavailable,
+            LightraySchedulerError::DeadlineExceeded => ServiceError::GatewayTimeout,
+            LightraySchedulerError::QueueFull(LightrayQueueLimit::Model) => {
+                ServiceError::TooManyRequests
+            }
+            LightraySchedulerError::QueueFull(LightrayQueueLimit::Global) => {
+                ServiceError::ServiceOverloaded
+            }
+        }
+    }
+}
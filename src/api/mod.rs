@@ -2,3 +2,27 @@ pub mod errors;
 pub mod model_controller;
 pub mod multipart_utils;
 pub mod static_files_handler;
+
+use actix_web::web;
+use lightray_core::lightray_executor::executor::InMemorySimpleLightrayExecutor;
+use lightray_core::lightray_scheduler::configured_queue::LightrayConfiguredWorkQueue;
+
+pub type LightrayServerQueue = LightrayConfiguredWorkQueue<InMemorySimpleLightrayExecutor>;
+
+/// Registers the model routes, which the server mounts under `/api`. All methods of a path are
+/// routed by one resource, a path registered as several resources only answers the methods of
+/// the first one.
+pub fn configure_model_routes(config: &mut web::ServiceConfig) {
+    config
+        .service(web::resource("/model").route(web::post().to(model_controller::upload_model)))
+        .service(
+            web::resource("/model/{model_id}/version/{model_version}")
+                .route(web::get().to(model_controller::describe_model))
+                .route(web::post().to(model_controller::execute_model))
+                .route(web::delete().to(model_controller::delete_model)),
+        )
+        .service(
+            web::resource("/model/{model_id}/version/{model_version}/method/{method}")
+                .route(web::post().to(model_controller::execute_model_method)),
+        );
+}
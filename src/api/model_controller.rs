@@ -1,34 +1,81 @@
 use lightray_core::lightray_torch::TorchScriptGraph;
 use std::fs;
 use std::io::Write;
+use std::time::{Duration, Instant};
 
 use actix_multipart::{Field, Multipart};
-use actix_web::{error::BlockingError, web, Error, HttpResponse};
+use actix_web::{error::BlockingError, http::header, web, Error, HttpRequest, HttpResponse};
 use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use tch::CModule;
 use uuid::Uuid;
 
-use lightray_core::lightray_executor::{LightrayModel, LightrayModelId, LightrayModelSemantics};
+use lightray_core::lightray_executor::{
+    LightrayModel, LightrayModelId, LightrayModelSemantics, LightrayVerificationPolicy,
+};
 
-use lightray_core::lightray_executor::executor::{
-    InMemorySimpleLightrayExecutor, LightrayExecutor,
+use lightray_core::lightray_executor::errors::{
+    LightrayModelExecutionError, LightrayRegistrationError,
 };
+use lightray_core::lightray_executor::executor::{LightrayExecutedExample, LightrayExecutor};
 
-use lightray_core::lightray_scheduler::greedy_fifo_queue::LightrayFIFOWorkQueue;
-use lightray_core::lightray_scheduler::queue::LightrayWorkQueue;
-use lightray_core::lightray_torch::TorchScriptInput;
+use lightray_core::lightray_scheduler::queue::{LightrayWorkOptions, LightrayWorkQueue};
+use lightray_core::lightray_scheduler::statistics::SchedulerStatistics;
+use lightray_core::lightray_torch::frame::encode_frame;
+use lightray_core::lightray_torch::schema::TorchScriptSchema;
+use lightray_core::lightray_torch::{TorchScriptInput, FORWARD_METHOD};
 
 use crate::api::errors::ServiceError;
 use crate::api::multipart_utils::read_multipart_json;
+use crate::api::LightrayServerQueue;
+
+const PRIORITY_HEADER: &str = "X-Lightray-Priority";
+const TIMEOUT_HEADER: &str = "X-Lightray-Timeout-Ms";
+const EXECUTION_STATISTIC_HEADER: &str = "X-Lightray-Execution-Statistic";
+const SCHEDULER_STATISTIC_HEADER: &str = "X-Lightray-Scheduler-Statistic";
+const TENSOR_FORMAT_HEADER: &str = "X-Lightray-Tensor-Format";
+/// Output tensors with more elements than this are returned as NPY even when the client asks
+/// for tensor literals.
+const MAX_TENSOR_LITERAL_ELEMENTS: usize = 1024;
+/// Media type of inputs and outputs sent as binary frames instead of JSON.
+const FRAME_MEDIA_TYPE: &str = "application/x-lightray-frame";
+
+/// Path of a model method, e.g. `/model/{model_id}/version/{model_version}/method/encode`.
+#[derive(Deserialize)]
+pub struct LightrayModelMethodPath {
+    model_id: Uuid,
+    model_version: u16,
+    method: String,
+}
+
+/// Upload form fields describing how the TorchScript graph is executed.
+struct LightrayGraphOptions {
+    batchable: bool,
+    batch_padding_value: Option<f64>,
+    deterministic: bool,
+}
+
+#[derive(Serialize)]
+struct ExecutionResponse {
+    #[serde(flatten)]
+    executed_example: LightrayExecutedExample,
+    scheduler_statistic: SchedulerStatistics,
+}
 
 pub async fn upload_model(
-    queue: web::Data<LightrayFIFOWorkQueue<InMemorySimpleLightrayExecutor>>,
+    queue: web::Data<LightrayServerQueue>,
     mut c_module: Multipart,
 ) -> Result<HttpResponse, Error> {
     fs::create_dir_all("./model_store")?;
     let mut filepath: Option<String> = None;
     let mut samples: Option<Vec<TorchScriptInput>> = None;
     let mut semantics: Option<LightrayModelSemantics> = None;
+    let mut method_semantics: HashMap<String, LightrayModelSemantics> = HashMap::new();
+    let mut deterministic = false;
+    let mut batchable = false;
+    let mut batch_padding_value: Option<f64> = None;
+    let mut verification_policy = LightrayVerificationPolicy::Off;
 
     while let Some(item) = c_module.next().await {
         let mut field = item?;
@@ -44,6 +91,21 @@ pub async fn upload_model(
             Some("semantics") => {
                 semantics = Some(get_model_semantics(&mut field).await?);
             }
+            Some("method_semantics") => {
+                method_semantics = get_method_semantics(&mut field).await?;
+            }
+            Some("deterministic") => {
+                deterministic = get_deterministic(&mut field).await?;
+            }
+            Some("batchable") => {
+                batchable = get_batchable(&mut field).await?;
+            }
+            Some("batch_padding_value") => {
+                batch_padding_value = Some(get_batch_padding_value(&mut field).await?);
+            }
+            Some("verification_policy") => {
+                verification_policy = get_verification_policy(&mut field).await?;
+            }
             Some(other) => {
                 return Err(ServiceError::BadRequest(format!(
                     "unsupported formdata field: {}",
@@ -59,11 +121,26 @@ pub async fn upload_model(
         }
     }
 
-    register_model(filepath, samples, semantics, queue).await
+    if let Some(semantics) = semantics {
+        method_semantics.insert(FORWARD_METHOD.to_string(), semantics);
+    }
+    register_model(
+        filepath,
+        samples,
+        method_semantics,
+        LightrayGraphOptions {
+            batchable,
+            batch_padding_value,
+            deterministic,
+        },
+        verification_policy,
+        queue,
+    )
+    .await
 }
 
 pub async fn delete_model(
-    queue: web::Data<LightrayFIFOWorkQueue<InMemorySimpleLightrayExecutor>>,
+    queue: web::Data<LightrayServerQueue>,
     params: web::Path<LightrayModelId>,
 ) -> Result<HttpResponse, ServiceError> {
     let model_id = LightrayModelId {
@@ -80,25 +157,197 @@ pub async fn delete_model(
     }
 }
 
-pub async fn execute_model(
-    queue: web::Data<LightrayFIFOWorkQueue<InMemorySimpleLightrayExecutor>>,
+/// Describes the registered methods of a model: their schema, when it could be read from the
+/// TorchScript file, and their semantics.
+pub async fn describe_model(
+    queue: web::Data<LightrayServerQueue>,
     params: web::Path<LightrayModelId>,
-    input: web::Json<TorchScriptInput>,
 ) -> Result<HttpResponse, ServiceError> {
     let model_id = LightrayModelId {
         model_id: params.model_id,
         model_version: params.model_version,
     };
 
-    match web::block(move || queue.get_executor().execute(&model_id, &input, false)).await {
-        Ok(stats) => Ok(HttpResponse::Ok().json(stats)),
+    match web::block(move || queue.get_executor().describe_model(&model_id)).await {
+        Ok(description) => Ok(HttpResponse::Ok().json(description)),
         Err(err) => match err {
             BlockingError::Canceled => Err(ServiceError::InternalServerError),
+            BlockingError::Error(LightrayModelExecutionError::MissingModel) => {
+                Err(ServiceError::NotFound)
+            }
             BlockingError::Error(lightray_exec_err) => Err(lightray_exec_err.into()),
         },
     }
 }
 
+pub async fn execute_model(
+    queue: web::Data<LightrayServerQueue>,
+    params: web::Path<LightrayModelId>,
+    body: web::Bytes,
+    request: HttpRequest,
+) -> Result<HttpResponse, ServiceError> {
+    let model_id = LightrayModelId {
+        model_id: params.model_id,
+        model_version: params.model_version,
+    };
+    let input = get_model_input(&request, &body)?;
+    execute_input(queue, model_id, input, request).await
+}
+
+pub async fn execute_model_method(
+    queue: web::Data<LightrayServerQueue>,
+    params: web::Path<LightrayModelMethodPath>,
+    body: web::Bytes,
+    request: HttpRequest,
+) -> Result<HttpResponse, ServiceError> {
+    let model_id = LightrayModelId {
+        model_id: params.model_id,
+        model_version: params.model_version,
+    };
+    let mut input = get_model_input(&request, &body)?;
+    set_input_method(&mut input, &params.method)?;
+    execute_input(queue, model_id, input, request).await
+}
+
+async fn execute_input(
+    queue: web::Data<LightrayServerQueue>,
+    model_id: LightrayModelId,
+    input: TorchScriptInput,
+    request: HttpRequest,
+) -> Result<HttpResponse, ServiceError> {
+    let options = get_work_options(&request)?;
+    let tensor_literals = get_tensor_literals(&request)?;
+
+    let scheduled_result = queue.enqueue_with_options(input, model_id, options).await;
+    if let Some(scheduler_error) = scheduled_result.scheduler_error {
+        return Err(scheduler_error.into());
+    }
+    match scheduled_result.execution_result {
+        Some(Ok(executed_example)) if accepts_frames(&request) => {
+            frame_response(executed_example, scheduled_result.scheduler_metrics)
+        }
+        Some(Ok(mut executed_example)) => {
+            if tensor_literals {
+                executed_example.execution_result = executed_example
+                    .execution_result
+                    .with_tensor_literals(MAX_TENSOR_LITERAL_ELEMENTS);
+            }
+            Ok(HttpResponse::Ok().json(ExecutionResponse {
+                executed_example,
+                scheduler_statistic: scheduled_result.scheduler_metrics,
+            }))
+        }
+        Some(Err(lightray_exec_err)) => Err(lightray_exec_err.into()),
+        None => Err(ServiceError::InternalServerError),
+    }
+}
+
+fn is_frame_media_type(media_type: &str) -> bool {
+    media_type.split(';').next().map_or(false, |media_type| {
+        media_type.trim().eq_ignore_ascii_case(FRAME_MEDIA_TYPE)
+    })
+}
+
+/// Reads the model input from a binary frame if the request says so with its
+/// `Content-Type`, from JSON otherwise.
+fn get_model_input(request: &HttpRequest, body: &[u8]) -> Result<TorchScriptInput, ServiceError> {
+    let is_frame = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map_or(false, is_frame_media_type);
+    if is_frame {
+        TorchScriptInput::decode_frame(body).map_err(|frame_error| {
+            ServiceError::BadRequest(format!("Model input frame format error: {}", frame_error))
+        })
+    } else {
+        serde_json::from_slice(body).map_err(|json_error| {
+            ServiceError::BadRequest(format!("Model input JSON format error: {}", json_error))
+        })
+    }
+}
+
+/// Makes `input` call the method named by the route. Inputs naming another method are
+/// rejected.
+fn set_input_method(input: &mut TorchScriptInput, method: &str) -> Result<(), ServiceError> {
+    match &input.method {
+        Some(input_method) if input_method != method => Err(ServiceError::BadRequest(format!(
+            "input calls method {} but the route names {}",
+            input_method, method
+        ))),
+        _ => {
+            input.method = Some(method.to_string());
+            Ok(())
+        }
+    }
+}
+
+/// Whether the `Accept` header of the request asks for a binary frame response.
+fn accepts_frames(request: &HttpRequest) -> bool {
+    request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .map_or(false, |accept| accept.split(',').any(is_frame_media_type))
+}
+
+/// Responds with the execution result as a binary frame, the statistics are sent as JSON in
+/// headers.
+fn frame_response(
+    executed_example: LightrayExecutedExample,
+    scheduler_statistic: SchedulerStatistics,
+) -> Result<HttpResponse, ServiceError> {
+    let frame = encode_frame(&executed_example.execution_result)
+        .map_err(|_| ServiceError::InternalServerError)?;
+    let execution_statistic = serde_json::to_string(&executed_example.execution_statistic)
+        .map_err(|_| ServiceError::InternalServerError)?;
+    let scheduler_statistic = serde_json::to_string(&scheduler_statistic)
+        .map_err(|_| ServiceError::InternalServerError)?;
+    Ok(HttpResponse::Ok()
+        .content_type(FRAME_MEDIA_TYPE)
+        .header(EXECUTION_STATISTIC_HEADER, execution_statistic)
+        .header(SCHEDULER_STATISTIC_HEADER, scheduler_statistic)
+        .body(frame))
+}
+
+/// Whether the client asks for small output tensors as tensor literals (`literal`) rather
+/// than base64 NPY files (`npy`, the default).
+fn get_tensor_literals(request: &HttpRequest) -> Result<bool, ServiceError> {
+    match request.headers().get(TENSOR_FORMAT_HEADER) {
+        None => Ok(false),
+        Some(tensor_format) => match tensor_format.to_str() {
+            Ok("npy") => Ok(false),
+            Ok("literal") => Ok(true),
+            _ => Err(ServiceError::BadRequest(format!(
+                "invalid {} header",
+                TENSOR_FORMAT_HEADER
+            ))),
+        },
+    }
+}
+
+fn get_work_options(request: &HttpRequest) -> Result<LightrayWorkOptions, ServiceError> {
+    let mut options = LightrayWorkOptions::default();
+    if let Some(priority) = request.headers().get(PRIORITY_HEADER) {
+        let priority = priority
+            .to_str()
+            .map_err(|_| ServiceError::BadRequest(format!("invalid {} header", PRIORITY_HEADER)))?;
+        options.priority = priority.parse().map_err(ServiceError::BadRequest)?;
+    }
+    if let Some(timeout) = request.headers().get(TIMEOUT_HEADER) {
+        let deadline = timeout
+            .to_str()
+            .ok()
+            .and_then(|timeout| timeout.parse::<u64>().ok())
+            .and_then(|timeout_ms| Instant::now().checked_add(Duration::from_millis(timeout_ms)))
+            .ok_or_else(|| {
+                ServiceError::BadRequest(format!("invalid {} header", TIMEOUT_HEADER))
+            })?;
+        options.deadline = Some(deadline);
+    }
+    Ok(options)
+}
+
 async fn get_samples(mut field: &mut Field) -> Result<Vec<TorchScriptInput>, Error> {
     match read_multipart_json::<Vec<TorchScriptInput>>(&mut field).await {
         Ok(s) => Ok(s),
@@ -121,6 +370,72 @@ async fn get_model_semantics(mut field: &mut Field) -> Result<LightrayModelSeman
     }
 }
 
+async fn get_method_semantics(
+    mut field: &mut Field,
+) -> Result<HashMap<String, LightrayModelSemantics>, Error> {
+    match read_multipart_json::<HashMap<String, LightrayModelSemantics>>(&mut field).await {
+        Ok(s) => Ok(s),
+        Err(json_error) => Err(ServiceError::BadRequest(format!(
+            "Model method semantics JSON format error: {}",
+            json_error
+        ))
+        .into()),
+    }
+}
+
+async fn get_deterministic(mut field: &mut Field) -> Result<bool, Error> {
+    match read_multipart_json::<bool>(&mut field).await {
+        Ok(s) => Ok(s),
+        Err(json_error) => Err(ServiceError::BadRequest(format!(
+            "Model deterministic flag JSON format error: {}",
+            json_error
+        ))
+        .into()),
+    }
+}
+
+async fn get_batchable(mut field: &mut Field) -> Result<bool, Error> {
+    match read_multipart_json::<bool>(&mut field).await {
+        Ok(s) => Ok(s),
+        Err(json_error) => Err(ServiceError::BadRequest(format!(
+            "Model batchable flag JSON format error: {}",
+            json_error
+        ))
+        .into()),
+    }
+}
+
+async fn get_batch_padding_value(mut field: &mut Field) -> Result<f64, Error> {
+    match read_multipart_json::<f64>(&mut field).await {
+        Ok(s) => Ok(s),
+        Err(json_error) => Err(ServiceError::BadRequest(format!(
+            "Model batch padding value JSON format error: {}",
+            json_error
+        ))
+        .into()),
+    }
+}
+
+async fn get_verification_policy(
+    mut field: &mut Field,
+) -> Result<LightrayVerificationPolicy, Error> {
+    match read_multipart_json::<LightrayVerificationPolicy>(&mut field).await {
+        Ok(LightrayVerificationPolicy::Sampled(percent)) if !(0.0..=100.0).contains(&percent) => {
+            Err(ServiceError::BadRequest(format!(
+                "Model verification policy samples {} percent of the inputs",
+                percent
+            ))
+            .into())
+        }
+        Ok(s) => Ok(s),
+        Err(json_error) => Err(ServiceError::BadRequest(format!(
+            "Model verification policy JSON format error: {}",
+            json_error
+        ))
+        .into()),
+    }
+}
+
 async fn save_model_file(field: &mut Field, filename: Option<&str>) -> Result<String, Error> {
     let filepath: String;
     match filename {
@@ -148,8 +463,10 @@ async fn save_model_file(field: &mut Field, filename: Option<&str>) -> Result<St
 async fn register_model(
     file: Option<String>,
     samples: Option<Vec<TorchScriptInput>>,
-    semantics: Option<LightrayModelSemantics>,
-    queue: web::Data<LightrayFIFOWorkQueue<InMemorySimpleLightrayExecutor>>,
+    semantics: HashMap<String, LightrayModelSemantics>,
+    graph_options: LightrayGraphOptions,
+    verification_policy: LightrayVerificationPolicy,
+    queue: web::Data<LightrayServerQueue>,
 ) -> Result<HttpResponse, Error> {
     let input_file = file.ok_or_else(|| {
         Into::<Error>::into(ServiceError::BadRequest(String::from(
@@ -161,14 +478,13 @@ async fn register_model(
             "missing input samples",
         )))
     })?;
-    let input_semantics = semantics.ok_or_else(|| {
-        Into::<Error>::into(ServiceError::BadRequest(String::from(
-            "missing model semantics",
-        )))
-    })?;
 
+    let schema = TorchScriptSchema::read(&input_file);
     let graph = TorchScriptGraph {
-        batchable: false,
+        batchable: graph_options.batchable,
+        batch_padding_value: graph_options.batch_padding_value,
+        deterministic: graph_options.deterministic,
+        schema: schema.as_ref().ok().cloned(),
         module: CModule::load(input_file).unwrap(),
     };
     let lightray_id = LightrayModelId {
@@ -176,11 +492,21 @@ async fn register_model(
         model_version: 0,
     };
 
-    let lightray_model =
-        LightrayModel::new(lightray_id, graph, input_samples, input_semantics).unwrap();
+    let lightray_model = LightrayModel::new(lightray_id, graph, input_samples, semantics)
+        .map_err(|verification_error| {
+            ServiceError::from(LightrayRegistrationError::LightrayModelVerificationError(
+                verification_error,
+            ))
+        })?
+        .with_verification_policy(verification_policy);
+    let lightray_model = match schema {
+        Ok(_) => lightray_model,
+        Err(schema_error) => lightray_model.with_schema_error(schema_error),
+    };
+    let description = lightray_model.describe();
 
     match web::block(move || queue.get_executor().register_model(lightray_model)).await {
-        Ok(model_id) => Ok(HttpResponse::Ok().json(model_id)),
+        Ok(_) => Ok(HttpResponse::Ok().json(description)),
         Err(err) => match err {
             BlockingError::Canceled => Err(ServiceError::InternalServerError.into()),
             BlockingError::Error(lightray_reg_err) => {
@@ -195,10 +521,18 @@ mod tests {
     use super::*;
     use actix_utils::mpsc;
     use actix_web::error::PayloadError;
-    use actix_web::http::header::{self, HeaderMap};
+    use actix_web::http::header::HeaderMap;
     use actix_web::http::StatusCode;
+    use crate::api::configure_model_routes;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::App;
     use bytes::Bytes;
     use futures::stream::Stream;
+    use lightray_core::lightray_executor::executor::InMemorySimpleLightrayExecutor;
+    use lightray_core::lightray_scheduler::configured_queue::LightrayConfiguredWorkQueue;
+    use lightray_core::lightray_scheduler::priority_queue::LightrayPriorityWorkQueue;
+    use lightray_core::lightray_scheduler::queue::LightrayWorkPriority;
+    use lightray_core::lightray_torch::SerializableIValue;
 
     fn create_stream() -> (
         mpsc::Sender<Result<Bytes, PayloadError>>,
@@ -319,4 +653,148 @@ mod tests {
             _ => unreachable!(),
         }
     }
+
+    #[test]
+    fn test_get_work_options() {
+        let request = TestRequest::default().to_http_request();
+        assert_eq!(
+            get_work_options(&request).unwrap().priority,
+            LightrayWorkPriority::Interactive
+        );
+
+        let request = TestRequest::default()
+            .header(PRIORITY_HEADER, "bulk")
+            .to_http_request();
+        assert_eq!(
+            get_work_options(&request).unwrap().priority,
+            LightrayWorkPriority::Bulk
+        );
+
+        let request = TestRequest::default()
+            .header(PRIORITY_HEADER, "urgent")
+            .to_http_request();
+        assert!(get_work_options(&request).is_err());
+    }
+
+    #[test]
+    fn test_get_work_options_timeout() {
+        let request = TestRequest::default().to_http_request();
+        assert!(get_work_options(&request).unwrap().deadline.is_none());
+
+        let before = Instant::now();
+        let request = TestRequest::default()
+            .header(TIMEOUT_HEADER, "250")
+            .to_http_request();
+        let deadline = get_work_options(&request).unwrap().deadline.unwrap();
+        assert!(deadline >= before + Duration::from_millis(250));
+
+        let request = TestRequest::default()
+            .header(TIMEOUT_HEADER, "-1")
+            .to_http_request();
+        assert!(get_work_options(&request).is_err());
+
+        // Whether such a deadline can be represented depends on the platform, but it never
+        // panics.
+        let request = TestRequest::default()
+            .header(TIMEOUT_HEADER, u64::MAX.to_string())
+            .to_http_request();
+        match get_work_options(&request) {
+            Ok(options) => assert!(options.deadline.is_some()),
+            Err(ServiceError::BadRequest(_)) => {}
+            Err(_) => panic!("an unrepresentable deadline is a bad request"),
+        }
+    }
+
+    #[test]
+    fn test_get_model_input() {
+        let input = TorchScriptInput {
+            positional_arguments: vec![SerializableIValue::Int(3)],
+            ..Default::default()
+        };
+
+        let request = TestRequest::default().to_http_request();
+        let json = serde_json::to_vec(&input).unwrap();
+        assert_eq!(get_model_input(&request, &json).unwrap(), input);
+
+        let request = TestRequest::default()
+            .header(header::CONTENT_TYPE, "application/x-lightray-frame; v=1")
+            .to_http_request();
+        let frame = input.encode_frame().unwrap();
+        assert_eq!(get_model_input(&request, &frame).unwrap(), input);
+        assert!(get_model_input(&request, &json).is_err());
+    }
+
+    #[test]
+    fn test_get_tensor_literals() {
+        let request = TestRequest::default().to_http_request();
+        assert!(!get_tensor_literals(&request).unwrap());
+
+        let request = TestRequest::default()
+            .header(TENSOR_FORMAT_HEADER, "literal")
+            .to_http_request();
+        assert!(get_tensor_literals(&request).unwrap());
+
+        let request = TestRequest::default()
+            .header(TENSOR_FORMAT_HEADER, "csv")
+            .to_http_request();
+        assert!(get_tensor_literals(&request).is_err());
+    }
+
+    #[test]
+    fn test_set_input_method() {
+        let mut input = TorchScriptInput::default();
+        set_input_method(&mut input, "encode").unwrap();
+        assert_eq!(input.method_name(), "encode");
+        set_input_method(&mut input, "encode").unwrap();
+        assert!(set_input_method(&mut input, "decode").is_err());
+    }
+
+    #[test]
+    fn test_accepts_frames() {
+        let request = TestRequest::default().to_http_request();
+        assert!(!accepts_frames(&request));
+
+        let request = TestRequest::default()
+            .header(header::ACCEPT, "application/json")
+            .to_http_request();
+        assert!(!accepts_frames(&request));
+
+        let request = TestRequest::default()
+            .header(
+                header::ACCEPT,
+                "application/json, application/x-lightray-frame",
+            )
+            .to_http_request();
+        assert!(accepts_frames(&request));
+    }
+
+    #[actix_rt::test]
+    async fn test_model_version_routes() {
+        let queue = web::Data::new(LightrayConfiguredWorkQueue::Priority(Box::new(
+            LightrayPriorityWorkQueue::new(
+                InMemorySimpleLightrayExecutor::new(),
+                false,
+                Duration::from_millis(500),
+            ),
+        )));
+        let mut app = test::init_service(
+            App::new()
+                .app_data(queue)
+                .service(web::scope("/api").configure(configure_model_routes)),
+        )
+        .await;
+        let uri = format!("/api/model/{}/version/0", Uuid::nil());
+
+        let response =
+            test::call_service(&mut app, TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            test::read_body(response).await,
+            Bytes::from_static(b"\"Model not found\"")
+        );
+        for request in vec![TestRequest::post(), TestRequest::delete()] {
+            let response = test::call_service(&mut app, request.uri(&uri).to_request()).await;
+            assert_ne!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        }
+    }
 }
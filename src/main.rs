@@ -1,8 +1,82 @@
 use actix_web::{web, App, HttpServer};
+use std::env;
+use std::io;
+use std::time::Duration;
 
-use lightray::api::{model_controller, static_files_handler};
+use lightray::api::{configure_model_routes, static_files_handler, LightrayServerQueue};
+use lightray_core::lightray_caching::lru_cache::LightrayLruCache;
 use lightray_core::lightray_executor::executor::InMemorySimpleLightrayExecutor;
-use lightray_core::lightray_scheduler::greedy_fifo_queue::LightrayFIFOWorkQueue;
+use lightray_core::lightray_scheduler::admission::LightrayQueueLimits;
+use lightray_core::lightray_scheduler::batching_queue::LightrayBatchingWorkQueue;
+use lightray_core::lightray_scheduler::priority_queue::LightrayPriorityWorkQueue;
+use lightray_core::lightray_scheduler::worker_pool::LightrayWorkerPool;
+
+/// Time after which queued bulk work is served ahead of newer interactive work.
+const BULK_AGING_INTERVAL: Duration = Duration::from_millis(500);
+/// Most inputs executed in one batch unless `LIGHTRAY_MAX_BATCH_SIZE` is set.
+const DEFAULT_MAX_BATCH_SIZE: usize = 16;
+/// Longest time an input waits for its batch to fill unless `LIGHTRAY_MAX_BATCH_LATENCY_MS` is
+/// set.
+const DEFAULT_MAX_BATCH_LATENCY: Duration = Duration::from_millis(5);
+/// Largest accepted request body unless `LIGHTRAY_MAX_REQUEST_BYTES` is set.
+const DEFAULT_MAX_REQUEST_BYTES: usize = 16 * 1024 * 1024;
+
+/// Number of scheduler worker threads, read from `LIGHTRAY_WORKER_THREADS` and defaulting to
+/// the number of CPUs.
+fn worker_thread_count() -> usize {
+    match env::var("LIGHTRAY_WORKER_THREADS") {
+        Ok(value) => value
+            .parse::<usize>()
+            .ok()
+            .filter(|count| *count > 0)
+            .unwrap_or_else(|| panic!("invalid LIGHTRAY_WORKER_THREADS: {}", value)),
+        Err(_) => sys_info::cpu_num().map(|count| count as usize).unwrap_or(1),
+    }
+}
+
+/// Optional limit read from the environment variable `name`.
+fn env_limit(name: &str) -> Option<usize> {
+    env::var(name).ok().map(|value| {
+        value
+            .parse::<usize>()
+            .unwrap_or_else(|_| panic!("invalid {}: {}", name, value))
+    })
+}
+
+/// Response cache for deterministic models. Holds `LIGHTRAY_RESPONSE_CACHE_ENTRIES` outputs
+/// (default 1024) for at most `LIGHTRAY_RESPONSE_CACHE_TTL_SECS` seconds when set.
+fn response_cache() -> LightrayLruCache {
+    let max_entries = env_limit("LIGHTRAY_RESPONSE_CACHE_ENTRIES").unwrap_or(1024);
+    let time_to_live = env_limit("LIGHTRAY_RESPONSE_CACHE_TTL_SECS")
+        .map(|seconds| Duration::from_secs(seconds as u64));
+    LightrayLruCache::new(max_entries, time_to_live)
+}
+
+/// Scheduler queue named by `LIGHTRAY_SCHEDULER`: `priority` (default) serves work by priority
+/// class, `batching` coalesces work for batchable models.
+fn server_queue(executor: InMemorySimpleLightrayExecutor) -> io::Result<LightrayServerQueue> {
+    match env::var("LIGHTRAY_SCHEDULER").as_ref().map(String::as_str) {
+        Err(_) | Ok("priority") => Ok(LightrayServerQueue::Priority(Box::new(
+            LightrayPriorityWorkQueue::new(executor, false, BULK_AGING_INTERVAL),
+        ))),
+        Ok("batching") => {
+            let max_batch_size = env_limit("LIGHTRAY_MAX_BATCH_SIZE")
+                .map(|size| {
+                    Some(size)
+                        .filter(|size| *size > 0)
+                        .unwrap_or_else(|| panic!("invalid LIGHTRAY_MAX_BATCH_SIZE: {}", size))
+                })
+                .unwrap_or(DEFAULT_MAX_BATCH_SIZE);
+            let max_batch_latency = env_limit("LIGHTRAY_MAX_BATCH_LATENCY_MS")
+                .map(|milliseconds| Duration::from_millis(milliseconds as u64))
+                .unwrap_or(DEFAULT_MAX_BATCH_LATENCY);
+            Ok(LightrayServerQueue::Batching(Box::new(
+                LightrayBatchingWorkQueue::new(executor, false, max_batch_size, max_batch_latency)?,
+            )))
+        }
+        Ok(other) => panic!("invalid LIGHTRAY_SCHEDULER: {}", other),
+    }
+}
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
@@ -11,30 +85,35 @@ async fn main() -> std::io::Result<()> {
         "lightray=debug,actix_web=info,actix_server=info",
     );
 
-    HttpServer::new(move || {
+    // A single queue is shared by every HTTP worker so that models registered through one
+    // worker are visible to all of them, and so that execution goes through the scheduler.
+    let queue_limits = LightrayQueueLimits {
+        max_queue_depth: env_limit("LIGHTRAY_MAX_QUEUE_DEPTH"),
+        max_queue_depth_per_model: env_limit("LIGHTRAY_MAX_QUEUE_DEPTH_PER_MODEL"),
+    };
+    let queue = web::Data::new(
+        server_queue(InMemorySimpleLightrayExecutor::with_response_cache(
+            response_cache(),
+        ))?
+        .with_queue_limits(queue_limits),
+    );
+    let mut worker_pool =
+        LightrayWorkerPool::new(queue.clone().into_inner(), worker_thread_count())?;
+    let max_request_bytes =
+        env_limit("LIGHTRAY_MAX_REQUEST_BYTES").unwrap_or(DEFAULT_MAX_REQUEST_BYTES);
+
+    let server_result = HttpServer::new(move || {
         App::new()
-            .data(LightrayFIFOWorkQueue::new(
-                InMemorySimpleLightrayExecutor::new(),
-                false,
-            ))
+            .app_data(queue.clone())
+            .app_data(web::PayloadConfig::new(max_request_bytes))
             .service(web::resource("/").route(web::get().to(static_files_handler::index)))
-            .service(
-                web::scope("/api")
-                    .service(
-                        web::resource("/model")
-                            .route(web::post().to(model_controller::upload_model)),
-                    )
-                    .service(
-                        web::resource("/model/{model_id}/version/{model_version}")
-                            .route(web::delete().to(model_controller::delete_model)),
-                    )
-                    .service(
-                        web::resource("/model/{model_id}/version/{model_version}")
-                            .route(web::post().to(model_controller::execute_model)),
-                    ),
-            )
+            .service(web::scope("/api").configure(configure_model_routes))
     })
     .bind("127.0.0.1:5000")?
     .run()
-    .await
+    .await;
+
+    // Answer everything that is still queued before exiting.
+    worker_pool.shutdown();
+    server_result
 }